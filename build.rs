@@ -34,5 +34,25 @@ fn main() {
         bindings
             .write_to_file(out_path.join("bindings.rs"))
             .expect("unable to write bindings");
+
+        // The tests need a custom op library to load, so we build a tiny one here.
+        let library_path = out_path.join(if cfg!(target_os = "macos") {
+            "libcustom_op_library.dylib"
+        } else {
+            "libcustom_op_library.so"
+        });
+        let status = cc::Build::new()
+            .get_compiler()
+            .to_command()
+            .args(["-shared", "-fPIC", "-Ivendor/onnxruntime/include", "-o"])
+            .arg(&library_path)
+            .arg("src/onnx/testdata/custom_op_library.c")
+            .status()
+            .expect("unable to run the c compiler");
+        assert!(status.success(), "unable to build the custom op library");
+        println!(
+            "cargo:rustc-env=INFER_TEST_CUSTOM_OP_LIBRARY={}",
+            library_path.display()
+        );
     }
 }
@@ -0,0 +1,148 @@
+//! Conversions between tensors and `ndarray` arrays, enabled by the `ndarray` feature.
+
+use super::{Element, InputTensor, OutputTensor, OwnedTensor, SessionInput};
+use ndarray::{Array, ArrayBase, ArrayD, ArrayViewD, CowArray, Data, Dimension, IxDyn};
+use std::convert::TryFrom;
+
+#[derive(thiserror::Error, Debug)]
+#[error("the array isn't in standard layout")]
+pub struct NotStandardLayoutError;
+
+/// Borrows a contiguous, row-major array as an input without copying it. Use `ArrayInput` for
+/// arrays that might not be in standard layout.
+impl<'a, S: Data<Elem = f32>, D: Dimension> TryFrom<&'a ArrayBase<S, D>> for InputTensor<'a> {
+    type Error = NotStandardLayoutError;
+
+    fn try_from(array: &'a ArrayBase<S, D>) -> Result<Self, Self::Error> {
+        Ok(Self::new(
+            array.as_slice().ok_or(NotStandardLayoutError)?,
+            array.shape(),
+        ))
+    }
+}
+
+/// An input made from an array of any layout. Arrays in standard layout are borrowed, and others
+/// (e.g. transposed or sliced with a step) are copied into standard layout.
+pub struct ArrayInput<'a> {
+    array: CowArray<'a, f32, IxDyn>,
+}
+
+impl<'a> ArrayInput<'a> {
+    pub fn as_input(&self) -> InputTensor<'_> {
+        InputTensor::new(
+            self.array
+                .as_slice()
+                .expect("the array should be in standard layout"),
+            self.array.shape(),
+        )
+    }
+}
+
+impl<'a, S: Data<Elem = f32>, D: Dimension> From<&'a ArrayBase<S, D>> for ArrayInput<'a> {
+    fn from(array: &'a ArrayBase<S, D>) -> Self {
+        let view = array.view().into_dyn();
+        Self {
+            array: if view.is_standard_layout() {
+                view.into()
+            } else {
+                view.as_standard_layout().into_owned().into()
+            },
+        }
+    }
+}
+
+impl<'a> From<&'a ArrayInput<'_>> for SessionInput<'a> {
+    fn from(input: &'a ArrayInput<'_>) -> Self {
+        Self::Float(input.as_input())
+    }
+}
+
+impl<'a> OutputTensor<'a> {
+    /// Borrows the output as an array. Returns `None` unless its elements are `f32`.
+    pub fn as_array_view(&self) -> Option<ArrayViewD<'_, f32>> {
+        ArrayViewD::from_shape(self.shape(), self.as_slice()?).ok()
+    }
+
+    /// Copies the output into an array. Returns `None` unless its elements are `f32`.
+    pub fn to_array(&self) -> Option<ArrayD<f32>> {
+        self.as_array_view().map(|view| view.to_owned())
+    }
+}
+
+impl OwnedTensor {
+    pub fn as_array_view<T: Element>(&self) -> Option<ArrayViewD<'_, T>> {
+        ArrayViewD::from_shape(self.shape(), self.as_slice()?).ok()
+    }
+}
+
+impl<T: Element, D: Dimension> From<Array<T, D>> for OwnedTensor {
+    fn from(array: Array<T, D>) -> Self {
+        let shape = array.shape().to_vec();
+        let data = if array.is_standard_layout() {
+            // A sliced array may only use part of its buffer, starting at an offset.
+            let (ptr, len) = (array.as_ptr() as usize, array.len());
+            let mut data = array.into_raw_vec();
+            if len == 0 {
+                data.clear();
+            } else {
+                let offset = (ptr - data.as_ptr() as usize) / std::mem::size_of::<T>();
+                data.truncate(offset + len);
+                data.drain(..offset);
+            }
+            data
+        } else {
+            array.iter().copied().collect()
+        };
+        Self::new(data, shape).expect("the array's length should match its shape")
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use ndarray::{array, s};
+
+    #[test]
+    fn test_input() {
+        let a = array![[1.0f32, 2.0, 3.0], [4.0, 5.0, 6.0]];
+        let input = InputTensor::try_from(&a).unwrap();
+        assert_eq!(input.data, &[1.0, 2.0, 3.0, 4.0, 5.0, 6.0]);
+        assert_eq!(input.shape, &[2, 3]);
+
+        let t = a.t();
+        assert!(InputTensor::try_from(&t).is_err());
+        let input = ArrayInput::from(&t);
+        assert_eq!(input.as_input().data, &[1.0, 4.0, 2.0, 5.0, 3.0, 6.0]);
+        assert_eq!(input.as_input().shape, &[3, 2]);
+
+        let column = a.slice(s![.., 1..2]);
+        let input = ArrayInput::from(&column);
+        assert_eq!(input.as_input().data, &[2.0, 5.0]);
+        assert_eq!(input.as_input().shape, &[2, 1]);
+    }
+
+    #[test]
+    fn test_owned_tensor() {
+        let a = array![[1i64, 2], [3, 4]];
+        let tensor = OwnedTensor::from(a.clone());
+        assert_eq!(tensor.shape(), &[2, 2]);
+        assert_eq!(tensor.as_array_view::<i64>().unwrap(), a.view().into_dyn());
+        assert!(tensor.as_array_view::<f32>().is_none());
+
+        let tensor = OwnedTensor::from(a.clone().reversed_axes());
+        assert_eq!(tensor.as_slice::<i64>(), Some(&[1, 3, 2, 4][..]));
+
+        let mut sliced = array![[1i64, 2], [3, 4], [5, 6], [7, 8]];
+        sliced.slice_collapse(s![1..3, ..]);
+        assert!(sliced.is_standard_layout());
+        let tensor = OwnedTensor::from(sliced);
+        assert_eq!(tensor.shape(), &[2, 2]);
+        assert_eq!(tensor.as_slice::<i64>(), Some(&[3, 4, 5, 6][..]));
+
+        let mut empty = a;
+        empty.slice_collapse(s![1..1, ..]);
+        let tensor = OwnedTensor::from(empty);
+        assert_eq!(tensor.shape(), &[0, 2]);
+        assert_eq!(tensor.as_slice::<i64>(), Some(&[][..]));
+    }
+}
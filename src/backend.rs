@@ -0,0 +1,349 @@
+//! The traits backends implement, and their implementations for ONNX Runtime and CoreML.
+
+#[cfg(all(feature = "coreml", target_os = "macos"))]
+use super::coreml;
+#[cfg(feature = "onnx")]
+use super::{onnx, Format};
+use super::{
+    NewSessionError, OutputTensor, SessionBackend, SessionInfoError, SessionInput, SessionOptions,
+    SessionRunError, Signature,
+};
+use std::path::Path;
+
+/// Loads models into something that can run them. The built-in backends are used for the formats
+/// they support unless other backends are registered with `Environment::register_backend`.
+/// Backends are shared by every thread using the environment.
+pub trait Backend: Send + Sync {
+    /// Loads the model at the given path. `options.format` is always set.
+    fn load<'a>(
+        &'a self,
+        path: &Path,
+        options: &SessionOptions,
+    ) -> Result<Box<dyn Model<'a> + 'a>, NewSessionError>;
+
+    /// Loads the model for a session. The built-in backends override this so that sessions expose
+    /// their models' own types. Otherwise, the model is wrapped in `SessionBackend::Custom`.
+    fn load_session_backend<'a>(
+        &'a self,
+        path: &Path,
+        options: &SessionOptions,
+    ) -> Result<SessionBackend<'a>, NewSessionError> {
+        Ok(SessionBackend::Custom(self.load(path, options)?))
+    }
+}
+
+/// A model loaded by a `Backend`. Sessions wrap these, mapping names and collecting outputs.
+pub trait Model<'a> {
+    /// Returns a short name for the backend, e.g. "onnxruntime".
+    fn backend_name(&self) -> &'static str;
+
+    fn signature(&self) -> Result<Signature, SessionInfoError>;
+
+    /// Returns the model's metadata as key-value pairs. Empty values are omitted by the session.
+    fn metadata(&self) -> Result<Vec<(String, String)>, SessionInfoError>;
+
+    /// Runs the model. Input names have already been mapped to the ones the model declares, and
+    /// outputs should be returned in the order the model declares them.
+    fn run(
+        &self,
+        inputs: &[(&str, SessionInput<'_>)],
+    ) -> Result<Vec<(String, OutputTensor<'a>)>, SessionRunError>;
+}
+
+#[cfg(feature = "onnx")]
+fn build_onnx_session<'a>(
+    env: &'a onnx::Environment,
+    path: &Path,
+    options: &SessionOptions,
+) -> Result<onnx::Session<'a>, NewSessionError> {
+    let mut builder =
+        env.session_builder()
+            .load_model_format(if options.format == Some(Format::ORT) {
+                onnx::ModelFormat::ORT
+            } else {
+                onnx::ModelFormat::ONNX
+            });
+    for (name, value) in &options.dimension_overrides {
+        builder = builder.free_dimension_override_by_name(name.as_str(), *value as _);
+    }
+    for path in &options.custom_ops_libraries {
+        builder = builder.custom_ops_library(path);
+    }
+    if let Some(dir) = &options.optimized_model_cache_dir {
+        builder = builder.optimized_model_cache_dir(dir);
+    }
+    Ok(builder.build(path)?)
+}
+
+#[cfg(feature = "onnx")]
+impl Backend for onnx::Environment {
+    fn load<'a>(
+        &'a self,
+        path: &Path,
+        options: &SessionOptions,
+    ) -> Result<Box<dyn Model<'a> + 'a>, NewSessionError> {
+        Ok(Box::new(build_onnx_session(self, path, options)?))
+    }
+
+    fn load_session_backend<'a>(
+        &'a self,
+        path: &Path,
+        options: &SessionOptions,
+    ) -> Result<SessionBackend<'a>, NewSessionError> {
+        Ok(SessionBackend::ONNX(build_onnx_session(
+            self, path, options,
+        )?))
+    }
+}
+
+#[cfg(feature = "onnx")]
+impl<'a> Model<'a> for onnx::Session<'a> {
+    fn backend_name(&self) -> &'static str {
+        "onnxruntime"
+    }
+
+    fn signature(&self) -> Result<Signature, SessionInfoError> {
+        Ok(Signature {
+            inputs: self.inputs().iter().map(Into::into).collect(),
+            outputs: self.outputs().iter().map(Into::into).collect(),
+        })
+    }
+
+    fn metadata(&self) -> Result<Vec<(String, String)>, SessionInfoError> {
+        let metadata = onnx::Session::metadata(self)?;
+        Ok(vec![
+            ("producer_name", metadata.producer_name),
+            ("graph_name", metadata.graph_name),
+            ("domain", metadata.domain),
+            ("description", metadata.description),
+            ("version", metadata.version.to_string()),
+        ]
+        .into_iter()
+        .map(|(k, v)| (k.to_string(), v))
+        .chain(metadata.custom)
+        .collect())
+    }
+
+    fn run(
+        &self,
+        inputs: &[(&str, SessionInput<'_>)],
+    ) -> Result<Vec<(String, OutputTensor<'a>)>, SessionRunError> {
+        let env = self.environment();
+        // ORT needs densely packed data, so strided inputs are packed first.
+        let packed = inputs
+            .iter()
+            .map(|(name, input)| match input {
+                SessionInput::Float(input) => input.packed_data(name).map(Some),
+                _ => Ok(None),
+            })
+            .collect::<Result<Vec<_>, SessionRunError>>()?;
+        // Inputs that aren't ORT tensors already are wrapped in ones that borrow their data.
+        let wrapped = inputs
+            .iter()
+            .zip(&packed)
+            .map(|((_, input), packed)| match (input, packed) {
+                (SessionInput::Float(input), Some(data)) => {
+                    env.new_tensor(data, input.shape).map(Some)
+                }
+                _ => input
+                    .owned_tensor()
+                    .map(|tensor| tensor.to_onnx(env))
+                    .transpose(),
+            })
+            .collect::<Result<Vec<_>, onnx::Error>>()?;
+        let inputs: Vec<_> = inputs
+            .iter()
+            .zip(&wrapped)
+            .map(|((name, input), wrapped)| {
+                let tensor = match input {
+                    SessionInput::ONNX(tensor) => tensor,
+                    _ => wrapped
+                        .as_ref()
+                        .expect("inputs should be wrapped in ORT tensors"),
+                };
+                (*name, tensor)
+            })
+            .collect();
+        let outputs = onnx::Session::run(self, &inputs)?;
+        Ok(outputs
+            .into_iter()
+            .map(|(name, output)| (name.to_string(), OutputTensor::ONNX(output)))
+            .collect())
+    }
+}
+
+/// The CoreML backend. Models are loaded with `coreml::MLModel::new`.
+#[cfg(all(feature = "coreml", target_os = "macos"))]
+pub struct CoreMLBackend;
+
+#[cfg(all(feature = "coreml", target_os = "macos"))]
+impl Backend for CoreMLBackend {
+    fn load<'a>(
+        &'a self,
+        path: &Path,
+        _options: &SessionOptions,
+    ) -> Result<Box<dyn Model<'a> + 'a>, NewSessionError> {
+        Ok(Box::new(coreml::MLModel::new(path)?))
+    }
+
+    fn load_session_backend<'a>(
+        &'a self,
+        path: &Path,
+        _options: &SessionOptions,
+    ) -> Result<SessionBackend<'a>, NewSessionError> {
+        Ok(SessionBackend::CoreML(coreml::MLModel::new(path)?))
+    }
+}
+
+#[cfg(all(feature = "coreml", target_os = "macos"))]
+impl<'a> Model<'a> for coreml::MLModel {
+    fn backend_name(&self) -> &'static str {
+        "coreml"
+    }
+
+    fn signature(&self) -> Result<Signature, SessionInfoError> {
+        Ok(Signature::from_coreml(&self.spec()?))
+    }
+
+    fn metadata(&self) -> Result<Vec<(String, String)>, SessionInfoError> {
+        let metadata = self.spec()?.metadata;
+        Ok(vec![
+            ("short_description", metadata.short_description),
+            ("version_string", metadata.version_string),
+            ("author", metadata.author),
+            ("license", metadata.license),
+        ]
+        .into_iter()
+        .map(|(k, v)| (k.to_string(), v))
+        .chain(metadata.user_defined)
+        .collect())
+    }
+
+    fn run(
+        &self,
+        inputs: &[(&str, SessionInput<'_>)],
+    ) -> Result<Vec<(String, OutputTensor<'a>)>, SessionRunError> {
+        let inputs = inputs
+            .iter()
+            .map(|(name, input)| {
+                let input = input.as_float(name)?;
+                if !input.has_valid_layout() {
+                    return Err(SessionRunError::InvalidStrides(name.to_string()));
+                }
+                Ok((
+                    *name,
+                    coreml::InputTensor {
+                        data: input.data,
+                        shape: input.shape,
+                        strides: input.strides,
+                    },
+                ))
+            })
+            .collect::<Result<Vec<_>, SessionRunError>>()?;
+        let outputs = self.predict(&inputs)?;
+        Ok(outputs
+            .into_iter()
+            .map(|(name, output)| (name.to_string(), OutputTensor::CoreML(output)))
+            .collect())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{onnx::proto::ElementType, Environment, Format, OwnedTensor, TensorSignature};
+    use std::borrow::Cow;
+
+    struct DoublingBackend;
+
+    impl Backend for DoublingBackend {
+        fn load<'a>(
+            &'a self,
+            _path: &Path,
+            _options: &SessionOptions,
+        ) -> Result<Box<dyn Model<'a> + 'a>, NewSessionError> {
+            Ok(Box::new(DoublingModel))
+        }
+    }
+
+    struct DoublingModel;
+
+    impl<'a> Model<'a> for DoublingModel {
+        fn backend_name(&self) -> &'static str {
+            "doubling"
+        }
+
+        fn signature(&self) -> Result<Signature, SessionInfoError> {
+            let tensor = |name: &str| TensorSignature {
+                name: name.to_string(),
+                element_type: ElementType::Float,
+                dimensions: None,
+            };
+            Ok(Signature {
+                inputs: vec![tensor("x")],
+                outputs: vec![tensor("y:0")],
+            })
+        }
+
+        fn metadata(&self) -> Result<Vec<(String, String)>, SessionInfoError> {
+            Ok(vec![
+                ("author".to_string(), "me".to_string()),
+                ("license".to_string(), String::new()),
+            ])
+        }
+
+        fn run(
+            &self,
+            inputs: &[(&str, SessionInput<'_>)],
+        ) -> Result<Vec<(String, OutputTensor<'a>)>, SessionRunError> {
+            let (name, input) = &inputs[0];
+            assert_eq!(*name, "x");
+            let input = input.as_float(name)?;
+            let data: Vec<f32> = input.packed_data(name)?.iter().map(|v| v * 2.0).collect();
+            let output = OwnedTensor::new(data, input.shape.to_vec()).unwrap();
+            Ok(vec![(
+                "y:0".to_string(),
+                OutputTensor::Tensor(Cow::Owned(output)),
+            )])
+        }
+    }
+
+    #[test]
+    fn test_register_backend() {
+        let mut env = Environment::new().unwrap();
+        env.register_backend(Format::ORT, DoublingBackend);
+        assert!(env.supports(Format::ORT));
+
+        let sess = env
+            .new_session_with_format("src/onnx/testdata/upsample.onnx", Format::ORT)
+            .unwrap();
+        assert_eq!(sess.backend_name(), "doubling");
+        assert!(matches!(sess.backend(), SessionBackend::Custom(_)));
+        #[cfg(feature = "onnx")]
+        assert!(sess.as_onnx().is_none());
+        assert_eq!(
+            sess.metadata().unwrap(),
+            vec![("author".to_string(), "me".to_string())]
+        );
+
+        let input = OwnedTensor::new(vec![1.0f32, 2.0], vec![2]).unwrap();
+        let outputs = sess.run(vec![("x:0", &input)]).unwrap();
+        assert_eq!(outputs.names().collect::<Vec<_>>(), vec!["y:0"]);
+        assert_eq!(outputs.get("y").unwrap().as_slice(), Some(&[2.0, 4.0][..]));
+        assert_eq!(outputs[0].shape(), &[2]);
+    }
+
+    #[cfg(feature = "onnx")]
+    #[test]
+    fn test_builtin_backend() {
+        let env = Environment::new().unwrap();
+        let mut sess = env.new_session("src/onnx/testdata/upsample.onnx").unwrap();
+        assert!(matches!(sess.backend(), SessionBackend::ONNX(_)));
+        assert_eq!(
+            sess.as_onnx().unwrap().inputs()[0].name,
+            "up_sampling2d_input:0"
+        );
+        assert!(sess.as_onnx_mut().is_some());
+        assert!(matches!(sess.into_backend(), SessionBackend::ONNX(_)));
+    }
+}
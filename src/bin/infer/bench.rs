@@ -0,0 +1,308 @@
+use super::{parse_key_value, read_inputs, Error, ModelArgs};
+use infer::{
+    onnx::proto::{Dimension, ElementType},
+    Environment, OwnedTensor, Session, TensorSignature,
+};
+use serde_json::json;
+use std::{
+    path::PathBuf,
+    sync::{
+        atomic::{AtomicUsize, Ordering},
+        Barrier,
+    },
+    time::{Duration, Instant},
+};
+
+#[derive(clap::Args)]
+pub struct Args {
+    #[clap(flatten)]
+    model: ModelArgs,
+
+    /// An input and the .npy file to read it from. May be given multiple times. Inputs that aren't
+    /// given are filled with random values.
+    #[clap(long = "input", value_name = "NAME=PATH", value_parser = parse_key_value::<PathBuf>)]
+    inputs: Vec<(String, PathBuf)>,
+
+    /// The number of untimed runs each worker does before measuring.
+    #[clap(long, default_value = "10")]
+    warmup: usize,
+
+    /// The number of timed runs, split across all workers.
+    #[clap(long, default_value = "100")]
+    iterations: usize,
+
+    /// The number of workers running the model at the same time. Each worker loads its own session.
+    #[clap(long, default_value = "1")]
+    concurrency: usize,
+
+    /// Seeds the random input values.
+    #[clap(long, default_value = "0")]
+    seed: u64,
+
+    /// Prints JSON instead of human-readable text.
+    #[clap(long)]
+    json: bool,
+}
+
+/// Fills random inputs. Benchmarks don't need good randomness, just values that aren't all zeros.
+struct XorShift(u64);
+
+impl XorShift {
+    fn next_f32(&mut self) -> f32 {
+        self.0 ^= self.0 << 13;
+        self.0 ^= self.0 >> 7;
+        self.0 ^= self.0 << 17;
+        (self.0 >> 40) as f32 / (1u64 << 24) as f32
+    }
+}
+
+/// Determines the shape to use for an input that isn't read from a file. Symbolic dimensions must
+/// be pinned with --dim. Dimensions without a name are assumed to be 1.
+fn resolve_shape(
+    input: &TensorSignature,
+    dimension_overrides: &[(String, usize)],
+) -> Result<Vec<usize>, String> {
+    let dims = input.dimensions.as_ref().ok_or_else(|| {
+        format!(
+            "input {} has an unknown shape, so it must be given with --input",
+            input.name
+        )
+    })?;
+    dims.iter()
+        .map(|dim| match dim {
+            Dimension::Fixed(n) => Ok(*n),
+            Dimension::Symbolic(name) => dimension_overrides
+                .iter()
+                .find(|(n, _)| n == name)
+                .map(|(_, value)| *value)
+                .ok_or_else(|| {
+                    format!(
+                        "input {} has symbolic dimension {:?}; pin it with --dim {}=N",
+                        input.name, name, name
+                    )
+                }),
+            Dimension::Unknown => Ok(1),
+        })
+        .collect()
+}
+
+/// Returns the latency at the given percentile using the nearest-rank method. `sorted` must not be
+/// empty.
+fn percentile(sorted: &[Duration], p: f64) -> Duration {
+    let rank = ((p / 100.0) * sorted.len() as f64).ceil() as usize;
+    sorted[rank.clamp(1, sorted.len()) - 1]
+}
+
+fn millis(d: Duration) -> f64 {
+    d.as_secs_f64() * 1000.0
+}
+
+type Inputs = Vec<(String, OwnedTensor)>;
+
+fn run_once(sess: &Session, inputs: &Inputs) -> Result<(), Error> {
+    sess.run(inputs.iter().map(|(name, tensor)| (name.as_str(), tensor)))?;
+    Ok(())
+}
+
+/// Loads a session, builds its inputs, and warms it up.
+fn prepare<'a>(
+    args: &Args,
+    env: &'a Environment,
+    worker_index: usize,
+    files: &Inputs,
+) -> Result<(Session<'a>, Inputs), Error> {
+    let sess = args.model.load(env)?;
+    // Files are matched to inputs the same way Session::run matches names.
+    let files = files
+        .iter()
+        .map(|(name, tensor)| match sess.input_name(name) {
+            Some(name) => Ok((name.to_string(), tensor)),
+            None => Err(format!("the model has no input named {}", name)),
+        })
+        .collect::<Result<Vec<_>, _>>()?;
+    let mut rng =
+        XorShift(args.seed ^ (worker_index as u64 + 1).wrapping_mul(0x9e37_79b9_7f4a_7c15));
+    let mut inputs = Vec::new();
+    for input in sess.signature()?.inputs {
+        if let Some((_, tensor)) = files.iter().find(|(name, _)| *name == input.name) {
+            inputs.push((input.name, (*tensor).clone()));
+            continue;
+        }
+        if input.element_type != ElementType::Float {
+            return Err(format!(
+                "input {} isn't a float tensor, so it must be given with --input",
+                input.name
+            )
+            .into());
+        }
+        let shape = resolve_shape(&input, &args.model.dimension_overrides)?;
+        let data = (0..shape.iter().product::<usize>())
+            .map(|_| rng.next_f32())
+            .collect::<Vec<_>>();
+        inputs.push((input.name, OwnedTensor::new(data, shape)?));
+    }
+    for _ in 0..args.warmup {
+        run_once(&sess, &inputs)?;
+    }
+    Ok((sess, inputs))
+}
+
+/// Runs the model until the shared iteration counter runs out, returning the latency of each timed
+/// run.
+fn worker(
+    args: &Args,
+    env: &Environment,
+    worker_index: usize,
+    files: &Inputs,
+    remaining: &AtomicUsize,
+    start: &Barrier,
+) -> Result<Vec<Duration>, String> {
+    let prepared = prepare(args, env, worker_index, files).map_err(|e| e.to_string());
+    // Every worker has to reach the barrier, even if it fails, or the others would wait forever.
+    start.wait();
+    let (sess, inputs) = prepared?;
+
+    let mut latencies = Vec::new();
+    while remaining
+        .fetch_update(Ordering::SeqCst, Ordering::SeqCst, |n| n.checked_sub(1))
+        .is_ok()
+    {
+        let begin = Instant::now();
+        run_once(&sess, &inputs).map_err(|e| e.to_string())?;
+        latencies.push(begin.elapsed());
+    }
+    Ok(latencies)
+}
+
+pub fn main(args: &Args) -> Result<(), Error> {
+    if args.iterations == 0 || args.concurrency == 0 {
+        return Err("--iterations and --concurrency must be at least 1".into());
+    }
+    let files = read_inputs(&args.inputs)?;
+    let env = Environment::new()?;
+
+    let remaining = AtomicUsize::new(args.iterations);
+    // The extra party is this thread, which starts the clock once every worker is warmed up.
+    let start = Barrier::new(args.concurrency + 1);
+    let (results, elapsed) = std::thread::scope(|scope| {
+        let handles = (0..args.concurrency)
+            .map(|i| {
+                let (env, files, remaining, start) = (&env, &files, &remaining, &start);
+                scope.spawn(move || worker(args, env, i, files, remaining, start))
+            })
+            .collect::<Vec<_>>();
+        start.wait();
+        let begin = Instant::now();
+        let results = handles
+            .into_iter()
+            .map(|handle| handle.join().expect("worker panicked"))
+            .collect::<Vec<_>>();
+        (results, begin.elapsed())
+    });
+
+    let mut latencies = Vec::with_capacity(args.iterations);
+    for result in results {
+        latencies.extend(result?);
+    }
+    latencies.sort();
+    let throughput = latencies.len() as f64 / elapsed.as_secs_f64();
+    let mean = latencies.iter().sum::<Duration>() / latencies.len() as u32;
+    let (p50, p90, p99, max) = (
+        percentile(&latencies, 50.0),
+        percentile(&latencies, 90.0),
+        percentile(&latencies, 99.0),
+        latencies[latencies.len() - 1],
+    );
+
+    if args.json {
+        let output = json!({
+            "iterations": latencies.len(),
+            "warmup": args.warmup,
+            "concurrency": args.concurrency,
+            "throughput": throughput,
+            "latency_ms": {
+                "mean": millis(mean),
+                "p50": millis(p50),
+                "p90": millis(p90),
+                "p99": millis(p99),
+                "max": millis(max),
+            },
+        });
+        println!("{}", serde_json::to_string_pretty(&output)?);
+        return Ok(());
+    }
+
+    println!(
+        "iterations: {} (concurrency {}, warmup {})",
+        latencies.len(),
+        args.concurrency,
+        args.warmup
+    );
+    println!("throughput: {:.2} runs/s", throughput);
+    println!(
+        "latency: mean {:.3} ms, p50 {:.3} ms, p90 {:.3} ms, p99 {:.3} ms, max {:.3} ms",
+        millis(mean),
+        millis(p50),
+        millis(p90),
+        millis(p99),
+        millis(max)
+    );
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_percentile() {
+        let sorted = (1..=10).map(Duration::from_millis).collect::<Vec<_>>();
+        assert_eq!(percentile(&sorted, 0.0), Duration::from_millis(1));
+        assert_eq!(percentile(&sorted, 50.0), Duration::from_millis(5));
+        assert_eq!(percentile(&sorted, 90.0), Duration::from_millis(9));
+        assert_eq!(percentile(&sorted, 99.0), Duration::from_millis(10));
+        assert_eq!(percentile(&sorted, 100.0), Duration::from_millis(10));
+        assert_eq!(
+            percentile(&[Duration::from_millis(3)], 50.0),
+            Duration::from_millis(3)
+        );
+    }
+
+    #[test]
+    fn test_resolve_shape() {
+        let input = TensorSignature {
+            name: "x".to_string(),
+            element_type: ElementType::Float,
+            dimensions: Some(vec![
+                Dimension::Symbolic("batch".to_string()),
+                Dimension::Unknown,
+                Dimension::Fixed(3),
+            ]),
+        };
+        assert_eq!(
+            resolve_shape(&input, &[("batch".to_string(), 4)]),
+            Ok(vec![4, 1, 3])
+        );
+        let err = resolve_shape(&input, &[]).unwrap_err();
+        assert!(err.contains("--dim batch=N"), "{}", err);
+
+        let unknown = TensorSignature {
+            dimensions: None,
+            ..input
+        };
+        assert!(resolve_shape(&unknown, &[]).is_err());
+    }
+
+    #[test]
+    fn test_xor_shift() {
+        let values = |seed| {
+            let mut rng = XorShift(seed);
+            (0..100).map(|_| rng.next_f32()).collect::<Vec<_>>()
+        };
+        let a = values(1);
+        assert_eq!(a, values(1));
+        assert_ne!(a, values(2));
+        assert!(a.iter().all(|v| (0.0..1.0).contains(v)));
+        assert!(a.iter().any(|v| *v != a[0]));
+    }
+}
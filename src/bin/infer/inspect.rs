@@ -0,0 +1,73 @@
+use super::{format_dimensions, format_element_type, Error, ModelArgs};
+use infer::{onnx::proto::Dimension, Environment, TensorSignature};
+use serde_json::{json, Value};
+
+#[derive(clap::Args)]
+pub struct Args {
+    #[clap(flatten)]
+    model: ModelArgs,
+
+    /// Prints JSON instead of human-readable text.
+    #[clap(long)]
+    json: bool,
+}
+
+fn tensor_json(tensor: &TensorSignature) -> Value {
+    json!({
+        "name": tensor.name,
+        "element_type": format_element_type(tensor),
+        "dimensions": tensor.dimensions.as_ref().map(|dims| {
+            dims.iter()
+                .map(|dim| match dim {
+                    Dimension::Fixed(n) => json!(n),
+                    Dimension::Symbolic(name) => json!(name),
+                    Dimension::Unknown => Value::Null,
+                })
+                .collect::<Vec<_>>()
+        }),
+    })
+}
+
+pub fn main(args: &Args) -> Result<(), Error> {
+    let env = Environment::new()?;
+    let sess = args.model.load(&env)?;
+    let signature = sess.signature()?;
+    let metadata = sess.metadata()?;
+
+    if args.json {
+        let output = json!({
+            "backend": sess.backend_name(),
+            "inputs": signature.inputs.iter().map(tensor_json).collect::<Vec<_>>(),
+            "outputs": signature.outputs.iter().map(tensor_json).collect::<Vec<_>>(),
+            "metadata": metadata
+                .into_iter()
+                .map(|(k, v)| (k, Value::String(v)))
+                .collect::<serde_json::Map<_, _>>(),
+        });
+        println!("{}", serde_json::to_string_pretty(&output)?);
+        return Ok(());
+    }
+
+    println!("backend: {}", sess.backend_name());
+    for (heading, tensors) in &[
+        ("inputs", &signature.inputs),
+        ("outputs", &signature.outputs),
+    ] {
+        println!("{}:", heading);
+        for tensor in tensors.iter() {
+            println!(
+                "  {}: {} {}",
+                tensor.name,
+                format_element_type(tensor),
+                format_dimensions(tensor)
+            );
+        }
+    }
+    if !metadata.is_empty() {
+        println!("metadata:");
+        for (key, value) in &metadata {
+            println!("  {}: {}", key, value);
+        }
+    }
+    Ok(())
+}
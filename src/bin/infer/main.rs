@@ -0,0 +1,139 @@
+use clap::Parser;
+use infer::{
+    bundle, npy, onnx::proto::Dimension, Bundle, Environment, Format, OwnedTensor, Session,
+    SessionOptions, TensorSignature,
+};
+use std::path::PathBuf;
+
+mod bench;
+mod inspect;
+mod run;
+
+type Error = Box<dyn std::error::Error>;
+
+#[derive(Parser)]
+#[clap(version, about = "Inspects and runs ONNX and CoreML models")]
+struct Args {
+    #[clap(subcommand)]
+    command: Command,
+}
+
+#[derive(clap::Subcommand)]
+enum Command {
+    /// Measures a model's latency and throughput.
+    Bench(bench::Args),
+    /// Prints a model's inputs, outputs, and metadata.
+    Inspect(inspect::Args),
+    /// Runs a model once with inputs read from .npy files.
+    Run(run::Args),
+}
+
+/// The arguments for loading a model, shared by all subcommands.
+#[derive(clap::Args)]
+pub struct ModelArgs {
+    /// The model to load, or a bundle directory to load the best-suited artifact of.
+    model: PathBuf,
+
+    /// The model's format (onnx, ort, or coreml). By default, it's detected from the model.
+    #[clap(long)]
+    format: Option<Format>,
+
+    /// Pins a symbolic dimension to a fixed value. May be given multiple times.
+    #[clap(long = "dim", value_name = "NAME=VALUE", value_parser = parse_key_value::<usize>)]
+    pub dimension_overrides: Vec<(String, usize)>,
+
+    /// Loads custom ops from a shared library. May be given multiple times.
+    #[clap(long = "custom-ops-library", value_name = "PATH")]
+    custom_ops_libraries: Vec<PathBuf>,
+
+    /// Caches graph-optimized models in the given directory.
+    #[clap(long, value_name = "DIR")]
+    optimized_model_cache_dir: Option<PathBuf>,
+
+    /// Gives an input or output another name. May be given multiple times.
+    #[clap(long = "alias", value_name = "ALIAS=NAME", value_parser = parse_key_value::<String>)]
+    name_aliases: Vec<(String, String)>,
+
+    /// Names outputs by their aliases, or without the ":0" suffix some converters add.
+    #[clap(long)]
+    canonical_output_names: bool,
+}
+
+impl ModelArgs {
+    pub fn load<'a>(&self, env: &'a Environment) -> Result<Session<'a>, Error> {
+        let options = SessionOptions {
+            format: self.format,
+            dimension_overrides: self.dimension_overrides.clone(),
+            custom_ops_libraries: self.custom_ops_libraries.clone(),
+            optimized_model_cache_dir: self.optimized_model_cache_dir.clone(),
+            name_aliases: self.name_aliases.clone(),
+            canonical_output_names: self.canonical_output_names,
+        };
+        if self.model.join(bundle::MANIFEST_NAME).is_file() {
+            let bundle = Bundle::open(&self.model)?;
+            return Ok(env.new_session_from_bundle(&bundle, &options)?);
+        }
+        Ok(env.new_session_with_options(&self.model, &options)?)
+    }
+}
+
+/// Parses arguments like "name=value".
+pub fn parse_key_value<T: std::str::FromStr>(s: &str) -> Result<(String, T), String>
+where
+    T::Err: std::fmt::Display,
+{
+    let (key, value) = s
+        .split_once('=')
+        .ok_or_else(|| format!("expected NAME=VALUE, got {:?}", s))?;
+    let value = value
+        .parse()
+        .map_err(|e| format!("invalid value {:?}: {}", value, e))?;
+    Ok((key.to_string(), value))
+}
+
+/// Reads the .npy files given with --input.
+pub fn read_inputs(inputs: &[(String, PathBuf)]) -> Result<Vec<(String, OwnedTensor)>, Error> {
+    inputs
+        .iter()
+        .map(|(name, path)| {
+            npy::read_path(path)
+                .map(|tensor| (name.clone(), tensor))
+                .map_err(|e| format!("unable to read {}: {}", path.display(), e).into())
+        })
+        .collect()
+}
+
+/// Formats dimensions like "[batch, 3, ?]".
+pub fn format_dimensions(tensor: &TensorSignature) -> String {
+    match &tensor.dimensions {
+        Some(dims) => format!(
+            "[{}]",
+            dims.iter()
+                .map(|dim| match dim {
+                    Dimension::Fixed(n) => n.to_string(),
+                    Dimension::Symbolic(name) => name.clone(),
+                    Dimension::Unknown => "?".to_string(),
+                })
+                .collect::<Vec<_>>()
+                .join(", ")
+        ),
+        None => "unknown shape".to_string(),
+    }
+}
+
+pub fn format_element_type(tensor: &TensorSignature) -> String {
+    format!("{:?}", tensor.element_type).to_lowercase()
+}
+
+fn main() {
+    let args = Args::parse();
+    let result = match &args.command {
+        Command::Bench(args) => bench::main(args),
+        Command::Inspect(args) => inspect::main(args),
+        Command::Run(args) => run::main(args),
+    };
+    if let Err(e) = result {
+        eprintln!("error: {}", e);
+        std::process::exit(1);
+    }
+}
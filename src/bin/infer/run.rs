@@ -0,0 +1,106 @@
+use super::{parse_key_value, read_inputs, Error, ModelArgs};
+use infer::{npy, Environment};
+use std::path::PathBuf;
+
+#[derive(clap::Args)]
+pub struct Args {
+    #[clap(flatten)]
+    model: ModelArgs,
+
+    /// An input and the .npy file to read it from. May be given multiple times.
+    #[clap(long = "input", value_name = "NAME=PATH", value_parser = parse_key_value::<PathBuf>)]
+    inputs: Vec<(String, PathBuf)>,
+
+    /// An output to write. May be given multiple times. By default, all outputs are written.
+    #[clap(long = "output", value_name = "NAME")]
+    outputs: Vec<String>,
+
+    /// The directory to write outputs to. Each output is written to a .npy file named after it.
+    #[clap(long, value_name = "DIR", default_value = ".")]
+    output_dir: PathBuf,
+}
+
+/// Turns an output name into something that's safe to use as a file name.
+fn file_name(output_name: &str) -> String {
+    let name: String = output_name
+        .chars()
+        .map(|c| match c {
+            'a'..='z' | 'A'..='Z' | '0'..='9' | '-' | '_' | '.' => c,
+            _ => '_',
+        })
+        .collect();
+    format!("{}.npy", name)
+}
+
+pub fn main(args: &Args) -> Result<(), Error> {
+    let env = Environment::new()?;
+    let sess = args.model.load(&env)?;
+
+    let inputs = read_inputs(&args.inputs)?;
+    let outputs = sess.run(inputs.iter().map(|(name, tensor)| (name.as_str(), tensor)))?;
+
+    // Requested outputs are looked up once, so a name that matches with or without the ":0"
+    // suffix is written under the name it was asked for.
+    let selected = if args.outputs.is_empty() {
+        outputs.iter().collect::<Vec<_>>()
+    } else {
+        args.outputs
+            .iter()
+            .map(|name| match outputs.get(name) {
+                Some(output) => Ok((name.as_str(), output)),
+                None => Err(format!("the model has no output named {:?}", name)),
+            })
+            .collect::<Result<Vec<_>, _>>()?
+    };
+
+    std::fs::create_dir_all(&args.output_dir)?;
+    for (name, output) in selected {
+        let path = args.output_dir.join(file_name(name));
+        npy::write_output_path(&path, output)
+            .map_err(|e| format!("unable to write {}: {}", path.display(), e))?;
+        println!("{} {:?} -> {}", name, output.shape(), path.display());
+    }
+    Ok(())
+}
+
+#[cfg(all(test, feature = "onnx"))]
+mod tests {
+    use super::*;
+    use clap::Parser;
+    use infer::OwnedTensor;
+
+    #[test]
+    fn test_run() {
+        let dir = std::env::temp_dir().join(format!("infer_test_run_{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let input_path = dir.join("input.npy");
+        let input = OwnedTensor::new(vec![1f32, 2., 3., 3., 4., 5.], vec![1, 1, 2, 3]).unwrap();
+        npy::write_path(&input_path, &input).unwrap();
+
+        // The model's output is named "Identity:0", but it can be asked for without the suffix.
+        let run = |output: &str| {
+            let args = crate::Args::try_parse_from([
+                "infer".as_ref(),
+                "run".as_ref(),
+                "src/onnx/testdata/upsample.onnx".as_ref(),
+                "--input".as_ref(),
+                format!("up_sampling2d_input:0={}", input_path.display()).as_ref(),
+                "--output".as_ref(),
+                output.as_ref(),
+                "--output-dir".as_ref(),
+                dir.as_os_str(),
+            ])
+            .unwrap();
+            match args.command {
+                crate::Command::Run(args) => main(&args),
+                _ => unreachable!(),
+            }
+        };
+        run("Identity").unwrap();
+        let output = npy::read_path(dir.join("Identity.npy")).unwrap();
+        assert_eq!(output.shape(), &[1, 2, 4, 3]);
+
+        assert!(run("Missing").is_err());
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+}
@@ -0,0 +1,293 @@
+//! Bundles: a directory holding the same model in several formats, along with a manifest
+//! describing the artifacts and metadata shared by all of them.
+//!
+//! The manifest is a `bundle.json` file like this:
+//!
+//! ```json
+//! {
+//!     "artifacts": [
+//!         {"path": "model.mlmodel"},
+//!         {"path": "model.onnx", "format": "onnx"}
+//!     ],
+//!     "labels": ["cat", "dog"],
+//!     "preprocessing": {"mean": [0.485, 0.456, 0.406], "std": [0.229, 0.224, 0.225]},
+//!     "metadata": {"version": "3"}
+//! }
+//! ```
+
+use super::{Format, ParseFormatError, ReadSignatureError, Signature, SignatureMismatch};
+use serde::Deserialize;
+use std::{
+    collections::BTreeMap,
+    io,
+    path::{Path, PathBuf},
+};
+
+/// The name of the manifest within a bundle's directory.
+pub const MANIFEST_NAME: &str = "bundle.json";
+
+/// The order formats are preferred in when more than one can be loaded. CoreML is only available
+/// on macOS, where it can use the GPU and Neural Engine, so it comes first.
+const PREFERRED_FORMATS: &[Format] = &[Format::CoreML, Format::ONNX, Format::ORT];
+
+#[derive(thiserror::Error, Debug)]
+pub enum OpenBundleError {
+    #[error("unable to read bundle: {0}")]
+    Io(#[from] io::Error),
+    #[error("malformed bundle manifest: {0}")]
+    MalformedManifest(#[from] serde_json::Error),
+    #[error(transparent)]
+    InvalidFormat(#[from] ParseFormatError),
+    #[error("the format of {0} couldn't be detected")]
+    UnrecognizedFormat(PathBuf),
+    #[error("the bundle has no artifacts")]
+    NoArtifacts,
+    #[error("unable to read the signature of {path}: {source}")]
+    ReadSignature {
+        path: PathBuf,
+        source: ReadSignatureError,
+    },
+    #[error("{path} has a different signature than {reference}: {}", format_mismatches(.mismatches))]
+    SignatureMismatch {
+        path: PathBuf,
+        reference: PathBuf,
+        mismatches: Vec<SignatureMismatch>,
+    },
+}
+
+fn format_mismatches(mismatches: &[SignatureMismatch]) -> String {
+    mismatches
+        .iter()
+        .map(|m| m.to_string())
+        .collect::<Vec<_>>()
+        .join(", ")
+}
+
+#[derive(Deserialize)]
+struct Manifest {
+    artifacts: Vec<ManifestArtifact>,
+    #[serde(default)]
+    labels: Vec<String>,
+    #[serde(default)]
+    preprocessing: Preprocessing,
+    #[serde(default)]
+    metadata: BTreeMap<String, String>,
+}
+
+#[derive(Deserialize)]
+struct ManifestArtifact {
+    path: PathBuf,
+    format: Option<String>,
+}
+
+/// How inputs should be prepared before they're passed to the model. Each field is empty if the
+/// manifest doesn't specify it.
+#[derive(Clone, Debug, Default, Deserialize, PartialEq)]
+#[serde(default)]
+pub struct Preprocessing {
+    /// A factor to multiply raw values by, e.g. 1/255 for 8-bit images.
+    pub scale: Option<f32>,
+    /// Per-channel means to subtract after scaling.
+    pub mean: Vec<f32>,
+    /// Per-channel standard deviations to divide by after subtracting the means.
+    pub std: Vec<f32>,
+}
+
+/// One of a bundle's model files.
+#[derive(Clone, Debug, PartialEq)]
+pub struct BundleArtifact {
+    pub path: PathBuf,
+    pub format: Format,
+}
+
+/// A model bundle, opened with `Bundle::open`. Sessions are created from it with
+/// `Environment::new_session_from_bundle`.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Bundle {
+    /// The artifacts, with paths resolved relative to the bundle's directory.
+    pub artifacts: Vec<BundleArtifact>,
+    /// The signature shared by the artifacts, if any of them is in a format it can be read from.
+    pub signature: Option<Signature>,
+    /// The class labels of a classifier's outputs.
+    pub labels: Vec<String>,
+    pub preprocessing: Preprocessing,
+    /// Free-form key-value pairs.
+    pub metadata: BTreeMap<String, String>,
+}
+
+impl Bundle {
+    /// Reads the bundle in the given directory and checks that all of its artifacts have
+    /// compatible signatures. Artifacts whose signatures can't be read without loading them, like
+    /// ORT models and compiled CoreML models, aren't checked.
+    pub fn open<P: AsRef<Path>>(dir: P) -> Result<Self, OpenBundleError> {
+        let dir = dir.as_ref();
+        let manifest: Manifest = serde_json::from_slice(&std::fs::read(dir.join(MANIFEST_NAME))?)?;
+        if manifest.artifacts.is_empty() {
+            return Err(OpenBundleError::NoArtifacts);
+        }
+
+        let artifacts = manifest
+            .artifacts
+            .into_iter()
+            .map(|artifact| {
+                let path = dir.join(artifact.path);
+                let format = match artifact.format {
+                    Some(format) => format.parse()?,
+                    None => Format::detect(&path)?
+                        .ok_or_else(|| OpenBundleError::UnrecognizedFormat(path.clone()))?,
+                };
+                Ok(BundleArtifact { path, format })
+            })
+            .collect::<Result<Vec<_>, OpenBundleError>>()?;
+
+        let mut signature: Option<(&Path, Signature)> = None;
+        for artifact in &artifacts {
+            let other = match Signature::from_path(&artifact.path) {
+                Ok(signature) => signature,
+                Err(ReadSignatureError::UnsupportedFormat(_)) => continue,
+                Err(source) => {
+                    return Err(OpenBundleError::ReadSignature {
+                        path: artifact.path.clone(),
+                        source,
+                    })
+                }
+            };
+            match &signature {
+                Some((reference, signature)) => {
+                    signature.check_compatible(&other).map_err(|mismatches| {
+                        OpenBundleError::SignatureMismatch {
+                            path: artifact.path.clone(),
+                            reference: reference.to_path_buf(),
+                            mismatches,
+                        }
+                    })?
+                }
+                None => signature = Some((&artifact.path, other)),
+            }
+        }
+
+        Ok(Self {
+            signature: signature.map(|(_, signature)| signature),
+            artifacts,
+            labels: manifest.labels,
+            preprocessing: manifest.preprocessing,
+            metadata: manifest.metadata,
+        })
+    }
+
+    /// Returns the artifact best suited to this build and host, or `None` if none of the
+    /// artifacts' formats are supported. Artifacts earlier in the manifest win ties.
+    pub fn select(&self) -> Option<&BundleArtifact> {
+        self.select_by(Format::is_supported)
+    }
+
+    /// Like `select`, but for the formats the given function accepts, e.g. those an
+    /// `Environment` has backends for.
+    pub fn select_by<F: Fn(Format) -> bool>(&self, supported: F) -> Option<&BundleArtifact> {
+        PREFERRED_FORMATS
+            .iter()
+            .filter(|&&format| supported(format))
+            .find_map(|&format| self.select_format(format))
+    }
+
+    /// Returns the first artifact in the given format.
+    pub fn select_format(&self, format: Format) -> Option<&BundleArtifact> {
+        self.artifacts
+            .iter()
+            .find(|artifact| artifact.format == format)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn write_bundle(name: &str, manifest: &str) -> PathBuf {
+        // Test binaries for different builds may run at the same time, so directories are unique
+        // per process.
+        let dir = std::env::temp_dir().join(format!("{}_{}", name, std::process::id()));
+        let _ = std::fs::remove_dir_all(&dir);
+        std::fs::create_dir_all(&dir).unwrap();
+        std::fs::copy("src/onnx/testdata/upsample.onnx", dir.join("upsample.onnx")).unwrap();
+        std::fs::copy(
+            "src/coreml/testdata/upsample.mlmodel",
+            dir.join("upsample.mlmodel"),
+        )
+        .unwrap();
+        std::fs::write(dir.join(MANIFEST_NAME), manifest).unwrap();
+        dir
+    }
+
+    #[test]
+    fn test_open() {
+        let dir = write_bundle(
+            "infer_test_bundle",
+            r#"{
+                "artifacts": [{"path": "upsample.mlmodel"}, {"path": "upsample.onnx", "format": "onnx"}],
+                "labels": ["a", "b"],
+                "preprocessing": {"scale": 0.5, "mean": [1.0]},
+                "metadata": {"version": "3"}
+            }"#,
+        );
+        let bundle = Bundle::open(&dir).unwrap();
+        assert_eq!(
+            bundle.artifacts,
+            vec![
+                BundleArtifact {
+                    path: dir.join("upsample.mlmodel"),
+                    format: Format::CoreML,
+                },
+                BundleArtifact {
+                    path: dir.join("upsample.onnx"),
+                    format: Format::ONNX,
+                },
+            ]
+        );
+        assert_eq!(
+            bundle.signature,
+            Some(Signature::from_path("src/coreml/testdata/upsample.mlmodel").unwrap())
+        );
+        assert_eq!(bundle.labels, vec!["a", "b"]);
+        assert_eq!(
+            bundle.preprocessing,
+            Preprocessing {
+                scale: Some(0.5),
+                mean: vec![1.0],
+                std: vec![],
+            }
+        );
+        assert_eq!(bundle.metadata["version"], "3");
+        let expected = if Format::CoreML.is_supported() {
+            Some(Format::CoreML)
+        } else if Format::ONNX.is_supported() {
+            Some(Format::ONNX)
+        } else {
+            None
+        };
+        assert_eq!(bundle.select().map(|artifact| artifact.format), expected);
+        assert!(bundle.select_format(Format::ORT).is_none());
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn test_signature_mismatch() {
+        let dir = write_bundle(
+            "infer_test_bundle_mismatch",
+            r#"{"artifacts": [{"path": "upsample.onnx"}, {"path": "other.onnx"}]}"#,
+        );
+        // Any ONNX model with different inputs will do.
+        std::fs::copy("src/onnx/testdata/custom_op.onnx", dir.join("other.onnx")).unwrap();
+        assert!(matches!(
+            Bundle::open(&dir),
+            Err(OpenBundleError::SignatureMismatch { path, .. }) if path == dir.join("other.onnx")
+        ));
+        std::fs::remove_dir_all(&dir).unwrap();
+
+        let dir = write_bundle("infer_test_bundle_empty", r#"{"artifacts": []}"#);
+        assert!(matches!(
+            Bundle::open(&dir),
+            Err(OpenBundleError::NoArtifacts)
+        ));
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+}
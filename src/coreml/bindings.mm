@@ -6,14 +6,15 @@
 #error "ARC is off"
 #endif
 
-extern "C" const void* open_coreml_model(const char* path, uint32_t counter) {
+extern "C" const void* open_coreml_model(const char* path, bool compiled, uint32_t counter) {
     @autoreleasepool {
         NSString* pathString = [NSString stringWithUTF8String:path];
         NSURL* pathURL = [NSURL fileURLWithPath:pathString];
 
         NSError *error = nil;
 
-        NSURL* compiledURL = [MLModel compileModelAtURL:pathURL error:&error];
+        // .mlmodelc directories are already compiled, so they can be loaded as is.
+        NSURL* compiledURL = compiled ? pathURL : [MLModel compileModelAtURL:pathURL error:&error];
         if (error != nil) {
             NSLog(@"%@", error);
             return NULL;
@@ -27,7 +28,9 @@ extern "C" const void* open_coreml_model(const char* path, uint32_t counter) {
         }
 
         MLModel* model = [MLModel modelWithContentsOfURL:compiledURL configuration:config error:&error];
-        [[NSFileManager defaultManager] removeItemAtURL:compiledURL error:NULL];
+        if (!compiled) {
+            [[NSFileManager defaultManager] removeItemAtURL:compiledURL error:NULL];
+        }
         if (error != nil) {
             NSLog(@"%@", error);
             return NULL;
@@ -95,6 +98,7 @@ extern "C" const void* mlmodel_predict(
     const void** inputDataPtrs,
     const uint32_t* inputDimensionalities,
     const uint32_t** inputShapes,
+    const uint32_t** inputStrides,
     uint32_t inputCount
 ) {
     @autoreleasepool {
@@ -106,19 +110,17 @@ extern "C" const void* mlmodel_predict(
             uint32_t dimensionality = inputDimensionalities[i];
 
             NSMutableArray* shape = [NSMutableArray arrayWithCapacity:dimensionality];
-            NSMutableArray* reversedStrides = [NSMutableArray arrayWithCapacity:dimensionality];
+            NSMutableArray* strides = [NSMutableArray arrayWithCapacity:dimensionality];
 
-            uint32_t p = 1;
             for (uint32_t d = 0; d < dimensionality; ++d) {
                 [shape insertObject:[NSNumber numberWithUnsignedInt:inputShapes[i][d]] atIndex:d];
-                [reversedStrides insertObject:[NSNumber numberWithUnsignedInt:p] atIndex:d];
-                p *= inputShapes[i][dimensionality - d - 1];
+                [strides insertObject:[NSNumber numberWithUnsignedInt:inputStrides[i][d]] atIndex:d];
             }
 
             MLMultiArray* array = [[MLMultiArray alloc] initWithDataPointer:(void*)inputDataPtrs[i]
                 shape:shape
                 dataType:MLMultiArrayDataTypeFloat32
-                strides:[[reversedStrides reverseObjectEnumerator] allObjects]
+                strides:strides
                 deallocator:nil
                 error:&error
             ];
@@ -1,21 +1,24 @@
 use std::{
     ffi::{c_void, CStr, CString},
     os::unix::ffi::OsStrExt,
-    path::Path,
+    path::{Path, PathBuf},
     sync::atomic::{AtomicUsize, Ordering},
 };
 
+pub mod spec;
+
 mod sys {
     use std::ffi::c_void;
 
     extern "C" {
-        pub fn open_coreml_model(path: *const i8, counter: u32) -> *const c_void;
+        pub fn open_coreml_model(path: *const i8, compiled: bool, counter: u32) -> *const c_void;
         pub fn mlmodel_predict(
             model: *const c_void,
             input_names: *const *const i8,
             input_data_ptrs: *const *const f32,
             input_dimensionalities: *const u32,
             input_shapes: *const *const u32,
+            input_strides: *const *const u32,
             input_count: u32,
         ) -> *const c_void;
         pub fn mlmultiarray_dimensionality(multiarray: *const c_void) -> u32;
@@ -34,6 +37,7 @@ mod sys {
 
 pub struct MLModel {
     inner: *const c_void,
+    path: PathBuf,
     output_names: Vec<String>,
     output_c_names: Vec<CString>,
 }
@@ -50,6 +54,8 @@ pub enum NewMLModelError {
 pub enum PredictError {
     #[error("malformed input name")]
     MalformedInputName,
+    #[error("input strides don't match the shape or reach past the end of the data")]
+    InvalidStrides,
     #[error("predict error")]
     PredictError,
 }
@@ -57,6 +63,9 @@ pub enum PredictError {
 pub struct InputTensor<'a> {
     pub data: &'a [f32],
     pub shape: &'a [usize],
+    /// The distance, in elements, between consecutive indices of each dimension. If `None`, the
+    /// data is densely packed in row-major order.
+    pub strides: Option<&'a [usize]>,
 }
 
 struct OutputProvider(*const c_void);
@@ -113,23 +122,26 @@ impl Drop for OutputTensor {
 static MODEL_COUNT: AtomicUsize = AtomicUsize::new(0);
 
 impl MLModel {
-    /// Creates a new model from the given path. Models created by this function will be
-    /// distributed evenly amongst available Metal devices.
+    /// Creates a new model from a .mlmodel file, a .mlpackage directory, or a compiled .mlmodelc
+    /// directory. Models created by this function will be distributed evenly amongst available
+    /// Metal devices.
     pub fn new<P: AsRef<Path>>(path: P) -> Result<Self, NewMLModelError> {
+        let compiled = path.as_ref().join("coremldata.bin").is_file();
+        let path_buf = path.as_ref().to_path_buf();
         let path = match CString::new(path.as_ref().as_os_str().as_bytes()) {
             Ok(s) => s,
             Err(_) => return Err(NewMLModelError::MalformedPath),
         };
         let counter = MODEL_COUNT.fetch_add(1, Ordering::SeqCst);
         unsafe {
-            let ptr = sys::open_coreml_model(path.as_ptr(), (counter % 0xffffffff) as _);
+            let ptr = sys::open_coreml_model(path.as_ptr(), compiled, (counter % 0xffffffff) as _);
             if ptr.is_null() {
                 Err(NewMLModelError::OpenError)
             } else {
                 let output_count = sys::mlmodel_output_count(ptr);
                 let mut output_names = vec![std::ptr::null(); output_count as usize];
                 sys::mlmodel_get_output_names(ptr, output_names.as_mut_ptr());
-                let (output_names, output_c_names) = output_names
+                let mut output_names: Vec<(String, CString)> = output_names
                     .into_iter()
                     .map(|s| {
                         let c_str = CStr::from_ptr(sys::nsstring_utf8(s)).to_owned();
@@ -140,9 +152,18 @@ impl MLModel {
                             .to_string();
                         (name, c_str)
                     })
-                    .unzip();
+                    .collect();
+                // The names come from a dictionary, so their order isn't meaningful. Use the order
+                // the outputs are declared in, or for compiled models, whose specs can't be read,
+                // sort them by name.
+                let declared: Vec<String> = spec::Model::from_path(&path_buf)
+                    .map(|spec| spec.outputs.into_iter().map(|output| output.name).collect())
+                    .unwrap_or_default();
+                crate::outputs::sort_by_declared_order(&mut output_names, &declared);
+                let (output_names, output_c_names) = output_names.into_iter().unzip();
                 Ok(Self {
                     inner: ptr,
+                    path: path_buf,
                     output_names,
                     output_c_names,
                 })
@@ -150,10 +171,16 @@ impl MLModel {
         }
     }
 
+    /// Returns the output names in the order the model declares them.
     pub fn output_names(&self) -> &[String] {
         &self.output_names
     }
 
+    /// Reads the model's specification. This isn't available for compiled .mlmodelc directories.
+    pub fn spec(&self) -> Result<spec::Model, spec::ReadModelError> {
+        spec::Model::from_path(&self.path)
+    }
+
     pub fn predict(
         &self,
         inputs: &[(&str, InputTensor)],
@@ -180,6 +207,30 @@ impl MLModel {
             .map(|(_, input)| input.shape.iter().map(|n| *n as u32).collect())
             .collect();
         let input_shapes: Vec<_> = input_shapes.iter().map(|shape| shape.as_ptr()).collect();
+        let input_strides: Vec<Vec<u32>> = inputs
+            .iter()
+            .map(|(_, input)| match input.strides {
+                Some(strides) => {
+                    if strides.len() != input.shape.len()
+                        || input.data.len() < crate::tensor::strided_len(input.shape, strides)
+                    {
+                        return Err(PredictError::InvalidStrides);
+                    }
+                    Ok(strides.iter().map(|n| *n as u32).collect())
+                }
+                None => {
+                    let mut strides = vec![1; input.shape.len()];
+                    for i in (1..strides.len()).rev() {
+                        strides[i - 1] = strides[i] * input.shape[i] as u32;
+                    }
+                    Ok(strides)
+                }
+            })
+            .collect::<Result<_, _>>()?;
+        let input_strides: Vec<_> = input_strides
+            .iter()
+            .map(|strides| strides.as_ptr())
+            .collect();
         let output_provider = unsafe {
             sys::mlmodel_predict(
                 self.inner,
@@ -187,6 +238,7 @@ impl MLModel {
                 input_data_ptrs.as_ptr(),
                 input_dimensionalities.as_ptr(),
                 input_shapes.as_ptr(),
+                input_strides.as_ptr(),
                 inputs.len() as _,
             )
         };
@@ -241,6 +293,7 @@ mod tests {
         let input = InputTensor {
             data: input.as_slice().unwrap(),
             shape: &[1, 1, 2, 3],
+            strides: None,
         };
         let outputs = model.predict(&[("up_sampling2d_input", input)]).unwrap();
         assert_eq!(outputs.len(), 1);
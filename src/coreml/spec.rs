@@ -0,0 +1,335 @@
+//! A read-only view of CoreML model specifications (the `Model` message in Model.proto), decoded in
+//! pure Rust. Unlike the rest of the `coreml` module, this works on any platform.
+
+use crate::protobuf::{DecodeError, Reader, Value};
+use serde::Deserialize;
+use std::{collections::HashMap, path::Path};
+
+#[derive(thiserror::Error, Debug)]
+pub enum ReadModelError {
+    #[error("unable to read model: {0}")]
+    Io(#[from] std::io::Error),
+    #[error(transparent)]
+    Decode(#[from] DecodeError),
+    #[error("malformed package manifest: {0}")]
+    MalformedManifest(#[from] serde_json::Error),
+    #[error("the package manifest has no entry for its root model {0}")]
+    MissingRootModel(String),
+}
+
+/// The parts of an .mlpackage's Manifest.json needed to find its model.
+#[derive(Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct PackageManifest {
+    item_info_entries: HashMap<String, PackageItem>,
+    root_model_identifier: String,
+}
+
+#[derive(Deserialize)]
+struct PackageItem {
+    /// Relative to the package's Data directory.
+    path: String,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub enum ArrayDataType {
+    Invalid,
+    Float16,
+    Float32,
+    Double,
+    Int32,
+}
+
+impl ArrayDataType {
+    fn from_spec(data_type: i64) -> Self {
+        match data_type {
+            65552 => Self::Float16,
+            65568 => Self::Float32,
+            65600 => Self::Double,
+            131_104 => Self::Int32,
+            _ => Self::Invalid,
+        }
+    }
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub enum FeatureType {
+    Int64,
+    Double,
+    String,
+    Image {
+        width: i64,
+        height: i64,
+    },
+    MultiArray {
+        data_type: ArrayDataType,
+        /// The default shape. This may be empty, e.g. for outputs whose shape isn't declared.
+        shape: Vec<i64>,
+        /// The allowed range of each dimension, if the model declares them. An upper bound of -1
+        /// means the dimension is unbounded.
+        shape_range: Option<Vec<(i64, i64)>>,
+    },
+    Dictionary,
+    Sequence,
+    Unknown,
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct FeatureDescription {
+    pub name: String,
+    pub short_description: String,
+    pub feature_type: FeatureType,
+    pub is_optional: bool,
+}
+
+impl FeatureDescription {
+    fn decode(r: Reader) -> Result<Self, DecodeError> {
+        let mut ret = Self {
+            name: String::new(),
+            short_description: String::new(),
+            feature_type: FeatureType::Unknown,
+            is_optional: false,
+        };
+        for field in r {
+            match field? {
+                (1, v) => ret.name = v.as_string()?,
+                (2, v) => ret.short_description = v.as_string()?,
+                (3, v) => {
+                    for field in v.as_message()? {
+                        match field? {
+                            (1, _) => ret.feature_type = FeatureType::Int64,
+                            (2, _) => ret.feature_type = FeatureType::Double,
+                            (3, _) => ret.feature_type = FeatureType::String,
+                            (4, v) => ret.feature_type = decode_image_type(v)?,
+                            (5, v) => ret.feature_type = decode_array_type(v)?,
+                            (6, _) => ret.feature_type = FeatureType::Dictionary,
+                            (7, _) => ret.feature_type = FeatureType::Sequence,
+                            (1000, v) => ret.is_optional = v.as_u64()? != 0,
+                            _ => {}
+                        }
+                    }
+                }
+                _ => {}
+            }
+        }
+        Ok(ret)
+    }
+}
+
+fn decode_image_type(v: Value) -> Result<FeatureType, DecodeError> {
+    let (mut width, mut height) = (0, 0);
+    for field in v.as_message()? {
+        match field? {
+            (1, v) => width = v.as_i64()?,
+            (2, v) => height = v.as_i64()?,
+            _ => {}
+        }
+    }
+    Ok(FeatureType::Image { width, height })
+}
+
+fn decode_array_type(v: Value) -> Result<FeatureType, DecodeError> {
+    let mut data_type = ArrayDataType::Invalid;
+    let mut shape = Vec::new();
+    let mut shape_range = None;
+    for field in v.as_message()? {
+        match field? {
+            (1, v) => v.extend_i64s(&mut shape)?,
+            (2, v) => data_type = ArrayDataType::from_spec(v.as_i64()?),
+            (31, v) => {
+                let mut ranges = Vec::new();
+                for field in v.as_message()? {
+                    if let (1, v) = field? {
+                        let mut range = (0, 0);
+                        for field in v.as_message()? {
+                            match field? {
+                                (1, v) => range.0 = v.as_i64()?,
+                                (2, v) => range.1 = v.as_i64()?,
+                                _ => {}
+                            }
+                        }
+                        ranges.push(range);
+                    }
+                }
+                shape_range = Some(ranges);
+            }
+            _ => {}
+        }
+    }
+    Ok(FeatureType::MultiArray {
+        data_type,
+        shape,
+        shape_range,
+    })
+}
+
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct Metadata {
+    pub short_description: String,
+    pub version_string: String,
+    pub author: String,
+    pub license: String,
+    pub user_defined: Vec<(String, String)>,
+}
+
+impl Metadata {
+    fn decode(r: Reader) -> Result<Self, DecodeError> {
+        let mut ret = Self::default();
+        for field in r {
+            match field? {
+                (1, v) => ret.short_description = v.as_string()?,
+                (2, v) => ret.version_string = v.as_string()?,
+                (3, v) => ret.author = v.as_string()?,
+                (4, v) => ret.license = v.as_string()?,
+                (100, v) => {
+                    let mut entry = (String::new(), String::new());
+                    for field in v.as_message()? {
+                        match field? {
+                            (1, v) => entry.0 = v.as_string()?,
+                            (2, v) => entry.1 = v.as_string()?,
+                            _ => {}
+                        }
+                    }
+                    ret.user_defined.push(entry);
+                }
+                _ => {}
+            }
+        }
+        Ok(ret)
+    }
+}
+
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct Model {
+    pub specification_version: i64,
+    pub inputs: Vec<FeatureDescription>,
+    pub outputs: Vec<FeatureDescription>,
+    pub metadata: Metadata,
+    pub is_updatable: bool,
+}
+
+impl Model {
+    /// Reads the specification from a .mlmodel file or a .mlpackage directory.
+    pub fn from_path<P: AsRef<Path>>(path: P) -> Result<Self, ReadModelError> {
+        let path = path.as_ref();
+        let buf = if path.is_dir() {
+            let manifest: PackageManifest =
+                serde_json::from_slice(&std::fs::read(path.join("Manifest.json"))?)?;
+            let root = manifest
+                .item_info_entries
+                .get(&manifest.root_model_identifier)
+                .ok_or_else(|| {
+                    ReadModelError::MissingRootModel(manifest.root_model_identifier.clone())
+                })?;
+            std::fs::read(path.join("Data").join(&root.path))?
+        } else {
+            std::fs::read(path)?
+        };
+        Ok(Self::decode(&buf)?)
+    }
+
+    pub fn decode(buf: &[u8]) -> Result<Self, DecodeError> {
+        let mut ret = Self::default();
+        for field in Reader::new(buf) {
+            match field? {
+                (1, v) => ret.specification_version = v.as_i64()?,
+                (2, v) => {
+                    for field in v.as_message()? {
+                        match field? {
+                            (1, v) => ret
+                                .inputs
+                                .push(FeatureDescription::decode(v.as_message()?)?),
+                            (10, v) => ret
+                                .outputs
+                                .push(FeatureDescription::decode(v.as_message()?)?),
+                            (100, v) => ret.metadata = Metadata::decode(v.as_message()?)?,
+                            _ => {}
+                        }
+                    }
+                }
+                (10, v) => ret.is_updatable = v.as_u64()? != 0,
+                _ => {}
+            }
+        }
+        Ok(ret)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_model() {
+        let model = Model::from_path("src/coreml/testdata/upsample.mlmodel").unwrap();
+        assert_eq!(model.specification_version, 4);
+        assert_eq!(
+            model.inputs,
+            vec![FeatureDescription {
+                name: "up_sampling2d_input".to_string(),
+                short_description: String::new(),
+                feature_type: FeatureType::MultiArray {
+                    data_type: ArrayDataType::Float32,
+                    shape: vec![1, 1, 2, 3],
+                    shape_range: Some(vec![(1, -1), (1, 1), (2, 2), (3, 3)]),
+                },
+                is_optional: false,
+            }]
+        );
+        assert_eq!(model.outputs.len(), 1);
+        assert_eq!(model.outputs[0].name, "Identity");
+        assert_eq!(
+            model.metadata.user_defined,
+            vec![
+                (
+                    "com.github.apple.coremltools.source".to_string(),
+                    "tensorflow==2.3.0".to_string()
+                ),
+                (
+                    "com.github.apple.coremltools.version".to_string(),
+                    "4.1".to_string()
+                ),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_package() {
+        let package = std::env::temp_dir().join(format!(
+            "infer_test_package_{}.mlpackage",
+            std::process::id()
+        ));
+        let _ = std::fs::remove_dir_all(&package);
+        std::fs::create_dir_all(package.join("Data/com.example/weights")).unwrap();
+        std::fs::copy(
+            "src/coreml/testdata/upsample.mlmodel",
+            package.join("Data/com.example/upsample.mlmodel"),
+        )
+        .unwrap();
+        let manifest = |root: &str| {
+            format!(
+                r#"{{
+                    "fileFormatVersion": "1.0.0",
+                    "itemInfoEntries": {{
+                        "A": {{"author": "com.example", "path": "com.example/weights"}},
+                        "B": {{"author": "com.example", "path": "com.example/upsample.mlmodel"}}
+                    }},
+                    "rootModelIdentifier": "{}"
+                }}"#,
+                root
+            )
+        };
+
+        std::fs::write(package.join("Manifest.json"), manifest("B")).unwrap();
+        let model = Model::from_path(&package).unwrap();
+        assert_eq!(model.outputs[0].name, "Identity");
+
+        std::fs::write(package.join("Manifest.json"), manifest("C")).unwrap();
+        assert!(matches!(
+            Model::from_path(&package),
+            Err(ReadModelError::MissingRootModel(id)) if id == "C"
+        ));
+
+        std::fs::remove_dir_all(&package).unwrap();
+    }
+}
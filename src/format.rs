@@ -0,0 +1,162 @@
+use super::protobuf::{FieldScanner, WIRE_TYPE_LENGTH_DELIMITED};
+use std::{
+    fmt,
+    fs::File,
+    io::{self, BufReader, Read},
+    path::Path,
+    str::FromStr,
+};
+
+/// A model format.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub enum Format {
+    /// An ONNX `ModelProto`.
+    ONNX,
+    /// An ONNX model converted into ORT's flatbuffer format.
+    ORT,
+    /// A CoreML `.mlmodel` file, `.mlpackage` directory, or compiled `.mlmodelc` directory.
+    CoreML,
+}
+
+impl fmt::Display for Format {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            Self::ONNX => "ONNX",
+            Self::ORT => "ORT",
+            Self::CoreML => "CoreML",
+        })
+    }
+}
+
+#[derive(thiserror::Error, Debug)]
+#[error("unknown format {0:?} (expected onnx, ort, or coreml)")]
+pub struct ParseFormatError(String);
+
+impl FromStr for Format {
+    type Err = ParseFormatError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_ascii_lowercase().as_str() {
+            "onnx" => Ok(Self::ONNX),
+            "ort" => Ok(Self::ORT),
+            "coreml" => Ok(Self::CoreML),
+            _ => Err(ParseFormatError(s.to_string())),
+        }
+    }
+}
+
+impl Format {
+    /// Returns the formats supported by the backends compiled into this build.
+    pub fn supported() -> &'static [Format] {
+        &[
+            #[cfg(any(feature = "onnx", feature = "reference"))]
+            Format::ONNX,
+            #[cfg(feature = "onnx")]
+            Format::ORT,
+            #[cfg(all(feature = "coreml", target_os = "macos"))]
+            Format::CoreML,
+        ]
+    }
+
+    pub fn is_supported(self) -> bool {
+        Self::supported().contains(&self)
+    }
+
+    /// Detects the format of the model at the given path. The content is inspected first, so
+    /// models don't need a particular extension, but the extension is used as a fallback if the
+    /// content is inconclusive.
+    pub fn detect<P: AsRef<Path>>(path: P) -> io::Result<Option<Format>> {
+        let path = path.as_ref();
+        let detected = if path.is_dir() {
+            if path.join("Manifest.json").is_file() || path.join("coremldata.bin").is_file() {
+                Some(Format::CoreML)
+            } else {
+                None
+            }
+        } else {
+            Self::detect_file(File::open(path)?)?
+        };
+        Ok(
+            detected.or_else(|| match path.extension().and_then(|s| s.to_str()) {
+                Some("onnx") => Some(Format::ONNX),
+                Some("ort") => Some(Format::ORT),
+                Some("mlmodel") | Some("mlpackage") | Some("mlmodelc") => Some(Format::CoreML),
+                _ => None,
+            }),
+        )
+    }
+
+    fn detect_file(mut f: File) -> io::Result<Option<Format>> {
+        // ORT models are flatbuffers with the "ORTM" file identifier.
+        let mut header = [0u8; 8];
+        if f.read_exact(&mut header).is_ok() && &header[4..] == b"ORTM" {
+            return Ok(Some(Format::ORT));
+        }
+
+        // Otherwise, look for fields that only exist in one of the protobuf messages: ONNX's
+        // ModelProto has its graph at field 7, while CoreML's Model puts its model types at
+        // fields 200 and up.
+        let mut fields = FieldScanner::new(BufReader::new(f))?;
+        loop {
+            match fields.next_field() {
+                Ok(Some((field, wire_type))) => match (field, wire_type) {
+                    (7, WIRE_TYPE_LENGTH_DELIMITED) => return Ok(Some(Format::ONNX)),
+                    (200..=u64::MAX, WIRE_TYPE_LENGTH_DELIMITED) => {
+                        return Ok(Some(Format::CoreML))
+                    }
+                    _ => {}
+                },
+                // Either we've reached the end, or the file isn't a protobuf message.
+                Ok(None) | Err(_) => return Ok(None),
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_detect() {
+        let dir =
+            std::env::temp_dir().join(format!("infer_test_detect_format_{}", std::process::id()));
+        let _ = std::fs::remove_dir_all(&dir);
+        std::fs::create_dir_all(&dir).unwrap();
+
+        // Content wins over extensions.
+        for (src, format) in &[
+            ("src/onnx/testdata/upsample.onnx", Format::ONNX),
+            ("src/coreml/testdata/upsample.mlmodel", Format::CoreML),
+        ] {
+            assert_eq!(Format::detect(src).unwrap(), Some(*format));
+            let dest = dir.join("3f6a0c");
+            std::fs::copy(src, &dest).unwrap();
+            assert_eq!(Format::detect(&dest).unwrap(), Some(*format));
+            let dest = dir.join("model.bin");
+            std::fs::copy(src, &dest).unwrap();
+            assert_eq!(Format::detect(&dest).unwrap(), Some(*format));
+        }
+
+        let ort = dir.join("ort");
+        std::fs::write(&ort, b"\x10\x00\x00\x00ORTM\x00\x00\x00\x00").unwrap();
+        assert_eq!(Format::detect(&ort).unwrap(), Some(Format::ORT));
+
+        let package = dir.join("model");
+        std::fs::create_dir(&package).unwrap();
+        std::fs::write(package.join("Manifest.json"), b"{}").unwrap();
+        assert_eq!(Format::detect(&package).unwrap(), Some(Format::CoreML));
+
+        // Inconclusive content falls back to the extension.
+        let unknown = dir.join("unknown");
+        std::fs::write(&unknown, b"hello world").unwrap();
+        assert_eq!(Format::detect(&unknown).unwrap(), None);
+        let onnx = dir.join("empty.onnx");
+        std::fs::write(&onnx, b"").unwrap();
+        assert_eq!(Format::detect(&onnx).unwrap(), Some(Format::ONNX));
+
+        assert!(Format::detect(dir.join("missing")).is_err());
+
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+}
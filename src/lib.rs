@@ -1,14 +1,74 @@
-use std::path::Path;
+use names::NameMap;
+use std::{
+    borrow::Cow,
+    path::{Path, PathBuf},
+    sync::Arc,
+};
+
+#[cfg(feature = "ndarray")]
+mod array;
+#[cfg(feature = "ndarray")]
+pub use array::{ArrayInput, NotStandardLayoutError};
 
 #[cfg(all(feature = "coreml", target_os = "macos"))]
 pub mod coreml;
 
+/// Elsewhere, only the parts of the coreml module that don't need CoreML itself are available.
+#[cfg(not(all(feature = "coreml", target_os = "macos")))]
+pub mod coreml {
+    pub mod spec;
+}
+
 #[cfg(feature = "onnx")]
 pub mod onnx;
 
+/// Without the onnx feature, only the parts of the onnx module that don't need ORT are available.
+#[cfg(not(feature = "onnx"))]
+pub mod onnx {
+    pub mod proto;
+}
+
+mod backend;
+#[cfg(all(feature = "coreml", target_os = "macos"))]
+pub use backend::CoreMLBackend;
+pub use backend::{Backend, Model};
+
+pub mod bundle;
+pub use bundle::{Bundle, BundleArtifact, OpenBundleError, Preprocessing};
+
+mod format;
+pub use format::{Format, ParseFormatError};
+
+pub mod npy;
+
+#[cfg(feature = "testing")]
+pub mod mock;
+
+mod names;
+
+mod outputs;
+pub use outputs::Outputs;
+
+mod protobuf;
+
+#[cfg(feature = "reference")]
+pub mod reference;
+
+#[cfg(feature = "remote")]
+pub mod remote;
+
+pub mod safetensors;
+
+mod signature;
+pub use signature::{ReadSignatureError, Signature, SignatureMismatch, TensorSignature};
+
+mod tensor;
+pub use tensor::{Element, OwnedTensor, ShapeMismatchError, TensorData};
+
 pub struct Environment {
     #[cfg(feature = "onnx")]
     onnx: onnx::Environment,
+    backends: Vec<(Format, Box<dyn Backend>)>,
 }
 
 #[derive(thiserror::Error, Debug)]
@@ -26,8 +86,37 @@ pub enum NewSessionError {
     #[cfg(all(feature = "coreml", target_os = "macos"))]
     #[error(transparent)]
     CoreML(#[from] coreml::NewMLModelError),
-    #[error("unsupported format")]
-    UnsupportedFormat,
+    #[error("unable to read model: {0}")]
+    Io(#[from] std::io::Error),
+    #[error(
+        "unrecognized model format (this build supports {})",
+        format_list(Format::supported())
+    )]
+    UnrecognizedFormat,
+    #[error(
+        "{0} models aren't supported by this build (it supports {})",
+        format_list(Format::supported())
+    )]
+    UnsupportedFormat(Format),
+    #[error(
+        "none of the bundle's artifacts are supported by this build (it supports {})",
+        format_list(Format::supported())
+    )]
+    NoSupportedArtifact,
+    #[error(transparent)]
+    Backend(Box<dyn std::error::Error + Send + Sync>),
+}
+
+fn format_list(formats: &[Format]) -> String {
+    if formats.is_empty() {
+        "no formats".to_string()
+    } else {
+        formats
+            .iter()
+            .map(|f| f.to_string())
+            .collect::<Vec<_>>()
+            .join(", ")
+    }
 }
 
 impl Environment {
@@ -35,26 +124,165 @@ impl Environment {
         Ok(Self {
             #[cfg(feature = "onnx")]
             onnx: onnx::Environment::new()?,
+            backends: Vec::new(),
         })
     }
 
-    pub fn new_session<P: AsRef<Path>>(&self, model_path: P) -> Result<Session, NewSessionError> {
-        let model_path = model_path.as_ref();
-        Ok(match model_path.extension().and_then(|s| s.to_str()) {
+    /// Registers a backend for models in the given format. It takes precedence over the built-in
+    /// backend and any backends registered for the format before it.
+    pub fn register_backend<B: Backend + 'static>(&mut self, format: Format, backend: B) {
+        self.backends.push((format, Box::new(backend)));
+    }
+
+    /// Returns the backend that loads models in the given format, if there is one.
+    pub fn backend(&self, format: Format) -> Option<&dyn Backend> {
+        if let Some((_, backend)) = self.backends.iter().rev().find(|(f, _)| *f == format) {
+            return Some(backend.as_ref());
+        }
+        match format {
             #[cfg(feature = "onnx")]
-            Some("onnx") => Session::ONNX(self.onnx.new_session(model_path)?),
+            Format::ONNX | Format::ORT => Some(&self.onnx),
             #[cfg(all(feature = "coreml", target_os = "macos"))]
-            Some("mlmodel") => Session::CoreML(coreml::MLModel::new(model_path)?),
-            _ => return Err(NewSessionError::UnsupportedFormat),
-        })
+            Format::CoreML => Some(&CoreMLBackend),
+            #[cfg(all(feature = "reference", not(feature = "onnx")))]
+            Format::ONNX => Some(&reference::ReferenceBackend),
+            #[allow(unreachable_patterns)]
+            _ => None,
+        }
+    }
+
+    pub fn supports(&self, format: Format) -> bool {
+        self.backend(format).is_some()
+    }
+
+    /// Returns the ONNX environment, e.g. for allocating tensors that ORT owns with
+    /// `onnx::Environment::allocate_tensor`.
+    #[cfg(feature = "onnx")]
+    pub fn onnx(&self) -> &onnx::Environment {
+        &self.onnx
+    }
+
+    /// Creates a session, detecting the model's format from its content or extension.
+    pub fn new_session<P: AsRef<Path>>(&self, model_path: P) -> Result<Session, NewSessionError> {
+        self.new_session_with_options(model_path, &SessionOptions::default())
+    }
+
+    pub fn new_session_with_format<P: AsRef<Path>>(
+        &self,
+        model_path: P,
+        format: Format,
+    ) -> Result<Session<'_>, NewSessionError> {
+        self.new_session_with_options(
+            model_path,
+            &SessionOptions {
+                format: Some(format),
+                ..SessionOptions::default()
+            },
+        )
+    }
+
+    /// Creates a session from the bundle's artifact that's best suited to this build, or if
+    /// `options.format` is given, from its first artifact in that format.
+    pub fn new_session_from_bundle(
+        &self,
+        bundle: &Bundle,
+        options: &SessionOptions,
+    ) -> Result<Session<'_>, NewSessionError> {
+        let artifact = match options.format {
+            Some(format) => bundle.select_format(format),
+            None => bundle.select_by(|format| self.supports(format)),
+        }
+        .ok_or(NewSessionError::NoSupportedArtifact)?;
+        self.new_session_with_options(
+            &artifact.path,
+            &SessionOptions {
+                format: Some(artifact.format),
+                ..options.clone()
+            },
+        )
+    }
+
+    pub fn new_session_with_options<P: AsRef<Path>>(
+        &self,
+        model_path: P,
+        options: &SessionOptions,
+    ) -> Result<Session<'_>, NewSessionError> {
+        let model_path = model_path.as_ref();
+        let format = match options.format {
+            Some(format) => format,
+            None => Format::detect(model_path)?.ok_or(NewSessionError::UnrecognizedFormat)?,
+        };
+        let backend = self
+            .backend(format)
+            .ok_or(NewSessionError::UnsupportedFormat(format))?;
+        let options = SessionOptions {
+            format: Some(format),
+            ..options.clone()
+        };
+        Ok(Session::from_backend(
+            backend.load_session_backend(model_path, &options)?,
+            &options,
+        ))
     }
 }
 
-pub enum Session<'a> {
+/// Options for creating sessions. Options that a backend doesn't support are ignored.
+#[derive(Clone, Debug, Default)]
+pub struct SessionOptions {
+    /// The model's format. By default, it's detected from the model's content or extension.
+    pub format: Option<Format>,
+    /// Pins symbolic dimensions with the given names to fixed values. ONNX only.
+    pub dimension_overrides: Vec<(String, usize)>,
+    /// Shared libraries that register custom ops. ONNX only.
+    pub custom_ops_libraries: Vec<PathBuf>,
+    /// Where to cache graph-optimized models. ONNX only.
+    pub optimized_model_cache_dir: Option<PathBuf>,
+    /// Alternative names for inputs and outputs, as (alias, name) pairs. The names may be given
+    /// with or without the ":0" suffix some converters add.
+    pub name_aliases: Vec<(String, String)>,
+    /// Reports outputs under their aliases, or without the ":0" suffix if they have none, so that
+    /// exports of the same model from different converters produce the same names.
+    pub canonical_output_names: bool,
+}
+
+/// A model loaded by any backend, along with the names its inputs and outputs go by.
+///
+/// This used to be an enum with a variant per built-in backend. It's now a struct so that it can
+/// hold models from registered backends and the name map; code that matched on it should match on
+/// `Session::backend` instead, or use `Session::as_onnx` and `Session::as_coreml`.
+pub struct Session<'a> {
+    backend: SessionBackend<'a>,
+    names: NameMap,
+}
+
+/// The model a session runs. Models loaded by the built-in backends keep their own types, so that
+/// their backend-specific APIs stay available:
+///
+/// ```ignore
+/// match sess.backend() {
+///     SessionBackend::ONNX(sess) => println!("{:?}", sess.overridable_initializers()),
+///     _ => {}
+/// }
+/// ```
+pub enum SessionBackend<'a> {
     #[cfg(feature = "onnx")]
     ONNX(onnx::Session<'a>),
     #[cfg(all(feature = "coreml", target_os = "macos"))]
     CoreML(coreml::MLModel),
+    /// A model loaded by a registered backend, or wrapped with `Session::new`.
+    Custom(Box<dyn Model<'a> + 'a>),
+}
+
+impl<'a> SessionBackend<'a> {
+    pub fn model(&self) -> &(dyn Model<'a> + 'a) {
+        match self {
+            #[cfg(feature = "onnx")]
+            Self::ONNX(sess) => sess,
+            #[cfg(all(feature = "coreml", target_os = "macos"))]
+            Self::CoreML(model) => model,
+            Self::Custom(model) => model.as_ref(),
+        }
+    }
 }
 
 #[derive(thiserror::Error, Debug)]
@@ -68,58 +296,284 @@ pub enum SessionRunError {
     #[cfg(all(feature = "coreml", target_os = "macos"))]
     #[error(transparent)]
     CoreML(#[from] coreml::PredictError),
+    #[error("input {name} has element type {element_type:?}, which this backend doesn't support")]
+    UnsupportedElementType {
+        name: String,
+        element_type: onnx::proto::ElementType,
+    },
+    #[error("input {0} has strides that don't match its shape or reach past the end of its data")]
+    InvalidStrides(String),
+    #[error("input {name}: {source}")]
+    ShapeMismatch {
+        name: String,
+        source: ShapeMismatchError,
+    },
+    #[error(transparent)]
+    Backend(Box<dyn std::error::Error + Send + Sync>),
+}
+
+#[derive(thiserror::Error, Debug)]
+pub enum SessionInfoError {
+    #[cfg(feature = "onnx")]
+    #[error(transparent)]
+    ONNX(#[from] onnx::Error),
+    #[cfg(all(feature = "coreml", target_os = "macos"))]
+    #[error(transparent)]
+    CoreML(#[from] coreml::spec::ReadModelError),
+    #[error(transparent)]
+    Backend(Box<dyn std::error::Error + Send + Sync>),
 }
 
 impl<'a> Session<'a> {
-    pub fn run<'r, I: IntoIterator<Item = (&'r str, InputTensor<'r>)>>(
-        &self,
-        inputs: I,
-    ) -> Result<Vec<(&str, OutputTensor<'a>)>, SessionRunError> {
-        match self {
-            #[cfg(feature = "onnx")]
-            Self::ONNX(sess) => {
-                let inputs: Vec<_> = inputs
-                    .into_iter()
-                    .map(|(name, input)| {
-                        Ok((
-                            name,
-                            sess.environment().new_tensor(input.data, input.shape)?,
-                        ))
-                    })
-                    .collect::<Result<_, onnx::Error>>()?;
-                let outputs = sess.run(&inputs)?;
-                Ok(outputs
-                    .into_iter()
-                    .map(|(name, output)| (name, OutputTensor::ONNX(output)))
-                    .collect())
-            }
-            #[cfg(all(feature = "coreml", target_os = "macos"))]
-            Self::CoreML(model) => {
-                let inputs: Vec<_> = inputs
-                    .into_iter()
-                    .map(|(name, input)| {
-                        (
-                            name,
-                            coreml::InputTensor {
-                                data: input.data,
-                                shape: input.shape,
-                            },
-                        )
-                    })
-                    .collect();
-                let outputs = model.predict(&inputs)?;
-                Ok(outputs
-                    .into_iter()
-                    .map(|(name, output)| (name, OutputTensor::CoreML(output)))
-                    .collect())
-            }
+    /// Wraps a model loaded by a backend, e.g. one loaded without an `Environment`. Only the
+    /// naming options are used.
+    pub fn new(model: Box<dyn Model<'a> + 'a>, options: &SessionOptions) -> Self {
+        Self::from_backend(SessionBackend::Custom(model), options)
+    }
+
+    /// Like `new`, but for any kind of model, e.g. an `onnx::Session` built with options that
+    /// `SessionOptions` doesn't cover.
+    pub fn from_backend(backend: SessionBackend<'a>, options: &SessionOptions) -> Self {
+        let names = NameMap::new(backend.model().signature().ok(), options);
+        Self { backend, names }
+    }
+
+    pub fn backend(&self) -> &SessionBackend<'a> {
+        &self.backend
+    }
+
+    pub fn into_backend(self) -> SessionBackend<'a> {
+        self.backend
+    }
+
+    /// Returns the ORT session if the model was loaded by the built-in ONNX backend, e.g. for its
+    /// overridable initializers or typed metadata.
+    #[cfg(feature = "onnx")]
+    pub fn as_onnx(&self) -> Option<&onnx::Session<'a>> {
+        match &self.backend {
+            SessionBackend::ONNX(sess) => Some(sess),
+            _ => None,
+        }
+    }
+
+    /// Like `as_onnx`, but mutable, e.g. for `onnx::Session::end_profiling`.
+    #[cfg(feature = "onnx")]
+    pub fn as_onnx_mut(&mut self) -> Option<&mut onnx::Session<'a>> {
+        match &mut self.backend {
+            SessionBackend::ONNX(sess) => Some(sess),
+            _ => None,
         }
     }
+
+    /// Returns the CoreML model if it was loaded by the built-in CoreML backend.
+    #[cfg(all(feature = "coreml", target_os = "macos"))]
+    pub fn as_coreml(&self) -> Option<&coreml::MLModel> {
+        match &self.backend {
+            SessionBackend::CoreML(model) => Some(model),
+            _ => None,
+        }
+    }
+
+    pub fn model(&self) -> &(dyn Model<'a> + 'a) {
+        self.backend.model()
+    }
+
+    /// Returns the name the model declares for an input, resolving aliases and the ":0" suffix
+    /// like `run` does. Returns `None` if the model has no such input or its signature isn't
+    /// available.
+    pub fn input_name<'n>(&'n self, name: &'n str) -> Option<&'n str> {
+        self.names.find_input(name)
+    }
+
+    /// Returns the name of the backend running the session.
+    pub fn backend_name(&self) -> &'static str {
+        self.model().backend_name()
+    }
+
+    pub fn signature(&self) -> Result<Signature, SessionInfoError> {
+        self.model().signature()
+    }
+
+    /// Returns the model's metadata as key-value pairs. Empty values are omitted.
+    pub fn metadata(&self) -> Result<Vec<(String, String)>, SessionInfoError> {
+        Ok(self
+            .model()
+            .metadata()?
+            .into_iter()
+            .filter(|(_, v)| !v.is_empty())
+            .collect())
+    }
+
+    /// Runs the model. Inputs may be anything that converts into a `SessionInput`, e.g.
+    /// `InputTensor`s or `OwnedTensor`s. The outputs are in the order the model declares them.
+    ///
+    /// Inputs can be named by their aliases or with or without the ":0" suffix some converters add,
+    /// whichever the model actually uses.
+    pub fn run<'r, I, T>(&self, inputs: I) -> Result<Outputs<'a>, SessionRunError>
+    where
+        I: IntoIterator<Item = (&'r str, T)>,
+        T: Into<SessionInput<'r>>,
+    {
+        let inputs: Vec<(&str, SessionInput)> = inputs
+            .into_iter()
+            .map(|(name, input)| (self.names.input(name), input.into()))
+            .collect();
+        let outputs = self.model().run(&inputs)?;
+        Ok(Outputs::new(
+            outputs
+                .into_iter()
+                .map(|(name, output)| (self.names.output(&name), output))
+                .collect(),
+        ))
+    }
 }
 
+#[derive(Clone, Copy)]
 pub struct InputTensor<'a> {
     pub data: &'a [f32],
     pub shape: &'a [usize],
+    /// The distance, in elements, between consecutive indices of each dimension. If `None`, the
+    /// data is densely packed in row-major order. Strides make it possible to pass a region of a
+    /// larger buffer, e.g. a crop of a frame, without copying it first.
+    pub strides: Option<&'a [usize]>,
+}
+
+impl<'a> InputTensor<'a> {
+    /// Creates an input whose data is densely packed in row-major order.
+    pub fn new(data: &'a [f32], shape: &'a [usize]) -> Self {
+        Self {
+            data,
+            shape,
+            strides: None,
+        }
+    }
+
+    pub fn with_strides(mut self, strides: &'a [usize]) -> Self {
+        self.strides = Some(strides);
+        self
+    }
+}
+
+/// Anything that can be passed to `Session::run` as an input.
+pub enum SessionInput<'a> {
+    Float(InputTensor<'a>),
+    /// A tensor of any element type. Backends that don't support its element type fail with
+    /// `SessionRunError::UnsupportedElementType`.
+    Tensor(&'a OwnedTensor),
+    /// Like `Tensor`, but owned, so the input doesn't borrow anything from the caller.
+    Owned(OwnedTensor),
+    /// Like `Owned`, but shared, so the same data can be used for many runs (possibly on other
+    /// threads) without copying it.
+    Shared(Arc<OwnedTensor>),
+    /// A tensor created with the ONNX environment, e.g. one allocated by ORT and filled in place.
+    #[cfg(feature = "onnx")]
+    ONNX(onnx::Tensor<'a>),
+}
+
+impl<'a> SessionInput<'a> {
+    pub(crate) fn owned_tensor(&self) -> Option<&OwnedTensor> {
+        match self {
+            Self::Tensor(tensor) => Some(tensor),
+            Self::Owned(tensor) => Some(tensor),
+            Self::Shared(tensor) => Some(tensor),
+            _ => None,
+        }
+    }
+
+    pub fn element_type(&self) -> onnx::proto::ElementType {
+        match self {
+            Self::Float(_) => onnx::proto::ElementType::Float,
+            #[cfg(feature = "onnx")]
+            Self::ONNX(tensor) => tensor.element_type(),
+            _ => self
+                .owned_tensor()
+                .expect("other inputs should be owned tensors")
+                .element_type(),
+        }
+    }
+
+    /// Returns the input as an `OwnedTensor`, for backends that don't need a particular tensor type.
+    /// Inputs that are `OwnedTensor`s already are borrowed, and others are copied, packing them if
+    /// they're strided.
+    pub fn as_tensor(&self, name: &str) -> Result<Cow<'_, OwnedTensor>, SessionRunError> {
+        Ok(match self {
+            Self::Float(input) => {
+                let data = input.packed_data(name)?.into_owned();
+                Cow::Owned(
+                    OwnedTensor::new(data, input.shape.to_vec()).map_err(|source| {
+                        SessionRunError::ShapeMismatch {
+                            name: name.to_string(),
+                            source,
+                        }
+                    })?,
+                )
+            }
+            Self::Tensor(tensor) => Cow::Borrowed(tensor),
+            Self::Owned(tensor) => Cow::Borrowed(tensor),
+            Self::Shared(tensor) => Cow::Borrowed(tensor),
+            #[cfg(feature = "onnx")]
+            Self::ONNX(tensor) => {
+                let data = tensor::onnx_tensor_data(tensor).ok_or_else(|| {
+                    SessionRunError::UnsupportedElementType {
+                        name: name.to_string(),
+                        element_type: tensor.element_type(),
+                    }
+                })?;
+                Cow::Owned(
+                    OwnedTensor::new(data, tensor.shape().to_vec())
+                        .expect("ORT tensors' data should match their shape"),
+                )
+            }
+        })
+    }
+
+    /// Borrows the input's data as `f32`s, for backends that only support floats. Strided inputs
+    /// are returned with their strides, which may need to be validated.
+    pub fn as_float(&self, name: &str) -> Result<InputTensor<'_>, SessionRunError> {
+        let input = match self {
+            Self::Float(input) => Some(*input),
+            #[cfg(feature = "onnx")]
+            Self::ONNX(tensor) => tensor
+                .as_slice()
+                .map(|data| InputTensor::new(data, tensor.shape())),
+            _ => self.owned_tensor().and_then(|tensor| tensor.as_input()),
+        };
+        input.ok_or_else(|| SessionRunError::UnsupportedElementType {
+            name: name.to_string(),
+            element_type: self.element_type(),
+        })
+    }
+}
+
+impl<'a> From<InputTensor<'a>> for SessionInput<'a> {
+    fn from(input: InputTensor<'a>) -> Self {
+        Self::Float(input)
+    }
+}
+
+impl<'a> From<&'a OwnedTensor> for SessionInput<'a> {
+    fn from(tensor: &'a OwnedTensor) -> Self {
+        Self::Tensor(tensor)
+    }
+}
+
+impl From<OwnedTensor> for SessionInput<'_> {
+    fn from(tensor: OwnedTensor) -> Self {
+        Self::Owned(tensor)
+    }
+}
+
+impl From<Arc<OwnedTensor>> for SessionInput<'_> {
+    fn from(tensor: Arc<OwnedTensor>) -> Self {
+        Self::Shared(tensor)
+    }
+}
+
+#[cfg(feature = "onnx")]
+impl<'a> From<onnx::Tensor<'a>> for SessionInput<'a> {
+    fn from(tensor: onnx::Tensor<'a>) -> Self {
+        Self::ONNX(tensor)
+    }
 }
 
 pub enum OutputTensor<'a> {
@@ -127,6 +581,9 @@ pub enum OutputTensor<'a> {
     ONNX(onnx::Tensor<'a>),
     #[cfg(all(feature = "coreml", target_os = "macos"))]
     CoreML(coreml::OutputTensor),
+    /// A plain tensor, from backends that don't have their own tensor type. It may borrow from the
+    /// model, e.g. for outputs that are constant.
+    Tensor(Cow<'a, OwnedTensor>),
 }
 
 impl<'a> OutputTensor<'a> {
@@ -136,6 +593,17 @@ impl<'a> OutputTensor<'a> {
             Self::ONNX(t) => t.as_slice(),
             #[cfg(all(feature = "coreml", target_os = "macos"))]
             Self::CoreML(t) => Some(t.as_slice()),
+            Self::Tensor(t) => t.as_slice(),
+        }
+    }
+
+    pub fn element_type(&self) -> onnx::proto::ElementType {
+        match self {
+            #[cfg(feature = "onnx")]
+            Self::ONNX(t) => t.element_type(),
+            #[cfg(all(feature = "coreml", target_os = "macos"))]
+            Self::CoreML(_) => onnx::proto::ElementType::Float,
+            Self::Tensor(t) => t.element_type(),
         }
     }
 
@@ -145,6 +613,7 @@ impl<'a> OutputTensor<'a> {
             Self::ONNX(t) => t.shape(),
             #[cfg(all(feature = "coreml", target_os = "macos"))]
             Self::CoreML(t) => t.shape(),
+            Self::Tensor(t) => t.shape(),
         }
     }
 }
@@ -0,0 +1,357 @@
+//! A mock backend for testing code that uses sessions without a real model or native libraries.
+//!
+//! Mock models declare a signature and either return canned outputs or compute them with a
+//! closure. Every call's inputs are recorded, and load and run failures can be injected:
+//!
+//! ```ignore
+//! let model = MockModel::new(signature).outputs(vec![("scores", scores)]);
+//! let sess = model.session();
+//! classify(&sess, &image)?;
+//! assert_eq!(model.calls()[0].input("image"), Some(&image));
+//!
+//! model.fail_next_run("out of memory");
+//! assert!(classify(&sess, &image).is_err());
+//! ```
+
+use super::{
+    onnx::proto::{Dimension, ElementType},
+    Backend, Model, NewSessionError, OutputTensor, OwnedTensor, Session, SessionInfoError,
+    SessionInput, SessionOptions, SessionRunError, Signature,
+};
+use std::{
+    borrow::Cow,
+    collections::VecDeque,
+    path::Path,
+    sync::{Arc, Mutex},
+};
+
+#[derive(thiserror::Error, Clone, Debug, PartialEq)]
+pub enum MockError {
+    /// An injected failure, or one returned by a closure computing outputs.
+    #[error("{0}")]
+    Failed(String),
+    #[error("the model has no input named {0}")]
+    UnknownInput(String),
+    #[error("missing input {0}")]
+    MissingInput(String),
+    #[error("input {name} should be {expected:?}, not {actual:?}")]
+    ElementType {
+        name: String,
+        expected: ElementType,
+        actual: ElementType,
+    },
+    #[error("input {name} has shape {shape:?}, which doesn't match {dimensions:?}")]
+    Shape {
+        name: String,
+        shape: Vec<usize>,
+        dimensions: Vec<Dimension>,
+    },
+}
+
+/// The inputs of one call to `Session::run`, under the names the model declares.
+#[derive(Clone, Debug, PartialEq)]
+pub struct MockCall {
+    pub inputs: Vec<(String, OwnedTensor)>,
+}
+
+impl MockCall {
+    pub fn input(&self, name: &str) -> Option<&OwnedTensor> {
+        self.inputs
+            .iter()
+            .find(|(n, _)| n == name)
+            .map(|(_, tensor)| tensor)
+    }
+}
+
+type ComputeFn = dyn Fn(&MockCall) -> Result<Vec<(String, OwnedTensor)>, MockError> + Send + Sync;
+
+/// A model that returns canned or computed outputs. Clones share their recorded calls and
+/// injected failures, so a clone kept by the test can inspect and control one owned by a session.
+#[derive(Clone)]
+pub struct MockModel {
+    signature: Signature,
+    metadata: Vec<(String, String)>,
+    compute: Arc<ComputeFn>,
+    calls: Arc<Mutex<Vec<MockCall>>>,
+    run_failures: Arc<Mutex<VecDeque<String>>>,
+}
+
+impl MockModel {
+    /// Creates a model with the given signature. Until outputs are given, runs return no outputs.
+    pub fn new(signature: Signature) -> Self {
+        Self {
+            signature,
+            metadata: Vec::new(),
+            compute: Arc::new(|_| Ok(Vec::new())),
+            calls: Arc::new(Mutex::new(Vec::new())),
+            run_failures: Arc::new(Mutex::new(VecDeque::new())),
+        }
+    }
+
+    /// Returns the given outputs from every run.
+    pub fn outputs<S: Into<String>>(self, outputs: Vec<(S, OwnedTensor)>) -> Self {
+        let outputs: Vec<_> = outputs
+            .into_iter()
+            .map(|(name, tensor)| (name.into(), tensor))
+            .collect();
+        self.compute(move |_| Ok(outputs.clone()))
+    }
+
+    /// Computes each run's outputs from its inputs. Errors are returned from `Session::run`.
+    pub fn compute<F>(mut self, f: F) -> Self
+    where
+        F: Fn(&MockCall) -> Result<Vec<(String, OwnedTensor)>, MockError> + Send + Sync + 'static,
+    {
+        self.compute = Arc::new(f);
+        self
+    }
+
+    pub fn metadata<K: Into<String>, V: Into<String>>(mut self, key: K, value: V) -> Self {
+        self.metadata.push((key.into(), value.into()));
+        self
+    }
+
+    /// Makes the next run fail with the given message. Failures queue up, one per run.
+    pub fn fail_next_run<S: Into<String>>(&self, message: S) {
+        self.run_failures.lock().unwrap().push_back(message.into());
+    }
+
+    /// Returns the calls made so far, including ones that failed.
+    pub fn calls(&self) -> Vec<MockCall> {
+        self.calls.lock().unwrap().clone()
+    }
+
+    /// Creates a session for the model without an `Environment`.
+    pub fn session(&self) -> Session<'static> {
+        Session::new(Box::new(self.clone()), &SessionOptions::default())
+    }
+
+    /// Checks the inputs against the signature, like a real backend would.
+    fn check(&self, call: &MockCall) -> Result<(), MockError> {
+        for (name, tensor) in &call.inputs {
+            let input = self
+                .signature
+                .inputs
+                .iter()
+                .find(|input| input.name == *name)
+                .ok_or_else(|| MockError::UnknownInput(name.clone()))?;
+            if input.element_type != ElementType::Undefined
+                && input.element_type != tensor.element_type()
+            {
+                return Err(MockError::ElementType {
+                    name: name.clone(),
+                    expected: input.element_type,
+                    actual: tensor.element_type(),
+                });
+            }
+            if let Some(dimensions) = &input.dimensions {
+                let compatible = dimensions.len() == tensor.shape().len()
+                    && dimensions
+                        .iter()
+                        .zip(tensor.shape())
+                        .all(|(dim, n)| match dim {
+                            Dimension::Fixed(expected) => expected == n,
+                            Dimension::Symbolic(_) | Dimension::Unknown => true,
+                        });
+                if !compatible {
+                    return Err(MockError::Shape {
+                        name: name.clone(),
+                        shape: tensor.shape().to_vec(),
+                        dimensions: dimensions.clone(),
+                    });
+                }
+            }
+        }
+        if let Some(input) = self
+            .signature
+            .inputs
+            .iter()
+            .find(|input| call.input(&input.name).is_none())
+        {
+            return Err(MockError::MissingInput(input.name.clone()));
+        }
+        Ok(())
+    }
+}
+
+impl<'a> Model<'a> for MockModel {
+    fn backend_name(&self) -> &'static str {
+        "mock"
+    }
+
+    fn signature(&self) -> Result<Signature, SessionInfoError> {
+        Ok(self.signature.clone())
+    }
+
+    fn metadata(&self) -> Result<Vec<(String, String)>, SessionInfoError> {
+        Ok(self.metadata.clone())
+    }
+
+    fn run(
+        &self,
+        inputs: &[(&str, SessionInput<'_>)],
+    ) -> Result<Vec<(String, OutputTensor<'a>)>, SessionRunError> {
+        let call = MockCall {
+            inputs: inputs
+                .iter()
+                .map(|(name, input)| Ok((name.to_string(), input.as_tensor(name)?.into_owned())))
+                .collect::<Result<Vec<_>, SessionRunError>>()?,
+        };
+        self.calls.lock().unwrap().push(call.clone());
+
+        let failure = self.run_failures.lock().unwrap().pop_front();
+        let mut outputs = failure
+            .map_or(Ok(()), |message| Err(MockError::Failed(message)))
+            .and_then(|_| self.check(&call))
+            .and_then(|_| (self.compute)(&call))
+            .map_err(|e| SessionRunError::Backend(Box::new(e)))?;
+        // Outputs are returned in the declared order, like a real backend's.
+        let declared: Vec<String> = self
+            .signature
+            .outputs
+            .iter()
+            .map(|output| output.name.clone())
+            .collect();
+        crate::outputs::sort_by_declared_order(&mut outputs, &declared);
+        Ok(outputs
+            .into_iter()
+            .map(|(name, tensor)| (name, OutputTensor::Tensor(Cow::Owned(tensor))))
+            .collect())
+    }
+}
+
+/// Loads a `MockModel` regardless of the path, for testing code that creates sessions with an
+/// `Environment`. Register it with `Environment::register_backend`.
+pub struct MockBackend {
+    model: MockModel,
+    load_failure: Option<String>,
+}
+
+impl MockBackend {
+    pub fn new(model: MockModel) -> Self {
+        Self {
+            model,
+            load_failure: None,
+        }
+    }
+
+    /// Makes every load fail with the given message.
+    pub fn fail_load<S: Into<String>>(mut self, message: S) -> Self {
+        self.load_failure = Some(message.into());
+        self
+    }
+}
+
+impl Backend for MockBackend {
+    fn load<'a>(
+        &'a self,
+        _path: &Path,
+        _options: &SessionOptions,
+    ) -> Result<Box<dyn Model<'a> + 'a>, NewSessionError> {
+        match &self.load_failure {
+            Some(message) => Err(NewSessionError::Backend(Box::new(MockError::Failed(
+                message.clone(),
+            )))),
+            None => Ok(Box::new(self.model.clone())),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{Environment, Format, TensorSignature};
+
+    fn signature() -> Signature {
+        let tensor = |name: &str, dimensions| TensorSignature {
+            name: name.to_string(),
+            element_type: ElementType::Float,
+            dimensions,
+        };
+        Signature {
+            inputs: vec![tensor(
+                "x:0",
+                Some(vec![Dimension::Unknown, Dimension::Fixed(2)]),
+            )],
+            outputs: vec![tensor("sum", None), tensor("doubled", None)],
+        }
+    }
+
+    #[test]
+    fn test_mock_model() {
+        let model = MockModel::new(signature())
+            .compute(|call| {
+                let x = call.input("x:0").unwrap();
+                let data: Vec<f32> = x
+                    .as_slice::<f32>()
+                    .unwrap()
+                    .iter()
+                    .map(|v| v * 2.)
+                    .collect();
+                let sum = data.iter().sum::<f32>() / 2.;
+                Ok(vec![
+                    (
+                        "doubled".to_string(),
+                        OwnedTensor::new(data, x.shape().to_vec()).unwrap(),
+                    ),
+                    (
+                        "sum".to_string(),
+                        OwnedTensor::new(vec![sum], vec![]).unwrap(),
+                    ),
+                ])
+            })
+            .metadata("author", "me");
+        let sess = model.session();
+        assert_eq!(sess.backend_name(), "mock");
+        assert_eq!(
+            sess.metadata().unwrap(),
+            vec![("author".to_string(), "me".to_string())]
+        );
+
+        let x = OwnedTensor::new(vec![1f32, 2., 3., 4.], vec![2, 2]).unwrap();
+        let outputs = sess.run(vec![("x", &x)]).unwrap();
+        assert_eq!(outputs.names().collect::<Vec<_>>(), vec!["sum", "doubled"]);
+        assert_eq!(outputs.get("sum").unwrap().as_slice(), Some(&[10.][..]));
+        assert_eq!(
+            model.calls(),
+            vec![MockCall {
+                inputs: vec![("x:0".to_string(), x.clone())]
+            }]
+        );
+
+        model.fail_next_run("out of memory");
+        let err = sess.run(vec![("x", &x)]).err().unwrap();
+        assert_eq!(err.to_string(), "out of memory");
+        assert!(sess.run(vec![("x", &x)]).is_ok());
+        assert_eq!(model.calls().len(), 3);
+
+        let wrong_shape = OwnedTensor::new(vec![1f32, 2., 3.], vec![1, 3]).unwrap();
+        assert!(sess.run(vec![("x", &wrong_shape)]).is_err());
+        assert!(sess.run(Vec::<(&str, &OwnedTensor)>::new()).is_err());
+    }
+
+    #[test]
+    fn test_mock_backend() {
+        let y = OwnedTensor::new(vec![1f32], vec![1]).unwrap();
+        let model = MockModel::new(signature()).outputs(vec![("sum", y.clone())]);
+        let mut env = Environment::new().unwrap();
+        env.register_backend(Format::ONNX, MockBackend::new(model.clone()));
+        let sess = env
+            .new_session_with_format("missing.onnx", Format::ONNX)
+            .unwrap();
+        let x = OwnedTensor::new(vec![0f32; 2], vec![1, 2]).unwrap();
+        let outputs = sess.run(vec![("x:0", &x)]).unwrap();
+        assert_eq!(outputs.get("sum").unwrap().to_owned_tensor(), Some(y));
+        assert_eq!(model.calls()[0].input("x:0"), Some(&x));
+
+        let mut env = Environment::new().unwrap();
+        env.register_backend(
+            Format::ONNX,
+            MockBackend::new(model).fail_load("no such model"),
+        );
+        assert!(matches!(
+            env.new_session_with_format("missing.onnx", Format::ONNX),
+            Err(NewSessionError::Backend(e)) if e.to_string() == "no such model"
+        ));
+    }
+}
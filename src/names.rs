@@ -0,0 +1,136 @@
+//! Maps the tensor names callers use onto the names a model declares, so that the same code works
+//! with exports of a model whose converters name tensors differently.
+
+use super::{SessionOptions, Signature};
+
+/// Returns the name without the ":0" suffix TensorFlow converters tend to add.
+pub(crate) fn normalize(name: &str) -> &str {
+    name.strip_suffix(":0").unwrap_or(name)
+}
+
+pub(crate) struct NameMap {
+    inputs: Vec<String>,
+    outputs: Vec<String>,
+    aliases: Vec<(String, String)>,
+    canonical_output_names: bool,
+}
+
+impl NameMap {
+    /// Creates a map for a model with the given signature. If the signature isn't available, only
+    /// aliases are resolved.
+    pub(crate) fn new(signature: Option<Signature>, options: &SessionOptions) -> Self {
+        let (inputs, outputs) = match signature {
+            Some(signature) => (
+                signature.inputs.into_iter().map(|t| t.name).collect(),
+                signature.outputs.into_iter().map(|t| t.name).collect(),
+            ),
+            None => (Vec::new(), Vec::new()),
+        };
+        Self {
+            inputs,
+            outputs,
+            aliases: options.name_aliases.clone(),
+            canonical_output_names: options.canonical_output_names,
+        }
+    }
+
+    /// Returns the model's name for an input. Names that don't match anything are returned as is,
+    /// so that the backend can report them.
+    pub(crate) fn input<'n>(&'n self, name: &'n str) -> &'n str {
+        resolve(&self.inputs, &self.aliases, name)
+    }
+
+    /// Like `input`, but returns `None` for names that don't match any of the model's inputs.
+    pub(crate) fn find_input<'n>(&'n self, name: &'n str) -> Option<&'n str> {
+        let name = self.input(name);
+        if self.inputs.iter().any(|n| n == name) {
+            Some(name)
+        } else {
+            None
+        }
+    }
+
+    /// Returns the name an output is reported under: its alias or its name without the ":0"
+    /// suffix if canonical names were requested, and otherwise the model's name.
+    pub(crate) fn output(&self, name: &str) -> String {
+        if !self.canonical_output_names {
+            return name.to_string();
+        }
+        self.aliases
+            .iter()
+            .find(|(_, target)| resolve(&self.outputs, &[], target) == name)
+            .map_or_else(|| normalize(name), |(alias, _)| alias.as_str())
+            .to_string()
+    }
+}
+
+fn resolve<'n>(names: &'n [String], aliases: &'n [(String, String)], name: &'n str) -> &'n str {
+    if names.iter().any(|n| n == name) {
+        return name;
+    }
+    let name = aliases
+        .iter()
+        .find(|(alias, _)| alias == name)
+        .map_or(name, |(_, target)| target.as_str());
+    names
+        .iter()
+        .find(|n| *n == name)
+        .or_else(|| names.iter().find(|n| normalize(n) == normalize(name)))
+        .map_or(name, |n| n.as_str())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_name_map() {
+        let onnx = Signature::from_path("src/onnx/testdata/upsample.onnx").unwrap();
+        let coreml = Signature::from_path("src/coreml/testdata/upsample.mlmodel").unwrap();
+        let options = SessionOptions {
+            name_aliases: vec![
+                ("image".to_string(), "up_sampling2d_input".to_string()),
+                ("upsampled".to_string(), "Identity".to_string()),
+            ],
+            ..SessionOptions::default()
+        };
+
+        let names = NameMap::new(Some(onnx), &options);
+        assert_eq!(names.input("up_sampling2d_input"), "up_sampling2d_input:0");
+        assert_eq!(
+            names.input("up_sampling2d_input:0"),
+            "up_sampling2d_input:0"
+        );
+        assert_eq!(names.input("image"), "up_sampling2d_input:0");
+        assert_eq!(names.input("missing"), "missing");
+        assert_eq!(names.find_input("image"), Some("up_sampling2d_input:0"));
+        assert_eq!(names.find_input("missing"), None);
+        assert_eq!(names.output("Identity:0"), "Identity:0");
+
+        let names = NameMap::new(Some(coreml), &options);
+        assert_eq!(names.input("up_sampling2d_input:0"), "up_sampling2d_input");
+        assert_eq!(names.input("image"), "up_sampling2d_input");
+
+        let canonical = SessionOptions {
+            canonical_output_names: true,
+            ..options.clone()
+        };
+        let names = NameMap::new(
+            Some(Signature::from_path("src/onnx/testdata/upsample.onnx").unwrap()),
+            &canonical,
+        );
+        assert_eq!(names.output("Identity:0"), "upsampled");
+        let names = NameMap::new(
+            Some(Signature::from_path("src/onnx/testdata/upsample.onnx").unwrap()),
+            &SessionOptions {
+                canonical_output_names: true,
+                ..SessionOptions::default()
+            },
+        );
+        assert_eq!(names.output("Identity:0"), "Identity");
+
+        let names = NameMap::new(None, &options);
+        assert_eq!(names.input("image"), "up_sampling2d_input");
+        assert_eq!(names.input("other:0"), "other:0");
+    }
+}
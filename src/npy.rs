@@ -0,0 +1,393 @@
+//! Reading and writing NumPy .npy and .npz files.
+
+use super::{onnx::proto::ElementType, OutputTensor, OwnedTensor, TensorData};
+use std::{
+    fs::File,
+    io::{self, BufReader, BufWriter, Read, Seek, Write},
+    path::Path,
+};
+
+const MAGIC: &[u8] = b"\x93NUMPY";
+
+#[derive(thiserror::Error, Debug)]
+pub enum NpyError {
+    #[error(transparent)]
+    Io(#[from] io::Error),
+    #[error(transparent)]
+    Zip(#[from] zip::result::ZipError),
+    #[error("not a npy file")]
+    NotNpy,
+    #[error("malformed npy header")]
+    MalformedHeader,
+    #[error("unsupported dtype {0}")]
+    UnsupportedDType(String),
+    #[error("{0:?} tensors can't be written to npy files")]
+    UnsupportedElementType(ElementType),
+    #[error("the array's data doesn't match its shape")]
+    ShapeMismatch,
+}
+
+/// Returns the text following `'key':` in a header dict.
+fn header_value<'a>(header: &'a str, key: &str) -> Option<&'a str> {
+    let start = header.find(&format!("'{}'", key))? + key.len() + 2;
+    let rest = header[start..].trim_start().strip_prefix(':')?.trim_start();
+    let end = if rest.starts_with('(') {
+        rest.find(')')? + 1
+    } else if let Some(quoted) = rest.strip_prefix('\'') {
+        quoted.find('\'')? + 2
+    } else {
+        rest.find(&[',', '}'][..])?
+    };
+    Some(&rest[..end])
+}
+
+/// Parses a dtype like "<f4", returning the element type and whether it's big-endian.
+fn parse_descr(descr: &str) -> Option<(ElementType, bool)> {
+    let mut chars = descr.chars();
+    let big_endian = match chars.next()? {
+        '<' | '|' => false,
+        '>' => true,
+        '=' => cfg!(target_endian = "big"),
+        _ => return None,
+    };
+    let element_type = match chars.as_str() {
+        "b1" | "?" => ElementType::Bool,
+        "i1" => ElementType::Int8,
+        "i2" => ElementType::Int16,
+        "i4" => ElementType::Int32,
+        "i8" => ElementType::Int64,
+        "u1" => ElementType::UInt8,
+        "u2" => ElementType::UInt16,
+        "u4" => ElementType::UInt32,
+        "u8" => ElementType::UInt64,
+        "f2" => ElementType::Float16,
+        "f4" => ElementType::Float,
+        "f8" => ElementType::Double,
+        _ => return None,
+    };
+    Some((element_type, big_endian))
+}
+
+fn descr(element_type: ElementType) -> Option<&'static str> {
+    Some(match element_type {
+        ElementType::Bool => "|b1",
+        ElementType::Int8 => "|i1",
+        ElementType::Int16 => "<i2",
+        ElementType::Int32 => "<i4",
+        ElementType::Int64 => "<i8",
+        ElementType::UInt8 => "|u1",
+        ElementType::UInt16 => "<u2",
+        ElementType::UInt32 => "<u4",
+        ElementType::UInt64 => "<u8",
+        ElementType::Float16 => "<f2",
+        ElementType::Float => "<f4",
+        ElementType::Double => "<f8",
+        _ => return None,
+    })
+}
+
+/// Reorders the elements of a column-major array so that they're row-major.
+fn fortran_to_c_order(bytes: &[u8], element_size: usize, shape: &[usize]) -> Vec<u8> {
+    let mut ret = Vec::with_capacity(bytes.len());
+    let mut index = vec![0; shape.len()];
+    for _ in 0..shape.iter().product::<usize>() {
+        let mut offset = 0;
+        for (&i, &n) in index.iter().zip(shape).rev() {
+            offset = offset * n + i;
+        }
+        ret.extend_from_slice(&bytes[offset * element_size..(offset + 1) * element_size]);
+        // Advance the row-major index, last dimension first.
+        for (i, &n) in index.iter_mut().zip(shape).rev() {
+            *i += 1;
+            if *i < n {
+                break;
+            }
+            *i = 0;
+        }
+    }
+    ret
+}
+
+/// Reads a .npy file. Any byte order and either memory order is accepted.
+pub fn read<R: Read>(mut r: R) -> Result<OwnedTensor, NpyError> {
+    let mut preamble = [0u8; 8];
+    r.read_exact(&mut preamble)?;
+    if &preamble[..6] != MAGIC {
+        return Err(NpyError::NotNpy);
+    }
+    let header_len = if preamble[6] == 1 {
+        let mut len = [0u8; 2];
+        r.read_exact(&mut len)?;
+        u16::from_le_bytes(len) as usize
+    } else {
+        let mut len = [0u8; 4];
+        r.read_exact(&mut len)?;
+        u32::from_le_bytes(len) as usize
+    };
+    let mut header = vec![0u8; header_len];
+    r.read_exact(&mut header)?;
+    let header = String::from_utf8(header).map_err(|_| NpyError::MalformedHeader)?;
+
+    let descr = header_value(&header, "descr")
+        .ok_or(NpyError::MalformedHeader)?
+        .trim_matches('\'');
+    let (element_type, big_endian) =
+        parse_descr(descr).ok_or_else(|| NpyError::UnsupportedDType(descr.to_string()))?;
+    let fortran_order = match header_value(&header, "fortran_order") {
+        Some("False") => false,
+        Some("True") => true,
+        _ => return Err(NpyError::MalformedHeader),
+    };
+    let shape = header_value(&header, "shape").ok_or(NpyError::MalformedHeader)?;
+    let shape = shape
+        .trim_start_matches('(')
+        .trim_end_matches(')')
+        .split(',')
+        .map(|n| n.trim())
+        .filter(|n| !n.is_empty())
+        .map(|n| n.parse::<usize>().map_err(|_| NpyError::MalformedHeader))
+        .collect::<Result<Vec<_>, _>>()?;
+
+    let element_size = element_type
+        .size_in_bytes()
+        .expect("npy dtypes should all have a fixed size");
+    let size = shape
+        .iter()
+        .try_fold(element_size, |size, &n| size.checked_mul(n))
+        .ok_or(NpyError::MalformedHeader)?;
+    let mut bytes = Vec::new();
+    r.read_to_end(&mut bytes)?;
+    if bytes.len() != size {
+        return Err(NpyError::ShapeMismatch);
+    }
+    if big_endian {
+        bytes
+            .chunks_exact_mut(element_size)
+            .for_each(|element| element.reverse());
+    }
+    if fortran_order {
+        bytes = fortran_to_c_order(&bytes, element_size, &shape);
+    }
+    let data = TensorData::from_le_bytes(element_type, &bytes)
+        .expect("npy dtypes should all be supported by TensorData");
+    OwnedTensor::new(data, shape).map_err(|_| NpyError::ShapeMismatch)
+}
+
+pub fn read_path<P: AsRef<Path>>(path: P) -> Result<OwnedTensor, NpyError> {
+    read(BufReader::new(File::open(path)?))
+}
+
+/// Writes a tensor as a little-endian, row-major .npy file.
+pub fn write<W: Write>(mut w: W, tensor: &OwnedTensor) -> Result<(), NpyError> {
+    let descr = descr(tensor.element_type())
+        .ok_or_else(|| NpyError::UnsupportedElementType(tensor.element_type()))?;
+    let shape = match tensor.shape() {
+        [n] => format!("({},)", n),
+        shape => format!(
+            "({})",
+            shape
+                .iter()
+                .map(|n| n.to_string())
+                .collect::<Vec<_>>()
+                .join(", ")
+        ),
+    };
+    let mut header = format!(
+        "{{'descr': '{}', 'fortran_order': False, 'shape': {}, }}",
+        descr, shape
+    );
+    // The header is padded so that the data is 64-byte aligned.
+    let unpadded_len = MAGIC.len() + 4 + header.len() + 1;
+    header.push_str(&" ".repeat((64 - unpadded_len % 64) % 64));
+    header.push('\n');
+
+    w.write_all(MAGIC)?;
+    w.write_all(&[1, 0])?;
+    w.write_all(&(header.len() as u16).to_le_bytes())?;
+    w.write_all(header.as_bytes())?;
+    w.write_all(&tensor.data().to_le_bytes())?;
+    w.flush()?;
+    Ok(())
+}
+
+pub fn write_path<P: AsRef<Path>>(path: P, tensor: &OwnedTensor) -> Result<(), NpyError> {
+    write(BufWriter::new(File::create(path)?), tensor)
+}
+
+/// Writes a session output as a .npy file.
+pub fn write_output<W: Write>(w: W, output: &OutputTensor) -> Result<(), NpyError> {
+    let tensor = output
+        .to_owned_tensor()
+        .ok_or_else(|| NpyError::UnsupportedElementType(output.element_type()))?;
+    write(w, &tensor)
+}
+
+pub fn write_output_path<P: AsRef<Path>>(path: P, output: &OutputTensor) -> Result<(), NpyError> {
+    write_output(BufWriter::new(File::create(path)?), output)
+}
+
+/// Reads all of the arrays in a .npz file, as written by `np.savez` or `np.savez_compressed`.
+/// They're returned in the order they're stored, named without the ".npy" extension.
+pub fn read_npz<R: Read + Seek>(r: R) -> Result<Vec<(String, OwnedTensor)>, NpyError> {
+    let mut archive = zip::ZipArchive::new(r)?;
+    let mut ret = Vec::with_capacity(archive.len());
+    for i in 0..archive.len() {
+        let file = archive.by_index(i)?;
+        let name = file.name();
+        let name = name.strip_suffix(".npy").unwrap_or(name).to_string();
+        ret.push((name, read(file)?));
+    }
+    Ok(ret)
+}
+
+pub fn read_npz_path<P: AsRef<Path>>(path: P) -> Result<Vec<(String, OwnedTensor)>, NpyError> {
+    read_npz(BufReader::new(File::open(path)?))
+}
+
+/// Writes named tensors to an uncompressed .npz file, like `np.savez`.
+pub fn write_npz<'a, W: Write + Seek, I: IntoIterator<Item = (&'a str, &'a OwnedTensor)>>(
+    w: W,
+    tensors: I,
+) -> Result<(), NpyError> {
+    let mut archive = zip::ZipWriter::new(w);
+    let options = zip::write::FileOptions::default()
+        .compression_method(zip::CompressionMethod::Stored)
+        .large_file(true);
+    for (name, tensor) in tensors {
+        archive.start_file(format!("{}.npy", name), options)?;
+        write(&mut archive, tensor)?;
+    }
+    archive.finish()?.flush()?;
+    Ok(())
+}
+
+pub fn write_npz_path<'a, P: AsRef<Path>, I: IntoIterator<Item = (&'a str, &'a OwnedTensor)>>(
+    path: P,
+    tensors: I,
+) -> Result<(), NpyError> {
+    write_npz(BufWriter::new(File::create(path)?), tensors)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use half::f16;
+
+    #[test]
+    fn test_round_trip() {
+        let tensors = vec![
+            OwnedTensor::new(vec![1.5f32], vec![]).unwrap(),
+            OwnedTensor::new(vec![1.0f32, 2.0, 3.0], vec![3]).unwrap(),
+            OwnedTensor::new(vec![-1i64, 0, 1, 2, 3, 4], vec![2, 3]).unwrap(),
+            OwnedTensor::new(vec![true, false], vec![1, 2]).unwrap(),
+            OwnedTensor::new(vec![f16::from_f32(0.5); 4], vec![2, 2]).unwrap(),
+            OwnedTensor::new(vec![255u8, 0], vec![2, 1]).unwrap(),
+            OwnedTensor::new(Vec::<f64>::new(), vec![0, 4]).unwrap(),
+        ];
+        for tensor in &tensors {
+            let mut buf = Vec::new();
+            write(&mut buf, tensor).unwrap();
+            let data_len = tensor.data().to_le_bytes().len();
+            assert_eq!((buf.len() - data_len) % 64, 0);
+            assert_eq!(&read(buf.as_slice()).unwrap(), tensor);
+        }
+
+        let mut buf = io::Cursor::new(Vec::new());
+        write_npz(&mut buf, vec![("a", &tensors[1]), ("b/c", &tensors[2])]).unwrap();
+        buf.set_position(0);
+        assert_eq!(
+            read_npz(buf).unwrap(),
+            vec![
+                ("a".to_string(), tensors[1].clone()),
+                ("b/c".to_string(), tensors[2].clone()),
+            ]
+        );
+    }
+
+    fn npy(header: &str, data: &[u8]) -> Vec<u8> {
+        let header = format!("{:<117}\n", header);
+        let mut buf = b"\x93NUMPY\x01\x00".to_vec();
+        buf.extend(&(header.len() as u16).to_le_bytes());
+        buf.extend(header.as_bytes());
+        buf.extend(data);
+        buf
+    }
+
+    #[test]
+    fn test_read() {
+        // np.save(f, np.array([[1, 2]], dtype='<f4'))
+        let buf = npy(
+            "{'descr': '<f4', 'fortran_order': False, 'shape': (1, 2), }",
+            &[0, 0, 0x80, 0x3f, 0, 0, 0, 0x40],
+        );
+        assert_eq!(
+            read(buf.as_slice()).unwrap(),
+            OwnedTensor::new(vec![1.0f32, 2.0], vec![1, 2]).unwrap()
+        );
+
+        // np.save(f, np.array([1, 256], dtype='>i2'))
+        let buf = npy(
+            "{'descr': '>i2', 'fortran_order': False, 'shape': (2,), }",
+            &[0, 1, 1, 0],
+        );
+        assert_eq!(
+            read(buf.as_slice()).unwrap(),
+            OwnedTensor::new(vec![1i16, 256], vec![2]).unwrap()
+        );
+
+        // np.save(f, np.asfortranarray(np.array([[1, 2, 3], [4, 5, 6]], dtype='u1')))
+        let buf = npy(
+            "{'descr': '|u1', 'fortran_order': True, 'shape': (2, 3), }",
+            &[1, 4, 2, 5, 3, 6],
+        );
+        assert_eq!(
+            read(buf.as_slice()).unwrap(),
+            OwnedTensor::new(vec![1u8, 2, 3, 4, 5, 6], vec![2, 3]).unwrap()
+        );
+
+        let buf = npy(
+            "{'descr': '<c8', 'fortran_order': False, 'shape': (1,), }",
+            &[0; 8],
+        );
+        assert!(matches!(
+            read(buf.as_slice()),
+            Err(NpyError::UnsupportedDType(dtype)) if dtype == "<c8"
+        ));
+
+        let buf = npy(
+            "{'descr': '<f4', 'fortran_order': False, 'shape': (3,), }",
+            &[0, 0, 0x80, 0x3f],
+        );
+        assert!(matches!(read(buf.as_slice()), Err(NpyError::ShapeMismatch)));
+
+        let buf = npy(
+            &format!(
+                "{{'descr': '<f8', 'fortran_order': False, 'shape': ({}, {}), }}",
+                usize::MAX,
+                usize::MAX
+            ),
+            &[],
+        );
+        assert!(matches!(
+            read(buf.as_slice()),
+            Err(NpyError::MalformedHeader)
+        ));
+    }
+
+    #[test]
+    fn test_read_compressed_npz() {
+        let mut archive = zip::ZipWriter::new(io::Cursor::new(Vec::new()));
+        archive
+            .start_file(
+                "x.npy",
+                zip::write::FileOptions::default()
+                    .compression_method(zip::CompressionMethod::Deflated),
+            )
+            .unwrap();
+        let tensor = OwnedTensor::new(vec![7i32; 100], vec![10, 10]).unwrap();
+        write(&mut archive, &tensor).unwrap();
+        let mut buf = archive.finish().unwrap();
+        buf.set_position(0);
+        assert_eq!(read_npz(buf).unwrap(), vec![("x".to_string(), tensor)]);
+    }
+}
@@ -27,6 +27,22 @@ impl API {
         }
     }
 
+    pub fn version() -> String {
+        unsafe {
+            let get_version_string = (*sys::OrtGetApiBase())
+                .GetVersionString
+                .expect("GetVersionString should be available");
+            CStr::from_ptr(get_version_string())
+                .to_string_lossy()
+                .into_owned()
+        }
+    }
+
+    /// Wraps an api pointer handed to us by ORT, e.g. in a custom op callback.
+    pub unsafe fn from_raw(api: *const sys::OrtApi) -> API {
+        API(api)
+    }
+
     pub unsafe fn create_env(
         &self,
         default_logging_level: sys::OrtLoggingLevel,
@@ -96,6 +112,332 @@ impl API {
         Ok(ret)
     }
 
+    pub unsafe fn set_optimized_model_file_path(
+        &self,
+        options: *mut sys::OrtSessionOptions,
+        optimized_model_filepath: *const ::std::os::raw::c_char,
+    ) -> Result<(), Error> {
+        self.consume_status((*self.0)
+            .SetOptimizedModelFilePath
+            .expect("SetOptimizedModelFilePath should be available")(
+            options,
+            optimized_model_filepath,
+        ))
+    }
+
+    pub unsafe fn set_session_execution_mode(
+        &self,
+        options: *mut sys::OrtSessionOptions,
+        execution_mode: sys::ExecutionMode,
+    ) -> Result<(), Error> {
+        self.consume_status((*self.0)
+            .SetSessionExecutionMode
+            .expect("SetSessionExecutionMode should be available")(
+            options,
+            execution_mode,
+        ))
+    }
+
+    pub unsafe fn set_session_graph_optimization_level(
+        &self,
+        options: *mut sys::OrtSessionOptions,
+        graph_optimization_level: sys::GraphOptimizationLevel,
+    ) -> Result<(), Error> {
+        self.consume_status((*self.0)
+            .SetSessionGraphOptimizationLevel
+            .expect("SetSessionGraphOptimizationLevel should be available")(
+            options,
+            graph_optimization_level,
+        ))
+    }
+
+    pub unsafe fn add_session_config_entry(
+        &self,
+        options: *mut sys::OrtSessionOptions,
+        config_key: *const ::std::os::raw::c_char,
+        config_value: *const ::std::os::raw::c_char,
+    ) -> Result<(), Error> {
+        self.consume_status((*self.0)
+            .AddSessionConfigEntry
+            .expect("AddSessionConfigEntry should be available")(
+            options,
+            config_key,
+            config_value,
+        ))
+    }
+
+    pub unsafe fn enable_profiling(
+        &self,
+        options: *mut sys::OrtSessionOptions,
+        profile_file_prefix: *const ::std::os::raw::c_char,
+    ) -> Result<(), Error> {
+        self.consume_status((*self.0)
+            .EnableProfiling
+            .expect("EnableProfiling should be available")(
+            options, profile_file_prefix
+        ))
+    }
+
+    pub unsafe fn session_end_profiling(
+        &self,
+        sess: *mut sys::OrtSession,
+        allocator: *mut sys::OrtAllocator,
+    ) -> Result<*mut ::std::os::raw::c_char, Error> {
+        let mut ret = std::ptr::null_mut();
+        self.consume_status((*self.0)
+            .SessionEndProfiling
+            .expect("SessionEndProfiling should be available")(
+            sess, allocator, &mut ret,
+        ))?;
+        Ok(ret)
+    }
+
+    pub unsafe fn add_free_dimension_override(
+        &self,
+        options: *mut sys::OrtSessionOptions,
+        dim_denotation: *const ::std::os::raw::c_char,
+        dim_value: i64,
+    ) -> Result<(), Error> {
+        self.consume_status((*self.0)
+            .AddFreeDimensionOverride
+            .expect("AddFreeDimensionOverride should be available")(
+            options,
+            dim_denotation,
+            dim_value,
+        ))
+    }
+
+    pub unsafe fn add_free_dimension_override_by_name(
+        &self,
+        options: *mut sys::OrtSessionOptions,
+        dim_name: *const ::std::os::raw::c_char,
+        dim_value: i64,
+    ) -> Result<(), Error> {
+        self.consume_status((*self.0)
+            .AddFreeDimensionOverrideByName
+            .expect("AddFreeDimensionOverrideByName should be available")(
+            options, dim_name, dim_value,
+        ))
+    }
+
+    pub unsafe fn register_custom_ops_library(
+        &self,
+        options: *mut sys::OrtSessionOptions,
+        library_path: *const ::std::os::raw::c_char,
+        library_handle: &mut *mut ::std::os::raw::c_void,
+    ) -> Result<(), Error> {
+        self.consume_status((*self.0)
+            .RegisterCustomOpsLibrary
+            .expect("RegisterCustomOpsLibrary should be available")(
+            options,
+            library_path,
+            library_handle,
+        ))
+    }
+
+    pub unsafe fn create_custom_op_domain(
+        &self,
+        domain: *const ::std::os::raw::c_char,
+    ) -> Result<*mut sys::OrtCustomOpDomain, Error> {
+        let mut ret = std::ptr::null_mut();
+        self.consume_status((*self.0)
+            .CreateCustomOpDomain
+            .expect("CreateCustomOpDomain should be available")(
+            domain, &mut ret
+        ))?;
+        Ok(ret)
+    }
+
+    pub unsafe fn custom_op_domain_add(
+        &self,
+        custom_op_domain: *mut sys::OrtCustomOpDomain,
+        op: *mut sys::OrtCustomOp,
+    ) -> Result<(), Error> {
+        self.consume_status((*self.0)
+            .CustomOpDomain_Add
+            .expect("CustomOpDomain_Add should be available")(
+            custom_op_domain, op
+        ))
+    }
+
+    pub unsafe fn add_custom_op_domain(
+        &self,
+        options: *mut sys::OrtSessionOptions,
+        custom_op_domain: *mut sys::OrtCustomOpDomain,
+    ) -> Result<(), Error> {
+        self.consume_status((*self.0)
+            .AddCustomOpDomain
+            .expect("AddCustomOpDomain should be available")(
+            options, custom_op_domain
+        ))
+    }
+
+    pub unsafe fn release_custom_op_domain(&self, custom_op_domain: *mut sys::OrtCustomOpDomain) {
+        (*self.0)
+            .ReleaseCustomOpDomain
+            .expect("ReleaseCustomOpDomain should be available")(custom_op_domain)
+    }
+
+    pub unsafe fn kernel_info_get_attribute_float(
+        &self,
+        info: *const sys::OrtKernelInfo,
+        name: *const ::std::os::raw::c_char,
+    ) -> Result<f32, Error> {
+        let mut ret = 0.0;
+        self.consume_status((*self.0)
+            .KernelInfoGetAttribute_float
+            .expect("KernelInfoGetAttribute_float should be available")(
+            info, name, &mut ret,
+        ))?;
+        Ok(ret)
+    }
+
+    pub unsafe fn kernel_info_get_attribute_int64(
+        &self,
+        info: *const sys::OrtKernelInfo,
+        name: *const ::std::os::raw::c_char,
+    ) -> Result<i64, Error> {
+        let mut ret = 0;
+        self.consume_status((*self.0)
+            .KernelInfoGetAttribute_int64
+            .expect("KernelInfoGetAttribute_int64 should be available")(
+            info, name, &mut ret,
+        ))?;
+        Ok(ret)
+    }
+
+    /// If `out` is null, only the required size (including the null terminator) is written to
+    /// `size`.
+    pub unsafe fn kernel_info_get_attribute_string(
+        &self,
+        info: *const sys::OrtKernelInfo,
+        name: *const ::std::os::raw::c_char,
+        out: *mut ::std::os::raw::c_char,
+        size: &mut sys::size_t,
+    ) -> Result<(), Error> {
+        self.consume_status((*self.0)
+            .KernelInfoGetAttribute_string
+            .expect("KernelInfoGetAttribute_string should be available")(
+            info, name, out, size,
+        ))
+    }
+
+    pub unsafe fn kernel_context_get_input_count(
+        &self,
+        context: *const sys::OrtKernelContext,
+    ) -> Result<sys::size_t, Error> {
+        let mut ret = 0;
+        self.consume_status((*self.0)
+            .KernelContext_GetInputCount
+            .expect("KernelContext_GetInputCount should be available")(
+            context, &mut ret,
+        ))?;
+        Ok(ret)
+    }
+
+    pub unsafe fn kernel_context_get_output_count(
+        &self,
+        context: *const sys::OrtKernelContext,
+    ) -> Result<sys::size_t, Error> {
+        let mut ret = 0;
+        self.consume_status((*self.0)
+            .KernelContext_GetOutputCount
+            .expect("KernelContext_GetOutputCount should be available")(
+            context, &mut ret,
+        ))?;
+        Ok(ret)
+    }
+
+    pub unsafe fn kernel_context_get_input(
+        &self,
+        context: *const sys::OrtKernelContext,
+        index: sys::size_t,
+    ) -> Result<*const sys::OrtValue, Error> {
+        let mut ret = std::ptr::null();
+        self.consume_status((*self.0)
+            .KernelContext_GetInput
+            .expect("KernelContext_GetInput should be available")(
+            context, index, &mut ret,
+        ))?;
+        Ok(ret)
+    }
+
+    /// The returned value is owned by the context and must not be released.
+    pub unsafe fn kernel_context_get_output(
+        &self,
+        context: *mut sys::OrtKernelContext,
+        index: sys::size_t,
+        shape: &[i64],
+    ) -> Result<*mut sys::OrtValue, Error> {
+        let mut ret = std::ptr::null_mut();
+        self.consume_status((*self.0)
+            .KernelContext_GetOutput
+            .expect("KernelContext_GetOutput should be available")(
+            context,
+            index,
+            shape.as_ptr(),
+            shape.len() as _,
+            &mut ret,
+        ))?;
+        Ok(ret)
+    }
+
+    pub unsafe fn session_get_input_count(
+        &self,
+        sess: *const sys::OrtSession,
+    ) -> Result<sys::size_t, Error> {
+        let mut ret = 0;
+        self.consume_status((*self.0)
+            .SessionGetInputCount
+            .expect("SessionGetInputCount should be available")(
+            sess, &mut ret
+        ))?;
+        Ok(ret)
+    }
+
+    pub unsafe fn session_get_input_name(
+        &self,
+        sess: *const sys::OrtSession,
+        index: sys::size_t,
+        allocator: *mut sys::OrtAllocator,
+    ) -> Result<*mut ::std::os::raw::c_char, Error> {
+        let mut ret = std::ptr::null_mut();
+        self.consume_status((*self.0)
+            .SessionGetInputName
+            .expect("SessionGetInputName should be available")(
+            sess, index, allocator, &mut ret,
+        ))?;
+        Ok(ret)
+    }
+
+    pub unsafe fn session_get_input_type_info(
+        &self,
+        sess: *const sys::OrtSession,
+        index: sys::size_t,
+    ) -> Result<*mut sys::OrtTypeInfo, Error> {
+        let mut ret = std::ptr::null_mut();
+        self.consume_status((*self.0)
+            .SessionGetInputTypeInfo
+            .expect("SessionGetInputTypeInfo should be available")(
+            sess, index, &mut ret,
+        ))?;
+        Ok(ret)
+    }
+
+    pub unsafe fn session_get_output_type_info(
+        &self,
+        sess: *const sys::OrtSession,
+        index: sys::size_t,
+    ) -> Result<*mut sys::OrtTypeInfo, Error> {
+        let mut ret = std::ptr::null_mut();
+        self.consume_status((*self.0)
+            .SessionGetOutputTypeInfo
+            .expect("SessionGetOutputTypeInfo should be available")(
+            sess, index, &mut ret,
+        ))?;
+        Ok(ret)
+    }
+
     pub unsafe fn session_get_output_count(
         &self,
         sess: *const sys::OrtSession,
@@ -124,6 +466,181 @@ impl API {
         Ok(ret)
     }
 
+    pub unsafe fn session_get_overridable_initializer_count(
+        &self,
+        sess: *const sys::OrtSession,
+    ) -> Result<sys::size_t, Error> {
+        let mut ret = 0;
+        self.consume_status((*self.0)
+            .SessionGetOverridableInitializerCount
+            .expect("SessionGetOverridableInitializerCount should be available")(
+            sess, &mut ret,
+        ))?;
+        Ok(ret)
+    }
+
+    pub unsafe fn session_get_overridable_initializer_name(
+        &self,
+        sess: *const sys::OrtSession,
+        index: sys::size_t,
+        allocator: *mut sys::OrtAllocator,
+    ) -> Result<*mut ::std::os::raw::c_char, Error> {
+        let mut ret = std::ptr::null_mut();
+        self.consume_status((*self.0)
+            .SessionGetOverridableInitializerName
+            .expect("SessionGetOverridableInitializerName should be available")(
+            sess, index, allocator, &mut ret,
+        ))?;
+        Ok(ret)
+    }
+
+    pub unsafe fn session_get_overridable_initializer_type_info(
+        &self,
+        sess: *const sys::OrtSession,
+        index: sys::size_t,
+    ) -> Result<*mut sys::OrtTypeInfo, Error> {
+        let mut ret = std::ptr::null_mut();
+        self.consume_status((*self.0)
+            .SessionGetOverridableInitializerTypeInfo
+            .expect("SessionGetOverridableInitializerTypeInfo should be available")(
+            sess, index, &mut ret,
+        ))?;
+        Ok(ret)
+    }
+
+    pub unsafe fn session_get_model_metadata(
+        &self,
+        sess: *const sys::OrtSession,
+    ) -> Result<*mut sys::OrtModelMetadata, Error> {
+        let mut ret = std::ptr::null_mut();
+        self.consume_status((*self.0)
+            .SessionGetModelMetadata
+            .expect("SessionGetModelMetadata should be available")(
+            sess, &mut ret
+        ))?;
+        Ok(ret)
+    }
+
+    pub unsafe fn model_metadata_get_producer_name(
+        &self,
+        model_metadata: *const sys::OrtModelMetadata,
+        allocator: *mut sys::OrtAllocator,
+    ) -> Result<*mut ::std::os::raw::c_char, Error> {
+        let mut ret = std::ptr::null_mut();
+        self.consume_status((*self.0)
+            .ModelMetadataGetProducerName
+            .expect("ModelMetadataGetProducerName should be available")(
+            model_metadata,
+            allocator,
+            &mut ret,
+        ))?;
+        Ok(ret)
+    }
+
+    pub unsafe fn model_metadata_get_graph_name(
+        &self,
+        model_metadata: *const sys::OrtModelMetadata,
+        allocator: *mut sys::OrtAllocator,
+    ) -> Result<*mut ::std::os::raw::c_char, Error> {
+        let mut ret = std::ptr::null_mut();
+        self.consume_status((*self.0)
+            .ModelMetadataGetGraphName
+            .expect("ModelMetadataGetGraphName should be available")(
+            model_metadata,
+            allocator,
+            &mut ret,
+        ))?;
+        Ok(ret)
+    }
+
+    pub unsafe fn model_metadata_get_domain(
+        &self,
+        model_metadata: *const sys::OrtModelMetadata,
+        allocator: *mut sys::OrtAllocator,
+    ) -> Result<*mut ::std::os::raw::c_char, Error> {
+        let mut ret = std::ptr::null_mut();
+        self.consume_status((*self.0)
+            .ModelMetadataGetDomain
+            .expect("ModelMetadataGetDomain should be available")(
+            model_metadata,
+            allocator,
+            &mut ret,
+        ))?;
+        Ok(ret)
+    }
+
+    pub unsafe fn model_metadata_get_description(
+        &self,
+        model_metadata: *const sys::OrtModelMetadata,
+        allocator: *mut sys::OrtAllocator,
+    ) -> Result<*mut ::std::os::raw::c_char, Error> {
+        let mut ret = std::ptr::null_mut();
+        self.consume_status((*self.0)
+            .ModelMetadataGetDescription
+            .expect("ModelMetadataGetDescription should be available")(
+            model_metadata,
+            allocator,
+            &mut ret,
+        ))?;
+        Ok(ret)
+    }
+
+    pub unsafe fn model_metadata_lookup_custom_metadata_map(
+        &self,
+        model_metadata: *const sys::OrtModelMetadata,
+        allocator: *mut sys::OrtAllocator,
+        key: *const ::std::os::raw::c_char,
+    ) -> Result<*mut ::std::os::raw::c_char, Error> {
+        let mut ret = std::ptr::null_mut();
+        self.consume_status((*self.0)
+            .ModelMetadataLookupCustomMetadataMap
+            .expect("ModelMetadataLookupCustomMetadataMap should be available")(
+            model_metadata,
+            allocator,
+            key,
+            &mut ret,
+        ))?;
+        Ok(ret)
+    }
+
+    pub unsafe fn model_metadata_get_custom_metadata_map_keys(
+        &self,
+        model_metadata: *const sys::OrtModelMetadata,
+        allocator: *mut sys::OrtAllocator,
+    ) -> Result<(*mut *mut ::std::os::raw::c_char, i64), Error> {
+        let mut keys = std::ptr::null_mut();
+        let mut num_keys = 0;
+        self.consume_status((*self.0)
+            .ModelMetadataGetCustomMetadataMapKeys
+            .expect("ModelMetadataGetCustomMetadataMapKeys should be available")(
+            model_metadata,
+            allocator,
+            &mut keys,
+            &mut num_keys,
+        ))?;
+        Ok((keys, num_keys))
+    }
+
+    pub unsafe fn model_metadata_get_version(
+        &self,
+        model_metadata: *const sys::OrtModelMetadata,
+    ) -> Result<i64, Error> {
+        let mut ret = 0;
+        self.consume_status((*self.0)
+            .ModelMetadataGetVersion
+            .expect("ModelMetadataGetVersion should be available")(
+            model_metadata,
+            &mut ret,
+        ))?;
+        Ok(ret)
+    }
+
+    pub unsafe fn release_model_metadata(&self, input: *mut sys::OrtModelMetadata) {
+        (*self.0)
+            .ReleaseModelMetadata
+            .expect("ReleaseModelMetadata should be available")(input)
+    }
+
     pub unsafe fn run(
         &self,
         sess: *mut sys::OrtSession,
@@ -181,6 +698,25 @@ impl API {
         Ok(ret)
     }
 
+    pub unsafe fn create_tensor_as_ort_value(
+        &self,
+        allocator: *mut sys::OrtAllocator,
+        shape: &[i64],
+        type_: sys::ONNXTensorElementDataType,
+    ) -> Result<*mut sys::OrtValue, Error> {
+        let mut ret = std::ptr::null_mut();
+        self.consume_status((*self.0)
+            .CreateTensorAsOrtValue
+            .expect("CreateTensorAsOrtValue should be available")(
+            allocator,
+            shape.as_ptr(),
+            shape.len() as _,
+            type_,
+            &mut ret,
+        ))?;
+        Ok(ret)
+    }
+
     pub unsafe fn release_value(&self, value: *mut sys::OrtValue) {
         (*self.0)
             .ReleaseValue
@@ -273,6 +809,41 @@ impl API {
         ))
     }
 
+    pub unsafe fn get_symbolic_dimensions(
+        &self,
+        info: *const sys::OrtTensorTypeAndShapeInfo,
+        dim_params: &mut [*const ::std::os::raw::c_char],
+    ) -> Result<(), Error> {
+        self.consume_status((*self.0)
+            .GetSymbolicDimensions
+            .expect("GetSymbolicDimensions should be available")(
+            info,
+            dim_params.as_mut_ptr(),
+            dim_params.len() as _,
+        ))
+    }
+
+    /// Returns null if the type info doesn't describe a tensor. The returned pointer is owned by
+    /// the type info and must not be released.
+    pub unsafe fn cast_type_info_to_tensor_info(
+        &self,
+        type_info: *const sys::OrtTypeInfo,
+    ) -> Result<*const sys::OrtTensorTypeAndShapeInfo, Error> {
+        let mut ret = std::ptr::null();
+        self.consume_status((*self.0)
+            .CastTypeInfoToTensorInfo
+            .expect("CastTypeInfoToTensorInfo should be available")(
+            type_info, &mut ret
+        ))?;
+        Ok(ret)
+    }
+
+    pub unsafe fn release_type_info(&self, type_info: *mut sys::OrtTypeInfo) {
+        (*self.0)
+            .ReleaseTypeInfo
+            .expect("ReleaseTypeInfo should be available")(type_info)
+    }
+
     pub unsafe fn release_tensor_type_and_shape_info(
         &self,
         info: *mut sys::OrtTensorTypeAndShapeInfo,
@@ -0,0 +1,653 @@
+use super::{api::API, sys, DataType, ElementType, Error};
+use std::{
+    any::Any,
+    collections::HashMap,
+    ffi::{c_void, CStr, CString},
+    marker::PhantomData,
+    panic::{self, AssertUnwindSafe},
+    sync::{Arc, Mutex, MutexGuard},
+};
+
+/// An operator implemented in Rust, which can be registered with a session via
+/// `SessionBuilder::custom_op`.
+///
+/// ORT creates one kernel per node using the op, and may compute a kernel from multiple threads
+/// at once, including threads of its own pools when using `ExecutionMode::Parallel`.
+pub trait CustomOp: Send + Sync + 'static {
+    type Kernel: Kernel;
+
+    /// The op type, as it appears in the graph's nodes.
+    fn name(&self) -> &str;
+
+    fn input_types(&self) -> &[ElementType];
+
+    fn output_types(&self) -> &[ElementType];
+
+    fn create_kernel(&self, info: &KernelInfo) -> Result<Self::Kernel, Error>;
+}
+
+pub trait Kernel: Send + Sync + 'static {
+    /// Computes the outputs for a single node. If an error is returned, `Session::run` fails with
+    /// `SessionRunError::CustomOp`. ORT doesn't say which run a kernel is computed for, so if the
+    /// session is being run from several threads at once, all of those runs fail.
+    fn compute(&self, context: &mut KernelContext) -> Result<(), Error>;
+}
+
+/// Gives kernels access to the attributes of the node they were created for.
+pub struct KernelInfo<'a> {
+    api: API,
+    inner: *const sys::OrtKernelInfo,
+    _info: PhantomData<&'a ()>,
+}
+
+impl<'a> KernelInfo<'a> {
+    pub fn attribute_f32(&self, name: &str) -> Result<f32, Error> {
+        let name = c_string(name, "malformed attribute name")?;
+        unsafe {
+            self.api
+                .kernel_info_get_attribute_float(self.inner, name.as_ptr())
+        }
+    }
+
+    pub fn attribute_i64(&self, name: &str) -> Result<i64, Error> {
+        let name = c_string(name, "malformed attribute name")?;
+        unsafe {
+            self.api
+                .kernel_info_get_attribute_int64(self.inner, name.as_ptr())
+        }
+    }
+
+    pub fn attribute_string(&self, name: &str) -> Result<String, Error> {
+        let name = c_string(name, "malformed attribute name")?;
+        unsafe {
+            let mut size = 0;
+            self.api.kernel_info_get_attribute_string(
+                self.inner,
+                name.as_ptr(),
+                std::ptr::null_mut(),
+                &mut size,
+            )?;
+            let mut buf = vec![0u8; size as usize];
+            self.api.kernel_info_get_attribute_string(
+                self.inner,
+                name.as_ptr(),
+                buf.as_mut_ptr() as _,
+                &mut size,
+            )?;
+            Ok(CStr::from_bytes_until_nul(&buf)
+                .map(|s| s.to_string_lossy().into_owned())
+                .unwrap_or_default())
+        }
+    }
+}
+
+fn c_string(s: &str, message: &str) -> Result<CString, Error> {
+    CString::new(s).map_err(|_| Error {
+        code: sys::OrtErrorCode_ORT_INVALID_ARGUMENT,
+        message: message.to_string(),
+    })
+}
+
+/// Gives kernels access to a node's inputs and outputs during computation.
+pub struct KernelContext<'a> {
+    api: API,
+    inner: *mut sys::OrtKernelContext,
+    _context: PhantomData<&'a ()>,
+}
+
+impl<'a> KernelContext<'a> {
+    pub fn input_count(&self) -> Result<usize, Error> {
+        unsafe { Ok(self.api.kernel_context_get_input_count(self.inner)? as _) }
+    }
+
+    pub fn output_count(&self) -> Result<usize, Error> {
+        unsafe { Ok(self.api.kernel_context_get_output_count(self.inner)? as _) }
+    }
+
+    pub fn input(&self, index: usize) -> Result<KernelTensor<'a>, Error> {
+        unsafe {
+            let value = self.api.kernel_context_get_input(self.inner, index as _)?;
+            let (data_type, shape) = tensor_type_and_shape(self.api, value)?;
+            Ok(KernelTensor {
+                data_type,
+                data_ptr: self.api.get_tensor_mutable_data(value as _)?,
+                shape,
+                _context: PhantomData,
+            })
+        }
+    }
+
+    /// Allocates the output at the given index and returns its data for the kernel to fill in. The
+    /// element type must match the one declared by the op.
+    pub fn output<T: DataType>(
+        &mut self,
+        index: usize,
+        shape: &[usize],
+    ) -> Result<&mut [T], Error> {
+        let ort_shape: Vec<_> = shape.iter().map(|n| *n as i64).collect();
+        unsafe {
+            let value = self
+                .api
+                .kernel_context_get_output(self.inner, index as _, &ort_shape)?;
+            let (data_type, shape) = tensor_type_and_shape(self.api, value)?;
+            if data_type != T::tensor_element_data_type() {
+                return Err(Error {
+                    code: sys::OrtErrorCode_ORT_INVALID_ARGUMENT,
+                    message: format!(
+                        "output {} has element type {:?}",
+                        index,
+                        ElementType::from_sys(data_type)
+                    ),
+                });
+            }
+            Ok(std::slice::from_raw_parts_mut(
+                self.api.get_tensor_mutable_data(value)? as _,
+                shape.iter().product(),
+            ))
+        }
+    }
+}
+
+unsafe fn tensor_type_and_shape(
+    api: API,
+    value: *const sys::OrtValue,
+) -> Result<(sys::ONNXTensorElementDataType, Vec<usize>), Error> {
+    let info = scopeguard::guard(api.get_tensor_type_and_shape(value)?, |ptr| {
+        api.release_tensor_type_and_shape_info(ptr)
+    });
+    let data_type = api.get_tensor_element_type(*info)?;
+    let mut dims = vec![0; api.get_dimensions_count(*info)? as usize];
+    api.get_dimensions(*info, &mut dims)?;
+    Ok((data_type, dims.into_iter().map(|n| n as _).collect()))
+}
+
+/// One of a kernel's inputs.
+pub struct KernelTensor<'a> {
+    data_type: sys::ONNXTensorElementDataType,
+    data_ptr: *const c_void,
+    shape: Vec<usize>,
+    _context: PhantomData<&'a ()>,
+}
+
+impl<'a> KernelTensor<'a> {
+    pub fn element_type(&self) -> ElementType {
+        ElementType::from_sys(self.data_type)
+    }
+
+    pub fn shape(&self) -> &[usize] {
+        &self.shape
+    }
+
+    pub fn as_slice<T: DataType>(&self) -> Option<&'a [T]> {
+        if T::tensor_element_data_type() == self.data_type {
+            unsafe {
+                Some(std::slice::from_raw_parts(
+                    self.data_ptr as _,
+                    self.shape.iter().product(),
+                ))
+            }
+        } else {
+            None
+        }
+    }
+}
+
+/// Where kernels report errors, shared by every op registered with a session. ORT may compute
+/// kernels on its own threads and doesn't tell them which run they're part of, so an error is
+/// reported to every run that's in progress when it happens.
+#[derive(Default)]
+pub(super) struct RunErrors {
+    runs: Mutex<RunErrorState>,
+}
+
+#[derive(Default)]
+struct RunErrorState {
+    next_id: u64,
+    // The first error reported to each run in progress.
+    active: HashMap<u64, Option<String>>,
+}
+
+impl RunErrors {
+    fn lock(&self) -> MutexGuard<'_, RunErrorState> {
+        self.runs.lock().unwrap_or_else(|e| e.into_inner())
+    }
+
+    /// Starts collecting errors for a run, returning the id to pass to `end_run`.
+    pub fn begin_run(&self) -> u64 {
+        let mut runs = self.lock();
+        let id = runs.next_id;
+        runs.next_id += 1;
+        runs.active.insert(id, None);
+        id
+    }
+
+    /// Stops collecting errors for a run, returning the first one reported during it.
+    pub fn end_run(&self, id: u64) -> Option<String> {
+        self.lock().active.remove(&id).flatten()
+    }
+
+    /// Records a kernel's error. If several kernels fail during a run, the first error is kept.
+    fn set_error(&self, message: String) {
+        let mut runs = self.lock();
+        let concurrent = runs.active.len() > 1;
+        for error in runs.active.values_mut() {
+            error.get_or_insert_with(|| {
+                if concurrent {
+                    format!("a custom op failed during a concurrent run: {}", message)
+                } else {
+                    message.clone()
+                }
+            });
+        }
+    }
+}
+
+pub(super) type ErrorSlot = Arc<RunErrors>;
+
+fn panic_message(payload: Box<dyn Any + Send>) -> String {
+    if let Some(s) = payload.downcast_ref::<&str>() {
+        s.to_string()
+    } else if let Some(s) = payload.downcast_ref::<String>() {
+        s.clone()
+    } else {
+        "panic".to_string()
+    }
+}
+
+/// Type-erased CustomOp so that ops of different types can share a domain.
+pub(super) trait ErasedCustomOp: Send + Sync {
+    fn name(&self) -> &str;
+    fn input_types(&self) -> &[ElementType];
+    fn output_types(&self) -> &[ElementType];
+    fn create_kernel(&self, info: &KernelInfo) -> Result<Box<dyn Kernel>, Error>;
+}
+
+impl<T: CustomOp> ErasedCustomOp for T {
+    fn name(&self) -> &str {
+        CustomOp::name(self)
+    }
+
+    fn input_types(&self) -> &[ElementType] {
+        CustomOp::input_types(self)
+    }
+
+    fn output_types(&self) -> &[ElementType] {
+        CustomOp::output_types(self)
+    }
+
+    fn create_kernel(&self, info: &KernelInfo) -> Result<Box<dyn Kernel>, Error> {
+        Ok(Box::new(CustomOp::create_kernel(self, info)?))
+    }
+}
+
+/// The OrtCustomOp handed to ORT. ORT passes the OrtCustomOp pointer back to us in callbacks, so
+/// it must come first.
+#[repr(C)]
+struct RegisteredCustomOp {
+    base: sys::OrtCustomOp,
+    name: CString,
+    input_types: Vec<sys::ONNXTensorElementDataType>,
+    output_types: Vec<sys::ONNXTensorElementDataType>,
+    op: Arc<dyn ErasedCustomOp>,
+    error: ErrorSlot,
+}
+
+struct KernelState {
+    api: API,
+    kernel: Result<Box<dyn Kernel>, String>,
+    error: ErrorSlot,
+}
+
+unsafe extern "C" fn create_kernel(
+    op: *mut sys::OrtCustomOp,
+    api: *const sys::OrtApi,
+    info: *const sys::OrtKernelInfo,
+) -> *mut c_void {
+    let op = &*(op as *const RegisteredCustomOp);
+    let api = API::from_raw(api);
+    let info = KernelInfo {
+        api,
+        inner: info,
+        _info: PhantomData,
+    };
+    let kernel = match panic::catch_unwind(AssertUnwindSafe(|| op.op.create_kernel(&info))) {
+        Ok(Ok(kernel)) => Ok(kernel),
+        Ok(Err(e)) => Err(format!("unable to create kernel: {}", e)),
+        Err(payload) => Err(format!(
+            "kernel creation panicked: {}",
+            panic_message(payload)
+        )),
+    };
+    // We never return null. If kernel creation failed, the error is reported when the kernel is
+    // computed instead.
+    Box::into_raw(Box::new(KernelState {
+        api,
+        kernel,
+        error: op.error.clone(),
+    })) as _
+}
+
+unsafe extern "C" fn get_name(op: *mut sys::OrtCustomOp) -> *const ::std::os::raw::c_char {
+    let op = &*(op as *const RegisteredCustomOp);
+    op.name.as_ptr()
+}
+
+unsafe extern "C" fn get_execution_provider_type(
+    _op: *mut sys::OrtCustomOp,
+) -> *const ::std::os::raw::c_char {
+    std::ptr::null()
+}
+
+unsafe extern "C" fn get_input_type(
+    op: *mut sys::OrtCustomOp,
+    index: sys::size_t,
+) -> sys::ONNXTensorElementDataType {
+    let op = &*(op as *const RegisteredCustomOp);
+    op.input_types[index as usize]
+}
+
+unsafe extern "C" fn get_input_type_count(op: *mut sys::OrtCustomOp) -> sys::size_t {
+    let op = &*(op as *const RegisteredCustomOp);
+    op.input_types.len() as _
+}
+
+unsafe extern "C" fn get_output_type(
+    op: *mut sys::OrtCustomOp,
+    index: sys::size_t,
+) -> sys::ONNXTensorElementDataType {
+    let op = &*(op as *const RegisteredCustomOp);
+    op.output_types[index as usize]
+}
+
+unsafe extern "C" fn get_output_type_count(op: *mut sys::OrtCustomOp) -> sys::size_t {
+    let op = &*(op as *const RegisteredCustomOp);
+    op.output_types.len() as _
+}
+
+unsafe extern "C" fn kernel_compute(kernel: *mut c_void, context: *mut sys::OrtKernelContext) {
+    let state = &*(kernel as *const KernelState);
+    let kernel = match &state.kernel {
+        Ok(kernel) => kernel,
+        Err(e) => return state.error.set_error(e.clone()),
+    };
+    let mut context = KernelContext {
+        api: state.api,
+        inner: context,
+        _context: PhantomData,
+    };
+    match panic::catch_unwind(AssertUnwindSafe(|| kernel.compute(&mut context))) {
+        Ok(Ok(())) => {}
+        Ok(Err(e)) => state.error.set_error(e.to_string()),
+        Err(payload) => state
+            .error
+            .set_error(format!("kernel panicked: {}", panic_message(payload))),
+    }
+}
+
+unsafe extern "C" fn kernel_destroy(kernel: *mut c_void) {
+    drop(Box::from_raw(kernel as *mut KernelState));
+}
+
+/// Owns an OrtCustomOpDomain and the ops added to it. ORT requires both to outlive every session
+/// using them.
+pub(super) struct CustomOpDomain {
+    api: API,
+    inner: *mut sys::OrtCustomOpDomain,
+    // ORT holds on to pointers to the ops, so they're boxed to keep them from moving.
+    #[allow(clippy::vec_box)]
+    ops: Vec<Box<RegisteredCustomOp>>,
+}
+
+impl CustomOpDomain {
+    pub unsafe fn new(
+        api: API,
+        domain: &str,
+        ops: &[Arc<dyn ErasedCustomOp>],
+        error: ErrorSlot,
+    ) -> Result<Self, Error> {
+        let domain = c_string(domain, "malformed custom op domain")?;
+        let mut ret = Self {
+            api,
+            inner: api.create_custom_op_domain(domain.as_ptr())?,
+            ops: Vec::with_capacity(ops.len()),
+        };
+        for op in ops {
+            let mut op = Box::new(RegisteredCustomOp {
+                base: sys::OrtCustomOp {
+                    version: sys::ORT_API_VERSION,
+                    CreateKernel: Some(create_kernel),
+                    GetName: Some(get_name),
+                    GetExecutionProviderType: Some(get_execution_provider_type),
+                    GetInputType: Some(get_input_type),
+                    GetInputTypeCount: Some(get_input_type_count),
+                    GetOutputType: Some(get_output_type),
+                    GetOutputTypeCount: Some(get_output_type_count),
+                    KernelCompute: Some(kernel_compute),
+                    KernelDestroy: Some(kernel_destroy),
+                },
+                name: c_string(op.name(), "malformed custom op name")?,
+                input_types: op.input_types().iter().map(|t| t.to_sys()).collect(),
+                output_types: op.output_types().iter().map(|t| t.to_sys()).collect(),
+                op: op.clone(),
+                error: error.clone(),
+            });
+            api.custom_op_domain_add(ret.inner, &mut op.base)?;
+            ret.ops.push(op);
+        }
+        Ok(ret)
+    }
+
+    pub fn as_ptr(&self) -> *mut sys::OrtCustomOpDomain {
+        self.inner
+    }
+}
+
+impl Drop for CustomOpDomain {
+    fn drop(&mut self) {
+        unsafe { self.api.release_custom_op_domain(self.inner) }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::super::{Environment, ExecutionMode, SessionRunError};
+    use super::*;
+
+    struct Scale;
+
+    struct ScaleKernel {
+        factor: f32,
+    }
+
+    impl CustomOp for Scale {
+        type Kernel = ScaleKernel;
+
+        fn name(&self) -> &str {
+            "Scale"
+        }
+
+        fn input_types(&self) -> &[ElementType] {
+            &[ElementType::Float]
+        }
+
+        fn output_types(&self) -> &[ElementType] {
+            &[ElementType::Float]
+        }
+
+        fn create_kernel(&self, info: &KernelInfo) -> Result<ScaleKernel, Error> {
+            assert_eq!(info.attribute_string("label").unwrap(), "scaled");
+            assert!(info.attribute_i64("factor").is_err());
+            Ok(ScaleKernel {
+                factor: info.attribute_f32("factor")?,
+            })
+        }
+    }
+
+    impl Kernel for ScaleKernel {
+        fn compute(&self, context: &mut KernelContext) -> Result<(), Error> {
+            let input = context.input(0)?;
+            let x = input.as_slice::<f32>().unwrap();
+            let y = context.output::<f32>(0, input.shape())?;
+            for (y, x) in y.iter_mut().zip(x) {
+                *y = x * self.factor;
+            }
+            Ok(())
+        }
+    }
+
+    // custom_op.onnx contains a single "Scale" node from the "infer.test" domain. It was produced
+    // via:
+    //
+    // ```
+    // import onnx
+    // from onnx import helper, TensorProto
+    //
+    // graph = helper.make_graph(
+    //     [helper.make_node('Scale', ['x'], ['y'], domain='infer.test', factor=2.5, label='scaled')],
+    //     'custom_op',
+    //     [helper.make_tensor_value_info('x', TensorProto.FLOAT, ['n'])],
+    //     [helper.make_tensor_value_info('y', TensorProto.FLOAT, ['n'])],
+    // )
+    // model = helper.make_model(graph, ir_version=7, opset_imports=[
+    //     helper.make_opsetid('', 12),
+    //     helper.make_opsetid('infer.test', 1),
+    // ])
+    // onnx.save(model, 'custom_op.onnx')
+    // ```
+    #[test]
+    fn test_custom_op() {
+        let env = Environment::new().unwrap();
+        let sess = env
+            .session_builder()
+            .custom_op("infer.test", Scale)
+            .build("src/onnx/testdata/custom_op.onnx")
+            .unwrap();
+        let x = env.new_tensor(&[1.0f32, 2.0, 4.0], &[3]).unwrap();
+        let outputs = sess.run(&[("x", x)]).unwrap();
+        assert_eq!(outputs.len(), 1);
+        let (name, output) = &outputs[0];
+        assert_eq!(name, &"y");
+        assert_eq!(output.shape(), &[3]);
+        assert_eq!(output.as_slice::<f32>().unwrap(), &[2.5, 5.0, 10.0]);
+    }
+
+    struct Failing;
+
+    struct FailingKernel;
+
+    impl CustomOp for Failing {
+        type Kernel = FailingKernel;
+
+        fn name(&self) -> &str {
+            "Scale"
+        }
+
+        fn input_types(&self) -> &[ElementType] {
+            &[ElementType::Float]
+        }
+
+        fn output_types(&self) -> &[ElementType] {
+            &[ElementType::Float]
+        }
+
+        fn create_kernel(&self, _info: &KernelInfo) -> Result<FailingKernel, Error> {
+            Ok(FailingKernel)
+        }
+    }
+
+    impl Kernel for FailingKernel {
+        fn compute(&self, context: &mut KernelContext) -> Result<(), Error> {
+            // Requesting the wrong element type fails without touching the data.
+            context.output::<i64>(0, &[3])?;
+            Ok(())
+        }
+    }
+
+    #[test]
+    fn test_custom_op_error() {
+        let env = Environment::new().unwrap();
+        for mode in &[ExecutionMode::Sequential, ExecutionMode::Parallel] {
+            let sess = env
+                .session_builder()
+                .execution_mode(*mode)
+                .custom_op("infer.test", Failing)
+                .build("src/onnx/testdata/custom_op.onnx")
+                .unwrap();
+            let x = env.new_tensor(&[1.0f32, 2.0, 4.0], &[3]).unwrap();
+            match sess.run(&[("x", x)]) {
+                Err(SessionRunError::CustomOp(message)) => {
+                    assert!(message.contains("element type"), "{}", message)
+                }
+                _ => panic!("expected a custom op error with {:?}", mode),
+            };
+        }
+
+        // Errors belong to the session whose kernel failed, even when other sessions run on the
+        // same thread.
+        let failing = env
+            .session_builder()
+            .execution_mode(ExecutionMode::Parallel)
+            .custom_op("infer.test", Failing)
+            .build("src/onnx/testdata/custom_op.onnx")
+            .unwrap();
+        let scaling = env
+            .session_builder()
+            .execution_mode(ExecutionMode::Parallel)
+            .custom_op("infer.test", Scale)
+            .build("src/onnx/testdata/custom_op.onnx")
+            .unwrap();
+        let x = env.new_tensor(&[1.0f32, 2.0, 4.0], &[3]).unwrap();
+        assert!(failing.run(&[("x", &x)]).is_err());
+        let outputs = scaling.run(&[("x", &x)]).unwrap();
+        assert_eq!(outputs[0].1.as_slice::<f32>().unwrap(), &[2.5, 5.0, 10.0]);
+    }
+
+    #[test]
+    fn test_run_errors() {
+        let errors = RunErrors::default();
+        let run = errors.begin_run();
+        errors.set_error("first".to_string());
+        errors.set_error("second".to_string());
+        assert_eq!(errors.end_run(run), Some("first".to_string()));
+
+        // An error can't be traced to one of several concurrent runs, so all of them get it.
+        let (a, b) = (errors.begin_run(), errors.begin_run());
+        errors.set_error("failed".to_string());
+        let c = errors.begin_run();
+        let message = "a custom op failed during a concurrent run: failed".to_string();
+        assert_eq!(errors.end_run(a), Some(message.clone()));
+        assert_eq!(errors.end_run(b), Some(message));
+        assert_eq!(errors.end_run(c), None);
+
+        // Errors outside of any run are dropped.
+        errors.set_error("dropped".to_string());
+        let run = errors.begin_run();
+        assert_eq!(errors.end_run(run), None);
+    }
+
+    #[test]
+    fn test_concurrent_runs() {
+        let env = Environment::new().unwrap();
+        let sess = env
+            .session_builder()
+            .custom_op("infer.test", Scale)
+            .build("src/onnx/testdata/custom_op.onnx")
+            .unwrap();
+        std::thread::scope(|s| {
+            for i in 0..4 {
+                let (env, sess) = (&env, &sess);
+                s.spawn(move || {
+                    let data = [i as f32; 3];
+                    let x = env.new_tensor(&data, &[3]).unwrap();
+                    for _ in 0..10 {
+                        let outputs = sess.run(&[("x", &x)]).unwrap();
+                        assert_eq!(
+                            outputs[0].1.as_slice::<f32>().unwrap(),
+                            &[i as f32 * 2.5; 3]
+                        );
+                    }
+                });
+            }
+        });
+    }
+}
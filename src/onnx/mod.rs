@@ -1,10 +1,19 @@
 use scopeguard::ScopeGuard;
+use sha2::{Digest, Sha256};
 use std::{
+    borrow::Borrow,
     ffi::{c_void, CStr, CString},
     fmt,
     marker::PhantomData,
-    os::{raw::c_uint, unix::ffi::OsStrExt},
-    path::Path,
+    os::{
+        raw::{c_int, c_uint},
+        unix::ffi::OsStrExt,
+    },
+    path::{Path, PathBuf},
+    sync::{
+        atomic::{AtomicUsize, Ordering},
+        Arc,
+    },
 };
 
 macro_rules! c_str {
@@ -16,6 +25,15 @@ macro_rules! c_str {
 mod api;
 use api::*;
 
+mod custom_op;
+pub use custom_op::{CustomOp, Kernel, KernelContext, KernelInfo, KernelTensor};
+use custom_op::{CustomOpDomain, ErasedCustomOp, RunErrors};
+
+pub mod profiling;
+
+pub mod proto;
+pub use proto::{Dimension, ElementType, TensorInfo};
+
 mod sys;
 
 #[derive(Debug)]
@@ -69,10 +87,84 @@ impl From<NewAPIError> for NewEnvironmentError {
 pub enum NewSessionError {
     #[error("malformed model path")]
     MalformedModelPath,
+    #[error("malformed dimension name")]
+    MalformedDimensionName,
+    #[error("dimension {name} can't be overridden with negative value {value}")]
+    NegativeDimensionOverride { name: String, value: i64 },
+    #[error("malformed library path")]
+    MalformedLibraryPath,
+    #[error("malformed profile file prefix")]
+    MalformedProfilePrefix,
+    #[error("malformed optimized model path")]
+    MalformedOptimizedModelPath,
+    #[error("malformed config entry")]
+    MalformedConfigEntry,
+    #[error(
+        "model uses opset {model_opset}, but onnxruntime {runtime_version} supports up to opset {max_opset}: {source}"
+    )]
+    UnsupportedOpset {
+        model_opset: i64,
+        runtime_version: String,
+        max_opset: i64,
+        source: Error,
+    },
+    #[error("optimized model cache error: {0}")]
+    OptimizedModelCache(#[source] std::io::Error),
     #[error(transparent)]
     Other(#[from] Error),
 }
 
+/// Returns the version of the loaded ORT library, e.g. "1.5.2".
+pub fn ort_version() -> String {
+    API::version()
+}
+
+/// Returns the newest version of the default operator set supported by the loaded ORT library, if
+/// known.
+pub fn max_supported_opset() -> Option<i64> {
+    let version = ort_version();
+    let mut parts = version.split('.').map(|part| part.parse::<u32>());
+    let (major, minor) = match (parts.next(), parts.next()) {
+        (Some(Ok(major)), Some(Ok(minor))) => (major, minor),
+        _ => return None,
+    };
+    Some(match (major, minor) {
+        (1, 0..=4) => return None,
+        (1, 5) => 12,
+        (1, 6..=7) => 13,
+        (1, 8) => 14,
+        (1, 9..=10) => 15,
+        (1, 11) => 16,
+        (1, 12..=13) => 17,
+        (1, 14) => 18,
+        (1, 15..=16) => 19,
+        (1, 17) => 20,
+        (1, 18..=20) => 21,
+        _ => return None,
+    })
+}
+
+/// If the model failed to load because it uses a newer opset than the runtime supports, returns an
+/// error saying so. Otherwise, returns the original error.
+fn unsupported_opset_error(model_path: &Path, err: Error) -> NewSessionError {
+    let model_opset = match proto::Model::from_path(model_path)
+        .ok()
+        .and_then(|model| model.opset_version())
+    {
+        Some(opset) => opset,
+        None => return err.into(),
+    };
+    match max_supported_opset() {
+        Some(max_opset) if model_opset > max_opset => NewSessionError::UnsupportedOpset {
+            model_opset,
+            runtime_version: ort_version(),
+            max_opset,
+            source: err,
+        },
+        _ => err.into(),
+    }
+}
+
 impl Environment {
     pub fn new() -> Result<Environment, NewEnvironmentError> {
         let api = API::new()?;
@@ -94,56 +186,91 @@ impl Environment {
         }
     }
 
+    /// Creates a session with the default options. Use `session_builder` to customize them.
     pub fn new_session<P: AsRef<Path>>(
         &self,
         model_path: P,
     ) -> Result<Session<'_>, NewSessionError> {
-        let model_path = CString::new(model_path.as_ref().as_os_str().as_bytes())
-            .map_err(|_| NewSessionError::MalformedModelPath)?;
-        unsafe {
-            let allocator = self.api.get_allocator_with_default_options()?;
-            let sess_options = scopeguard::guard(self.api.create_session_options()?, |ptr| {
-                self.api.release_session_options(ptr)
-            });
+        self.session_builder().build(model_path)
+    }
 
-            #[cfg(feature = "cuda")]
-            sys::OrtSessionOptionsAppendExecutionProvider_CUDA(*sess_options, 0);
+    pub fn session_builder(&self) -> SessionBuilder<'_> {
+        SessionBuilder {
+            env: self,
+            free_dimension_overrides: Vec::new(),
+            free_dimension_overrides_by_name: Vec::new(),
+            custom_ops_libraries: Vec::new(),
+            custom_ops: Vec::new(),
+            execution_mode: ExecutionMode::Sequential,
+            profile_file_prefix: None,
+            optimized_model_file_path: None,
+            optimized_model_cache_dir: None,
+            config_entries: Vec::new(),
+        }
+    }
 
-            let sess = scopeguard::guard(
-                self.api
-                    .create_session(self.inner, model_path.as_ptr(), *sess_options)?,
-                |ptr| self.api.release_session(ptr),
-            );
+    /// Converts an ONNX model into the ORT format, which is smaller and faster to load. Use
+    /// `SessionBuilder::convert_to_ort_format` if the model needs custom ops.
+    pub fn convert_to_ort_format<P: AsRef<Path>, Q: AsRef<Path>>(
+        &self,
+        onnx_path: P,
+        ort_path: Q,
+    ) -> Result<(), NewSessionError> {
+        self.session_builder()
+            .convert_to_ort_format(onnx_path, ort_path)
+    }
 
-            let output_c_names = (0..self.api.session_get_output_count(*sess)?)
-                .map(|i| -> Result<CString, Error> {
-                    let raw = self.api.session_get_output_name(*sess, i, allocator)?;
-                    let ret = CStr::from_ptr(raw).to_owned();
-                    self.api.allocator_free(allocator, raw as _)?;
-                    Ok(ret)
-                })
-                .collect::<Result<Vec<_>, Error>>()?;
-            let output_c_name_ptrs = output_c_names.iter().map(|s| s.as_ptr()).collect();
+    /// Copies a string allocated by ORT and frees the original.
+    unsafe fn take_allocated_string(
+        &self,
+        allocator: *mut sys::OrtAllocator,
+        ptr: *mut ::std::os::raw::c_char,
+    ) -> Result<String, Error> {
+        let ret = CStr::from_ptr(ptr).to_string_lossy().into_owned();
+        self.api.allocator_free(allocator, ptr as _)?;
+        Ok(ret)
+    }
 
-            let output_names = output_c_names
-                .iter()
-                .map(|s| {
-                    s.as_c_str()
-                        .to_str()
-                        .expect("ort shouldn't return invalid names")
-                        .to_string()
-                })
-                .collect();
+    unsafe fn tensor_info(
+        &self,
+        name: String,
+        type_info: *mut sys::OrtTypeInfo,
+    ) -> Result<TensorInfo, Error> {
+        let type_info = scopeguard::guard(type_info, |ptr| self.api.release_type_info(ptr));
+        let info = self.api.cast_type_info_to_tensor_info(*type_info)?;
+        if info.is_null() {
+            return Ok(TensorInfo {
+                name,
+                element_type: ElementType::Undefined,
+                dimensions: Vec::new(),
+            });
+        }
 
-            Ok(Session {
-                api: self.api,
-                inner: ScopeGuard::into_inner(sess),
-                output_names,
-                _output_c_names: output_c_names,
-                output_c_name_ptrs,
-                env: self,
+        let element_type = ElementType::from_sys(self.api.get_tensor_element_type(info)?);
+        let count = self.api.get_dimensions_count(info)? as usize;
+        let mut dims = vec![0; count];
+        self.api.get_dimensions(info, &mut dims)?;
+        let mut symbolic_dims = vec![std::ptr::null(); count];
+        self.api.get_symbolic_dimensions(info, &mut symbolic_dims)?;
+        let dimensions = dims
+            .into_iter()
+            .zip(symbolic_dims)
+            .map(|(n, symbol)| {
+                if n >= 0 {
+                    Dimension::Fixed(n as _)
+                } else if symbol.is_null() || *symbol == 0 {
+                    Dimension::Unknown
+                } else {
+                    Dimension::Symbolic(CStr::from_ptr(symbol).to_string_lossy().into_owned())
+                }
             })
-        }
+            .collect();
+
+        Ok(TensorInfo {
+            name,
+            element_type,
+            dimensions,
+        })
     }
 
     pub fn new_tensor<'t, 'a: 't, 'data: 't, T: DataType>(
@@ -166,31 +293,623 @@ impl Environment {
                 data_type,
                 data_ptr: data.as_ptr() as _,
                 shape: shape.to_vec(),
+                mutable: false,
+                env_and_data: PhantomData,
+            })
+        }
+    }
+
+    /// Creates a tensor whose data is allocated by ORT rather than borrowed. Its elements start
+    /// out zeroed, and can be filled in place via `Tensor::as_mut_slice`.
+    pub fn allocate_tensor<T: DataType>(&self, shape: &[usize]) -> Result<Tensor<'_>, Error> {
+        let ort_shape: Vec<_> = shape.iter().map(|n| *n as i64).collect();
+        let data_type = T::tensor_element_data_type();
+        unsafe {
+            let allocator = self.api.get_allocator_with_default_options()?;
+            let inner = scopeguard::guard(
+                self.api
+                    .create_tensor_as_ort_value(allocator, &ort_shape, data_type)?,
+                |value| self.api.release_value(value),
+            );
+            let data_ptr = self.api.get_tensor_mutable_data(*inner)?;
+            let len = shape.iter().product::<usize>();
+            std::ptr::write_bytes(data_ptr as *mut T, 0, len);
+            Ok(Tensor {
+                api: self.api,
+                inner: ScopeGuard::into_inner(inner),
+                data_type,
+                data_ptr,
+                shape: shape.to_vec(),
+                mutable: true,
                 env_and_data: PhantomData,
             })
         }
     }
 }
 
+// ORT's environment and CPU memory info are safe to use from multiple threads, and we don't hold
+// any other state.
+unsafe impl Send for Environment {}
+unsafe impl Sync for Environment {}
+
 impl Drop for Environment {
     fn drop(&mut self) {
         unsafe { self.api.release_env(self.inner) }
     }
 }
 
+/// Configures sessions before they're created. Builders are obtained via
+/// `Environment::session_builder` and can be used to create any number of sessions.
+pub struct SessionBuilder<'env> {
+    env: &'env Environment,
+    free_dimension_overrides: Vec<(String, i64)>,
+    free_dimension_overrides_by_name: Vec<(String, i64)>,
+    custom_ops_libraries: Vec<PathBuf>,
+    custom_ops: Vec<(String, Arc<dyn ErasedCustomOp>)>,
+    execution_mode: ExecutionMode,
+    profile_file_prefix: Option<PathBuf>,
+    optimized_model_file_path: Option<PathBuf>,
+    optimized_model_cache_dir: Option<PathBuf>,
+    config_entries: Vec<(String, String)>,
+}
+
+/// Session config keys, mirroring onnxruntime_session_options_config_keys.h.
+pub mod config_keys {
+    /// "1" disables prepacking.
+    pub const DISABLE_PREPACKING: &str = "session.disable_prepacking";
+    /// "1" makes the session use the allocators registered in the environment.
+    pub const USE_ENV_ALLOCATORS: &str = "session.use_env_allocators";
+    /// "ORT" loads an ORT format model.
+    pub const LOAD_MODEL_FORMAT: &str = "session.load_model_format";
+    /// "ORT" saves the optimized model in the ORT format.
+    pub const SAVE_MODEL_FORMAT: &str = "session.save_model_format";
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum ModelFormat {
+    ONNX,
+    ORT,
+}
+
+impl ModelFormat {
+    fn config_value(self) -> &'static str {
+        match self {
+            Self::ONNX => "ONNX",
+            Self::ORT => "ORT",
+        }
+    }
+}
+
+/// Controls how the nodes of a graph are scheduled.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum ExecutionMode {
+    /// Nodes are computed one at a time, on the thread that invoked the run.
+    Sequential,
+    /// Independent nodes are computed concurrently on ORT's inter-op thread pool, which can be
+    /// faster for graphs with many branches.
+    Parallel,
+}
+
+impl ExecutionMode {
+    fn to_sys(self) -> sys::ExecutionMode {
+        match self {
+            Self::Sequential => sys::ExecutionMode_ORT_SEQUENTIAL,
+            Self::Parallel => sys::ExecutionMode_ORT_PARALLEL,
+        }
+    }
+}
+
+impl<'env> SessionBuilder<'env> {
+    /// Pins symbolic dimensions carrying the given denotation (e.g. "DATA_BATCH") to a fixed
+    /// value, which enables optimizations such as memory planning. Unlike overrides by name, these
+    /// aren't reflected in the session's reported inputs and outputs, because ORT doesn't expose
+    /// dimension denotations.
+    pub fn free_dimension_override<S: Into<String>>(mut self, denotation: S, value: i64) -> Self {
+        self.free_dimension_overrides
+            .push((denotation.into(), value));
+        self
+    }
+
+    /// Pins symbolic dimensions with the given name (e.g. "batch") to a fixed value. The pinned
+    /// values are reflected in the session's reported inputs and outputs.
+    pub fn free_dimension_override_by_name<S: Into<String>>(mut self, name: S, value: i64) -> Self {
+        self.free_dimension_overrides_by_name
+            .push((name.into(), value));
+        self
+    }
+
+    /// Loads a shared library that registers custom ops via a `RegisterCustomOps` function, such
+    /// as onnxruntime-extensions. The library stays loaded for as long as the session does.
+    pub fn custom_ops_library<P: AsRef<Path>>(mut self, path: P) -> Self {
+        self.custom_ops_libraries.push(path.as_ref().to_path_buf());
+        self
+    }
+
+    /// Registers an op implemented in Rust under the given domain. Runs stay concurrent, but if
+    /// an op fails while the session is being run from several threads, every one of those runs
+    /// fails with `SessionRunError::CustomOp`, since the failure can't be traced to one of them.
+    pub fn custom_op<D: Into<String>, O: CustomOp>(mut self, domain: D, op: O) -> Self {
+        self.custom_ops.push((domain.into(), Arc::new(op)));
+        self
+    }
+
+    /// Sets how the graph's nodes are scheduled. The default is `ExecutionMode::Sequential`.
+    pub fn execution_mode(mut self, mode: ExecutionMode) -> Self {
+        self.execution_mode = mode;
+        self
+    }
+
+    /// Enables profiling. ORT writes a JSON trace to a file starting with the given prefix, whose
+    /// path is returned by `Session::end_profiling`. The trace can be read with
+    /// `profiling::Profile`.
+    pub fn enable_profiling<P: AsRef<Path>>(mut self, profile_file_prefix: P) -> Self {
+        self.profile_file_prefix = Some(profile_file_prefix.as_ref().to_path_buf());
+        self
+    }
+
+    /// Sets a session config entry. See `config_keys` for the supported keys.
+    pub fn config_entry<K: Into<String>, V: Into<String>>(mut self, key: K, value: V) -> Self {
+        self.config_entries.push((key.into(), value.into()));
+        self
+    }
+
+    /// Sets the format of the model being loaded. By default, it's inferred from the model's
+    /// extension or content.
+    pub fn load_model_format(self, format: ModelFormat) -> Self {
+        self.config_entry(config_keys::LOAD_MODEL_FORMAT, format.config_value())
+    }
+
+    /// Sets the format of the model written to `optimized_model_file_path`. By default, it's
+    /// inferred from the path's extension.
+    pub fn save_model_format(self, format: ModelFormat) -> Self {
+        self.config_entry(config_keys::SAVE_MODEL_FORMAT, format.config_value())
+    }
+
+    /// Converts an ONNX model into the ORT format using this builder's options.
+    pub fn convert_to_ort_format<P: AsRef<Path>, Q: AsRef<Path>>(
+        &self,
+        onnx_path: P,
+        ort_path: Q,
+    ) -> Result<(), NewSessionError> {
+        let builder = SessionBuilder {
+            env: self.env,
+            free_dimension_overrides: self.free_dimension_overrides.clone(),
+            free_dimension_overrides_by_name: self.free_dimension_overrides_by_name.clone(),
+            custom_ops_libraries: self.custom_ops_libraries.clone(),
+            custom_ops: self.custom_ops.clone(),
+            execution_mode: self.execution_mode,
+            profile_file_prefix: None,
+            optimized_model_file_path: None,
+            optimized_model_cache_dir: None,
+            config_entries: self.config_entries.clone(),
+        }
+        .save_model_format(ModelFormat::ORT);
+        builder.build_session(onnx_path.as_ref(), Some(ort_path.as_ref()), false)?;
+        Ok(())
+    }
+
+    /// Writes the model to the given path after ORT has applied its graph optimizations.
+    pub fn optimized_model_file_path<P: AsRef<Path>>(mut self, path: P) -> Self {
+        self.optimized_model_file_path = Some(path.as_ref().to_path_buf());
+        self
+    }
+
+    /// Caches graph-optimized models in the given directory. The first time a model is loaded, the
+    /// optimized model is written to the cache, and later loads use it instead of optimizing the
+    /// model again. Entries are keyed on the model's content, the ORT version, and the session
+    /// options, so changing any of these results in a new entry. If an entry fails to load but the
+    /// original model loads, the entry is replaced.
+    ///
+    /// The optimized models may be specific to the hardware they were created on, so the cache
+    /// directory shouldn't be shared between machines. When a cache directory is set,
+    /// `optimized_model_file_path` is ignored.
+    pub fn optimized_model_cache_dir<P: AsRef<Path>>(mut self, dir: P) -> Self {
+        self.optimized_model_cache_dir = Some(dir.as_ref().to_path_buf());
+        self
+    }
+
+    pub fn build<P: AsRef<Path>>(&self, model_path: P) -> Result<Session<'env>, NewSessionError> {
+        let model_path = model_path.as_ref();
+        let cache_dir = match &self.optimized_model_cache_dir {
+            Some(dir) => dir,
+            None => {
+                return self.build_session(
+                    model_path,
+                    self.optimized_model_file_path.as_deref(),
+                    false,
+                )
+            }
+        };
+
+        let model = std::fs::read(model_path).map_err(NewSessionError::OptimizedModelCache)?;
+        let key = self.optimized_model_cache_key(&model);
+        let cached_path = cache_dir.join(format!("{}.onnx", key));
+        // If the entry fails to load, it may be unusable, e.g. because it was truncated, or the
+        // failure may have nothing to do with it, e.g. a missing custom ops library. Either way we
+        // load the original model, and the entry is only replaced if that succeeds.
+        if cached_path.exists() {
+            if let Ok(sess) = self.build_session(&cached_path, None, true) {
+                return Ok(sess);
+            }
+        }
+
+        // ORT writes the optimized model while creating the session, so we have it write to a
+        // temporary file and move it into place once it's complete. That way concurrent loads
+        // never see partially written entries.
+        std::fs::create_dir_all(cache_dir).map_err(NewSessionError::OptimizedModelCache)?;
+        static NEXT_TEMP_ID: AtomicUsize = AtomicUsize::new(0);
+        let temp_path = cache_dir.join(format!(
+            ".{}.{}.{}.tmp",
+            key,
+            std::process::id(),
+            NEXT_TEMP_ID.fetch_add(1, Ordering::Relaxed)
+        ));
+        let _temp_file = scopeguard::guard(&temp_path, |path| {
+            let _ = std::fs::remove_file(path);
+        });
+        let sess = self.build_session(model_path, Some(&temp_path), false)?;
+        std::fs::rename(&temp_path, &cached_path).map_err(NewSessionError::OptimizedModelCache)?;
+        Ok(sess)
+    }
+
+    fn optimized_model_cache_key(&self, model: &[u8]) -> String {
+        let mut hasher = Sha256::new();
+        let mut write = |bytes: &[u8]| {
+            hasher.update((bytes.len() as u64).to_le_bytes());
+            hasher.update(bytes);
+        };
+        write(model);
+        write(API::version().as_bytes());
+        write(&[cfg!(feature = "cuda") as u8]);
+        for overrides in &[
+            &self.free_dimension_overrides,
+            &self.free_dimension_overrides_by_name,
+        ] {
+            write(&(overrides.len() as u64).to_le_bytes());
+            for (name, value) in overrides.iter() {
+                write(name.as_bytes());
+                write(&value.to_le_bytes());
+            }
+        }
+        write(&(self.custom_ops_libraries.len() as u64).to_le_bytes());
+        for path in &self.custom_ops_libraries {
+            write(path.as_os_str().as_bytes());
+        }
+        write(&(self.custom_ops.len() as u64).to_le_bytes());
+        for (domain, op) in &self.custom_ops {
+            write(domain.as_bytes());
+            write(op.name().as_bytes());
+        }
+        write(&(self.config_entries.len() as u64).to_le_bytes());
+        for (key, value) in &self.config_entries {
+            write(key.as_bytes());
+            write(value.as_bytes());
+        }
+        format!("{:x}", hasher.finalize())
+    }
+
+    fn build_session(
+        &self,
+        model_path: &Path,
+        optimized_model_file_path: Option<&Path>,
+        disable_optimizations: bool,
+    ) -> Result<Session<'env>, NewSessionError> {
+        let env = self.env;
+        let model_c_path = CString::new(model_path.as_os_str().as_bytes())
+            .map_err(|_| NewSessionError::MalformedModelPath)?;
+        unsafe {
+            let allocator = env.api.get_allocator_with_default_options()?;
+            let sess_options = scopeguard::guard(env.api.create_session_options()?, |ptr| {
+                env.api.release_session_options(ptr)
+            });
+
+            #[cfg(feature = "cuda")]
+            sys::OrtSessionOptionsAppendExecutionProvider_CUDA(*sess_options, 0);
+
+            env.api
+                .set_session_execution_mode(*sess_options, self.execution_mode.to_sys())?;
+
+            if let Some(prefix) = &self.profile_file_prefix {
+                let prefix = CString::new(prefix.as_os_str().as_bytes())
+                    .map_err(|_| NewSessionError::MalformedProfilePrefix)?;
+                env.api.enable_profiling(*sess_options, prefix.as_ptr())?;
+            }
+
+            if let Some(path) = optimized_model_file_path {
+                let path = CString::new(path.as_os_str().as_bytes())
+                    .map_err(|_| NewSessionError::MalformedOptimizedModelPath)?;
+                env.api
+                    .set_optimized_model_file_path(*sess_options, path.as_ptr())?;
+            }
+            for (key, value) in &self.config_entries {
+                let key = CString::new(key.as_bytes())
+                    .map_err(|_| NewSessionError::MalformedConfigEntry)?;
+                let value = CString::new(value.as_bytes())
+                    .map_err(|_| NewSessionError::MalformedConfigEntry)?;
+                env.api
+                    .add_session_config_entry(*sess_options, key.as_ptr(), value.as_ptr())?;
+            }
+            if disable_optimizations {
+                env.api.set_session_graph_optimization_level(
+                    *sess_options,
+                    sys::GraphOptimizationLevel_ORT_DISABLE_ALL,
+                )?;
+            }
+
+            for (name, value) in self
+                .free_dimension_overrides
+                .iter()
+                .chain(&self.free_dimension_overrides_by_name)
+            {
+                if *value < 0 {
+                    return Err(NewSessionError::NegativeDimensionOverride {
+                        name: name.clone(),
+                        value: *value,
+                    });
+                }
+            }
+            for (denotation, value) in &self.free_dimension_overrides {
+                let denotation = CString::new(denotation.as_bytes())
+                    .map_err(|_| NewSessionError::MalformedDimensionName)?;
+                env.api
+                    .add_free_dimension_override(*sess_options, denotation.as_ptr(), *value)?;
+            }
+            for (name, value) in &self.free_dimension_overrides_by_name {
+                let name = CString::new(name.as_bytes())
+                    .map_err(|_| NewSessionError::MalformedDimensionName)?;
+                env.api.add_free_dimension_override_by_name(
+                    *sess_options,
+                    name.as_ptr(),
+                    *value,
+                )?;
+            }
+
+            let custom_ops_libraries = self
+                .custom_ops_libraries
+                .iter()
+                .map(|path| {
+                    let path = CString::new(path.as_os_str().as_bytes())
+                        .map_err(|_| NewSessionError::MalformedLibraryPath)?;
+                    let mut library = CustomOpsLibrary(std::ptr::null_mut());
+                    env.api.register_custom_ops_library(
+                        *sess_options,
+                        path.as_ptr(),
+                        &mut library.0,
+                    )?;
+                    Ok(library)
+                })
+                .collect::<Result<Vec<_>, NewSessionError>>()?;
+
+            let mut domains: Vec<(&str, Vec<Arc<dyn ErasedCustomOp>>)> = Vec::new();
+            for (domain, op) in &self.custom_ops {
+                match domains.iter_mut().find(|(name, _)| name == domain) {
+                    Some((_, ops)) => ops.push(op.clone()),
+                    None => domains.push((domain, vec![op.clone()])),
+                }
+            }
+            let custom_op_errors = Arc::new(RunErrors::default());
+            let custom_op_domains = domains
+                .into_iter()
+                .map(|(domain, ops)| {
+                    let domain =
+                        CustomOpDomain::new(env.api, domain, &ops, custom_op_errors.clone())?;
+                    env.api
+                        .add_custom_op_domain(*sess_options, domain.as_ptr())?;
+                    Ok(domain)
+                })
+                .collect::<Result<Vec<_>, Error>>()?;
+
+            let sess = scopeguard::guard(
+                env.api
+                    .create_session(env.inner, model_c_path.as_ptr(), *sess_options)
+                    .map_err(|e| unsupported_opset_error(model_path, e))?,
+                |ptr| env.api.release_session(ptr),
+            );
+
+            let mut inputs = (0..env.api.session_get_input_count(*sess)?)
+                .map(|i| {
+                    let name = env.take_allocated_string(
+                        allocator,
+                        env.api.session_get_input_name(*sess, i, allocator)?,
+                    )?;
+                    env.tensor_info(name, env.api.session_get_input_type_info(*sess, i)?)
+                })
+                .collect::<Result<Vec<_>, Error>>()?;
+            let mut outputs = (0..env.api.session_get_output_count(*sess)?)
+                .map(|i| {
+                    let name = env.take_allocated_string(
+                        allocator,
+                        env.api.session_get_output_name(*sess, i, allocator)?,
+                    )?;
+                    env.tensor_info(name, env.api.session_get_output_type_info(*sess, i)?)
+                })
+                .collect::<Result<Vec<_>, Error>>()?;
+            let mut overridable_initializers =
+                (0..env.api.session_get_overridable_initializer_count(*sess)?)
+                    .map(|i| {
+                        let name = env.take_allocated_string(
+                            allocator,
+                            env.api
+                                .session_get_overridable_initializer_name(*sess, i, allocator)?,
+                        )?;
+                        env.tensor_info(
+                            name,
+                            env.api
+                                .session_get_overridable_initializer_type_info(*sess, i)?,
+                        )
+                    })
+                    .collect::<Result<Vec<_>, Error>>()?;
+
+            // Depending on the graph, ORT may still report overridden dimensions as symbolic, so
+            // we apply the overrides ourselves too.
+            for info in inputs
+                .iter_mut()
+                .chain(outputs.iter_mut())
+                .chain(overridable_initializers.iter_mut())
+            {
+                for dim in &mut info.dimensions {
+                    if let Dimension::Symbolic(symbol) = dim {
+                        if let Some((_, value)) = self
+                            .free_dimension_overrides_by_name
+                            .iter()
+                            .rev()
+                            .find(|(name, _)| name == symbol)
+                        {
+                            *dim = Dimension::Fixed(*value as _);
+                        }
+                    }
+                }
+            }
+
+            let output_c_names = outputs
+                .iter()
+                .map(|info| {
+                    CString::new(info.name.as_bytes()).expect("ort shouldn't return invalid names")
+                })
+                .collect::<Vec<_>>();
+            let output_c_name_ptrs = output_c_names.iter().map(|s| s.as_ptr()).collect();
+
+            Ok(Session {
+                api: env.api,
+                inner: ScopeGuard::into_inner(sess),
+                inputs,
+                outputs,
+                overridable_initializers,
+                _output_c_names: output_c_names,
+                output_c_name_ptrs,
+                env,
+                _custom_ops_libraries: custom_ops_libraries,
+                _custom_op_domains: custom_op_domains,
+                custom_op_errors,
+            })
+        }
+    }
+}
+
+extern "C" {
+    fn dlclose(handle: *mut c_void) -> c_int;
+}
+
+/// A library loaded by ORT via `RegisterCustomOpsLibrary`. ORT leaves it up to us to close it once
+/// every session using it has been released.
+struct CustomOpsLibrary(*mut c_void);
+
+impl Drop for CustomOpsLibrary {
+    fn drop(&mut self) {
+        if !self.0.is_null() {
+            unsafe { dlclose(self.0) };
+        }
+    }
+}
+
+impl ElementType {
+    fn to_sys(self) -> sys::ONNXTensorElementDataType {
+        match self {
+            Self::Undefined => {
+                sys::ONNXTensorElementDataType_ONNX_TENSOR_ELEMENT_DATA_TYPE_UNDEFINED
+            }
+            Self::Float => sys::ONNXTensorElementDataType_ONNX_TENSOR_ELEMENT_DATA_TYPE_FLOAT,
+            Self::UInt8 => sys::ONNXTensorElementDataType_ONNX_TENSOR_ELEMENT_DATA_TYPE_UINT8,
+            Self::Int8 => sys::ONNXTensorElementDataType_ONNX_TENSOR_ELEMENT_DATA_TYPE_INT8,
+            Self::UInt16 => sys::ONNXTensorElementDataType_ONNX_TENSOR_ELEMENT_DATA_TYPE_UINT16,
+            Self::Int16 => sys::ONNXTensorElementDataType_ONNX_TENSOR_ELEMENT_DATA_TYPE_INT16,
+            Self::Int32 => sys::ONNXTensorElementDataType_ONNX_TENSOR_ELEMENT_DATA_TYPE_INT32,
+            Self::Int64 => sys::ONNXTensorElementDataType_ONNX_TENSOR_ELEMENT_DATA_TYPE_INT64,
+            Self::String => sys::ONNXTensorElementDataType_ONNX_TENSOR_ELEMENT_DATA_TYPE_STRING,
+            Self::Bool => sys::ONNXTensorElementDataType_ONNX_TENSOR_ELEMENT_DATA_TYPE_BOOL,
+            Self::Float16 => sys::ONNXTensorElementDataType_ONNX_TENSOR_ELEMENT_DATA_TYPE_FLOAT16,
+            Self::Double => sys::ONNXTensorElementDataType_ONNX_TENSOR_ELEMENT_DATA_TYPE_DOUBLE,
+            Self::UInt32 => sys::ONNXTensorElementDataType_ONNX_TENSOR_ELEMENT_DATA_TYPE_UINT32,
+            Self::UInt64 => sys::ONNXTensorElementDataType_ONNX_TENSOR_ELEMENT_DATA_TYPE_UINT64,
+            Self::Complex64 => {
+                sys::ONNXTensorElementDataType_ONNX_TENSOR_ELEMENT_DATA_TYPE_COMPLEX64
+            }
+            Self::Complex128 => {
+                sys::ONNXTensorElementDataType_ONNX_TENSOR_ELEMENT_DATA_TYPE_COMPLEX128
+            }
+            Self::BFloat16 => sys::ONNXTensorElementDataType_ONNX_TENSOR_ELEMENT_DATA_TYPE_BFLOAT16,
+        }
+    }
+
+    fn from_sys(data_type: sys::ONNXTensorElementDataType) -> Self {
+        match data_type {
+            sys::ONNXTensorElementDataType_ONNX_TENSOR_ELEMENT_DATA_TYPE_FLOAT => Self::Float,
+            sys::ONNXTensorElementDataType_ONNX_TENSOR_ELEMENT_DATA_TYPE_UINT8 => Self::UInt8,
+            sys::ONNXTensorElementDataType_ONNX_TENSOR_ELEMENT_DATA_TYPE_INT8 => Self::Int8,
+            sys::ONNXTensorElementDataType_ONNX_TENSOR_ELEMENT_DATA_TYPE_UINT16 => Self::UInt16,
+            sys::ONNXTensorElementDataType_ONNX_TENSOR_ELEMENT_DATA_TYPE_INT16 => Self::Int16,
+            sys::ONNXTensorElementDataType_ONNX_TENSOR_ELEMENT_DATA_TYPE_INT32 => Self::Int32,
+            sys::ONNXTensorElementDataType_ONNX_TENSOR_ELEMENT_DATA_TYPE_INT64 => Self::Int64,
+            sys::ONNXTensorElementDataType_ONNX_TENSOR_ELEMENT_DATA_TYPE_STRING => Self::String,
+            sys::ONNXTensorElementDataType_ONNX_TENSOR_ELEMENT_DATA_TYPE_BOOL => Self::Bool,
+            sys::ONNXTensorElementDataType_ONNX_TENSOR_ELEMENT_DATA_TYPE_FLOAT16 => Self::Float16,
+            sys::ONNXTensorElementDataType_ONNX_TENSOR_ELEMENT_DATA_TYPE_DOUBLE => Self::Double,
+            sys::ONNXTensorElementDataType_ONNX_TENSOR_ELEMENT_DATA_TYPE_UINT32 => Self::UInt32,
+            sys::ONNXTensorElementDataType_ONNX_TENSOR_ELEMENT_DATA_TYPE_UINT64 => Self::UInt64,
+            sys::ONNXTensorElementDataType_ONNX_TENSOR_ELEMENT_DATA_TYPE_COMPLEX64 => {
+                Self::Complex64
+            }
+            sys::ONNXTensorElementDataType_ONNX_TENSOR_ELEMENT_DATA_TYPE_COMPLEX128 => {
+                Self::Complex128
+            }
+            sys::ONNXTensorElementDataType_ONNX_TENSOR_ELEMENT_DATA_TYPE_BFLOAT16 => Self::BFloat16,
+            _ => Self::Undefined,
+        }
+    }
+}
+
+/// The metadata embedded in a model.
+#[derive(Clone, Debug, PartialEq)]
+pub struct ModelMetadata {
+    pub producer_name: String,
+    pub graph_name: String,
+    pub domain: String,
+    pub description: String,
+    pub version: i64,
+    /// The model's `metadata_props`.
+    pub custom: Vec<(String, String)>,
+}
+
 pub struct Session<'env> {
     api: API,
     inner: *mut sys::OrtSession,
-    output_names: Vec<String>,
+    inputs: Vec<TensorInfo>,
+    outputs: Vec<TensorInfo>,
+    overridable_initializers: Vec<TensorInfo>,
     _output_c_names: Vec<CString>,
     output_c_name_ptrs: Vec<*const ::std::os::raw::c_char>,
     env: &'env Environment,
+    // Fields are dropped after Drop::drop, so these are closed only once the session is released.
+    _custom_ops_libraries: Vec<CustomOpsLibrary>,
+    _custom_op_domains: Vec<CustomOpDomain>,
+    // Errors from this session's Rust custom ops. Kernels may be computed on ORT's thread pools, so
+    // they report to the session rather than to the thread that invoked the run.
+    custom_op_errors: Arc<RunErrors>,
 }
 
+// ORT allows a session to be run from multiple threads at once, and the rest of our state doesn't
+// change once the session is built.
+unsafe impl Send for Session<'_> {}
+unsafe impl Sync for Session<'_> {}
+
 #[derive(thiserror::Error, Debug)]
 pub enum SessionRunError {
     #[error("malformed input name")]
     MalformedInputName,
+    #[error("custom op error: {0}")]
+    CustomOp(String),
+    #[error("initializer {name} has element type {expected:?}, but the override has {actual:?}")]
+    InitializerTypeMismatch {
+        name: String,
+        expected: ElementType,
+        actual: ElementType,
+    },
+    #[error("initializer {name} has shape {expected:?}, but the override has {actual:?}")]
+    InitializerShapeMismatch {
+        name: String,
+        expected: Vec<Dimension>,
+        actual: Vec<usize>,
+    },
     #[error(transparent)]
     Other(#[from] Error),
 }
@@ -200,10 +919,130 @@ impl<'env> Session<'env> {
         self.env
     }
 
-    pub fn run(
+    pub fn inputs(&self) -> &[TensorInfo] {
+        &self.inputs
+    }
+
+    pub fn outputs(&self) -> &[TensorInfo] {
+        &self.outputs
+    }
+
+    /// Returns the initializers that can be overridden at run time by passing a tensor with the
+    /// same name to `run`.
+    pub fn overridable_initializers(&self) -> &[TensorInfo] {
+        &self.overridable_initializers
+    }
+
+    pub fn metadata(&self) -> Result<ModelMetadata, Error> {
+        unsafe {
+            let env = self.env;
+            let allocator = self.api.get_allocator_with_default_options()?;
+            let metadata =
+                scopeguard::guard(self.api.session_get_model_metadata(self.inner)?, |ptr| {
+                    self.api.release_model_metadata(ptr)
+                });
+            let (keys, num_keys) = self
+                .api
+                .model_metadata_get_custom_metadata_map_keys(*metadata, allocator)?;
+            let keys = if keys.is_null() {
+                Vec::new()
+            } else {
+                let key_ptrs = std::slice::from_raw_parts(keys, num_keys as _).to_vec();
+                self.api.allocator_free(allocator, keys as _)?;
+                key_ptrs
+                    .into_iter()
+                    .map(|key| env.take_allocated_string(allocator, key))
+                    .collect::<Result<Vec<_>, Error>>()?
+            };
+            let custom = keys
+                .into_iter()
+                .map(|key| {
+                    let c_key =
+                        CString::new(key.as_bytes()).expect("ort shouldn't return invalid keys");
+                    let value = env.take_allocated_string(
+                        allocator,
+                        self.api.model_metadata_lookup_custom_metadata_map(
+                            *metadata,
+                            allocator,
+                            c_key.as_ptr(),
+                        )?,
+                    )?;
+                    Ok((key, value))
+                })
+                .collect::<Result<Vec<_>, Error>>()?;
+            Ok(ModelMetadata {
+                producer_name: env.take_allocated_string(
+                    allocator,
+                    self.api
+                        .model_metadata_get_producer_name(*metadata, allocator)?,
+                )?,
+                graph_name: env.take_allocated_string(
+                    allocator,
+                    self.api
+                        .model_metadata_get_graph_name(*metadata, allocator)?,
+                )?,
+                domain: env.take_allocated_string(
+                    allocator,
+                    self.api.model_metadata_get_domain(*metadata, allocator)?,
+                )?,
+                description: env.take_allocated_string(
+                    allocator,
+                    self.api
+                        .model_metadata_get_description(*metadata, allocator)?,
+                )?,
+                version: self.api.model_metadata_get_version(*metadata)?,
+                custom,
+            })
+        }
+    }
+
+    /// Stops profiling and returns the path of the trace file. Only valid for sessions built with
+    /// `SessionBuilder::enable_profiling`.
+    pub fn end_profiling(&mut self) -> Result<PathBuf, Error> {
+        unsafe {
+            let allocator = self.api.get_allocator_with_default_options()?;
+            let path = self.env.take_allocated_string(
+                allocator,
+                self.api.session_end_profiling(self.inner, allocator)?,
+            )?;
+            Ok(PathBuf::from(path))
+        }
+    }
+
+    /// Runs the model. Besides the model's inputs, `inputs` may contain overrides for any of the
+    /// session's overridable initializers, which must match their declared type and shape. Inputs
+    /// may be given as tensors or references to tensors.
+    ///
+    /// The session may be run from several threads at once. A Rust custom op's error fails every
+    /// run in progress when it happens, as described on `SessionBuilder::custom_op`.
+    pub fn run<'t, T: Borrow<Tensor<'t>>>(
         &self,
-        inputs: &[(&str, Tensor)],
+        inputs: &[(&str, T)],
     ) -> Result<Vec<(&str, Tensor<'env>)>, SessionRunError> {
+        for (name, input) in inputs {
+            let input = input.borrow();
+            if let Some(info) = self
+                .overridable_initializers
+                .iter()
+                .find(|info| info.name == *name)
+            {
+                if input.element_type() != info.element_type {
+                    return Err(SessionRunError::InitializerTypeMismatch {
+                        name: info.name.clone(),
+                        expected: info.element_type,
+                        actual: input.element_type(),
+                    });
+                }
+                if !info.accepts_shape(input.shape()) {
+                    return Err(SessionRunError::InitializerShapeMismatch {
+                        name: info.name.clone(),
+                        expected: info.dimensions.clone(),
+                        actual: input.shape().to_vec(),
+                    });
+                }
+            }
+        }
+
         let input_names: Vec<CString> = inputs
             .iter()
             .map(|(name, _)| {
@@ -216,8 +1055,13 @@ impl<'env> Session<'env> {
             .collect();
         let input_ptrs: Vec<_> = inputs
             .iter()
-            .map(|(_, input)| input.inner as *const sys::OrtValue)
+            .map(|(_, input)| input.borrow().inner as *const sys::OrtValue)
             .collect();
+        let custom_op_run = if self._custom_op_domains.is_empty() {
+            None
+        } else {
+            Some(self.custom_op_errors.begin_run())
+        };
         unsafe {
             let outputs = self.api.run(
                 self.inner,
@@ -225,11 +1069,18 @@ impl<'env> Session<'env> {
                 &input_name_ptrs,
                 &input_ptrs,
                 &self.output_c_name_ptrs,
-            )?;
-            let outputs: Vec<_> = outputs
-                .into_iter()
-                .map(|ptr| scopeguard::guard(ptr, |ptr| self.api.release_value(ptr)))
-                .collect();
+            );
+            let outputs = outputs.map(|outputs| {
+                outputs
+                    .into_iter()
+                    .map(|ptr| scopeguard::guard(ptr, |ptr| self.api.release_value(ptr)))
+                    .collect::<Vec<_>>()
+            });
+            // A failed custom op may also cause ORT to fail, but the op's error is more useful.
+            if let Some(message) = custom_op_run.and_then(|id| self.custom_op_errors.end_run(id)) {
+                return Err(SessionRunError::CustomOp(message));
+            }
+            let outputs = outputs?;
             Ok(outputs
                 .into_iter()
                 .enumerate()
@@ -245,13 +1096,14 @@ impl<'env> Session<'env> {
                     self.api.get_dimensions(*info, &mut dims)?;
                     let shape = dims.into_iter().map(|n| n as _).collect();
                     Ok((
-                        &self.output_names[i],
+                        &self.outputs[i].name,
                         Tensor {
                             api: self.api,
                             inner: ScopeGuard::into_inner(value),
                             data_type,
                             data_ptr: data_ptr,
                             shape: shape,
+                            mutable: true,
                             env_and_data: PhantomData,
                         },
                     ))
@@ -271,10 +1123,32 @@ pub trait DataType {
     fn tensor_element_data_type() -> sys::ONNXTensorElementDataType;
 }
 
-impl DataType for f32 {
-    fn tensor_element_data_type() -> sys::ONNXTensorElementDataType {
-        sys::ONNXTensorElementDataType_ONNX_TENSOR_ELEMENT_DATA_TYPE_FLOAT
-    }
+macro_rules! impl_data_type {
+    ($($t:ty => $data_type:ident,)*) => {
+        $(
+            impl DataType for $t {
+                fn tensor_element_data_type() -> sys::ONNXTensorElementDataType {
+                    sys::$data_type
+                }
+            }
+        )*
+    };
+}
+
+impl_data_type! {
+    f32 => ONNXTensorElementDataType_ONNX_TENSOR_ELEMENT_DATA_TYPE_FLOAT,
+    u8 => ONNXTensorElementDataType_ONNX_TENSOR_ELEMENT_DATA_TYPE_UINT8,
+    i8 => ONNXTensorElementDataType_ONNX_TENSOR_ELEMENT_DATA_TYPE_INT8,
+    u16 => ONNXTensorElementDataType_ONNX_TENSOR_ELEMENT_DATA_TYPE_UINT16,
+    i16 => ONNXTensorElementDataType_ONNX_TENSOR_ELEMENT_DATA_TYPE_INT16,
+    i32 => ONNXTensorElementDataType_ONNX_TENSOR_ELEMENT_DATA_TYPE_INT32,
+    i64 => ONNXTensorElementDataType_ONNX_TENSOR_ELEMENT_DATA_TYPE_INT64,
+    bool => ONNXTensorElementDataType_ONNX_TENSOR_ELEMENT_DATA_TYPE_BOOL,
+    f64 => ONNXTensorElementDataType_ONNX_TENSOR_ELEMENT_DATA_TYPE_DOUBLE,
+    u32 => ONNXTensorElementDataType_ONNX_TENSOR_ELEMENT_DATA_TYPE_UINT32,
+    u64 => ONNXTensorElementDataType_ONNX_TENSOR_ELEMENT_DATA_TYPE_UINT64,
+    half::f16 => ONNXTensorElementDataType_ONNX_TENSOR_ELEMENT_DATA_TYPE_FLOAT16,
+    half::bf16 => ONNXTensorElementDataType_ONNX_TENSOR_ELEMENT_DATA_TYPE_BFLOAT16,
 }
 
 pub struct Tensor<'a> {
@@ -283,15 +1157,32 @@ pub struct Tensor<'a> {
     data_type: sys::ONNXTensorElementDataType,
     data_ptr: *const c_void,
     shape: Vec<usize>,
+    /// Whether the data is owned by ORT. Tensors that borrow the caller's data can't be written.
+    mutable: bool,
     env_and_data: PhantomData<&'a ()>,
 }
 
 impl<'a> Tensor<'a> {
+    pub fn element_type(&self) -> ElementType {
+        ElementType::from_sys(self.data_type)
+    }
+
     pub fn shape(&self) -> &[usize] {
         &self.shape
     }
 
-    pub fn as_slice<T: DataType>(&self) -> Option<&'a [T]> {
+    /// Returns the data if its elements are of type `T`. The slice borrows the tensor, so it can't
+    /// outlive it or be held across writes via `as_mut_slice`:
+    ///
+    /// ```compile_fail
+    /// # fn f(env: &infer::onnx::Environment) {
+    /// let mut tensor = env.allocate_tensor::<f32>(&[2]).unwrap();
+    /// let data = tensor.as_slice::<f32>().unwrap();
+    /// tensor.as_mut_slice::<f32>().unwrap()[0] = 1.0;
+    /// assert_eq!(data[0], 0.0);
+    /// # }
+    /// ```
+    pub fn as_slice<T: DataType>(&self) -> Option<&[T]> {
         if T::tensor_element_data_type() == self.data_type {
             unsafe {
                 Some(std::slice::from_raw_parts(
@@ -303,6 +1194,21 @@ impl<'a> Tensor<'a> {
             None
         }
     }
+
+    /// Returns the data for writing. This is only possible for tensors whose data is owned by
+    /// ORT, i.e. outputs and tensors created by `Environment::allocate_tensor`.
+    pub fn as_mut_slice<T: DataType>(&mut self) -> Option<&mut [T]> {
+        if self.mutable && T::tensor_element_data_type() == self.data_type {
+            unsafe {
+                Some(std::slice::from_raw_parts_mut(
+                    self.data_ptr as _,
+                    self.shape.iter().product(),
+                ))
+            }
+        } else {
+            None
+        }
+    }
 }
 
 impl<'a> Drop for Tensor<'a> {
@@ -358,4 +1264,368 @@ mod tests {
             ]
         );
     }
+
+    #[test]
+    fn test_allocate_tensor() {
+        let env = Environment::new().unwrap();
+        let sess = env.new_session("src/onnx/testdata/upsample.onnx").unwrap();
+        let mut input = env.allocate_tensor::<f32>(&[1, 1, 2, 3]).unwrap();
+        assert!(input.as_mut_slice::<i64>().is_none());
+        let data = input.as_mut_slice::<f32>().unwrap();
+        assert_eq!(data, &[0.; 6]);
+        data.copy_from_slice(&[1., 2., 3., 3., 4., 5.]);
+
+        let outputs = sess.run(&[("up_sampling2d_input:0", &input)]).unwrap();
+        assert_eq!(
+            outputs[0].1.as_slice::<f32>().unwrap(),
+            vec![
+                1., 2., 3., 1., 2., 3., 3., 4., 5., 3., 4., 5., 1., 2., 3., 1., 2., 3., 3., 4., 5.,
+                3., 4., 5.
+            ]
+        );
+
+        let borrowed = [0f32; 6];
+        let mut borrowed = env.new_tensor(&borrowed, &[1, 1, 2, 3]).unwrap();
+        assert!(borrowed.as_mut_slice::<f32>().is_none());
+    }
+
+    #[test]
+    fn test_metadata() {
+        let env = Environment::new().unwrap();
+
+        let sess = env.new_session("src/onnx/testdata/upsample.onnx").unwrap();
+        let metadata = sess.metadata().unwrap();
+        assert_eq!(metadata.producer_name, "tf2onnx");
+        assert_eq!(metadata.graph_name, "tf2onnx");
+        assert_eq!(metadata.custom, vec![]);
+    }
+
+    #[test]
+    fn test_free_dimension_override() {
+        let env = Environment::new().unwrap();
+
+        let sess = env.new_session("src/onnx/testdata/upsample.onnx").unwrap();
+        assert_eq!(
+            sess.inputs(),
+            &[TensorInfo {
+                name: "up_sampling2d_input:0".to_string(),
+                element_type: ElementType::Float,
+                dimensions: vec![
+                    Dimension::Symbolic("unk__31".to_string()),
+                    Dimension::Symbolic("unk__32".to_string()),
+                    Dimension::Symbolic("unk__33".to_string()),
+                    Dimension::Fixed(3),
+                ],
+            }]
+        );
+
+        let sess = env
+            .session_builder()
+            .free_dimension_override_by_name("unk__31", 1)
+            .build("src/onnx/testdata/upsample.onnx")
+            .unwrap();
+        assert_eq!(
+            sess.inputs()[0].dimensions,
+            vec![
+                Dimension::Fixed(1),
+                Dimension::Symbolic("unk__32".to_string()),
+                Dimension::Symbolic("unk__33".to_string()),
+                Dimension::Fixed(3),
+            ]
+        );
+
+        let input = array![[1., 2., 3.], [3., 4., 5.]];
+        let input = env
+            .new_tensor(input.as_slice().unwrap(), &[1, 1, 2, 3])
+            .unwrap();
+        let outputs = sess.run(&[("up_sampling2d_input:0", input)]).unwrap();
+        assert_eq!(outputs[0].1.shape(), vec![1, 2, 4, 3]);
+
+        assert!(matches!(
+            env.session_builder()
+                .free_dimension_override_by_name("unk__31", -1)
+                .build("src/onnx/testdata/upsample.onnx"),
+            Err(NewSessionError::NegativeDimensionOverride { value: -1, .. })
+        ));
+    }
+
+    // custom_op_library.onnx contains a single "CustomAdd" node from the "infer.test" domain, which
+    // is implemented by custom_op_library.c. It was produced via:
+    //
+    // ```
+    // import onnx
+    // from onnx import helper, TensorProto
+    //
+    // graph = helper.make_graph(
+    //     [helper.make_node('CustomAdd', ['x', 'y'], ['z'], domain='infer.test')],
+    //     'custom_op_library',
+    //     [
+    //         helper.make_tensor_value_info('x', TensorProto.FLOAT, [3]),
+    //         helper.make_tensor_value_info('y', TensorProto.FLOAT, [3]),
+    //     ],
+    //     [helper.make_tensor_value_info('z', TensorProto.FLOAT, [3])],
+    // )
+    // model = helper.make_model(graph, ir_version=7, opset_imports=[
+    //     helper.make_opsetid('', 12),
+    //     helper.make_opsetid('infer.test', 1),
+    // ])
+    // onnx.save(model, 'custom_op_library.onnx')
+    // ```
+    #[test]
+    fn test_custom_ops_library() {
+        let env = Environment::new().unwrap();
+
+        assert!(env
+            .new_session("src/onnx/testdata/custom_op_library.onnx")
+            .is_err());
+
+        let builder = env
+            .session_builder()
+            .custom_ops_library(env!("INFER_TEST_CUSTOM_OP_LIBRARY"));
+        let sessions = (0..2)
+            .map(|_| {
+                builder
+                    .build("src/onnx/testdata/custom_op_library.onnx")
+                    .unwrap()
+            })
+            .collect::<Vec<_>>();
+
+        // Each session holds its own reference to the library, so dropping one mustn't unload it
+        // from under the other.
+        for sess in sessions {
+            let x = env.new_tensor(&[1.0f32, 2.0, 3.0], &[3]).unwrap();
+            let y = env.new_tensor(&[10.0f32, 20.0, 30.0], &[3]).unwrap();
+            let outputs = sess.run(&[("x", x), ("y", y)]).unwrap();
+            assert_eq!(outputs.len(), 1);
+            let (name, output) = &outputs[0];
+            assert_eq!(name, &"z");
+            assert_eq!(output.as_slice::<f32>().unwrap(), &[11.0, 22.0, 33.0]);
+        }
+    }
+
+    #[test]
+    fn test_profiling() {
+        let env = Environment::new().unwrap();
+
+        let prefix =
+            std::env::temp_dir().join(format!("infer_test_profiling_{}", std::process::id()));
+        let mut sess = env
+            .session_builder()
+            .enable_profiling(&prefix)
+            .build("src/onnx/testdata/upsample.onnx")
+            .unwrap();
+
+        let input = env
+            .new_tensor(&[1.0f32, 2.0, 3.0, 3.0, 4.0, 5.0], &[1, 1, 2, 3])
+            .unwrap();
+        sess.run(&[("up_sampling2d_input:0", input)]).unwrap();
+
+        let path = sess.end_profiling().unwrap();
+        assert!(path
+            .file_name()
+            .unwrap()
+            .to_string_lossy()
+            .starts_with(&*prefix.file_name().unwrap().to_string_lossy()));
+        let profile = profiling::Profile::from_path(&path).unwrap();
+
+        let summaries = profile.op_type_summaries();
+        assert!(summaries.iter().any(|s| s.op_type == "Resize"));
+
+        std::fs::remove_file(&path).unwrap();
+    }
+
+    #[test]
+    fn test_optimized_model_cache() {
+        let env = Environment::new().unwrap();
+
+        let cache_dir = std::env::temp_dir().join(format!(
+            "infer_test_optimized_model_cache_{}",
+            std::process::id()
+        ));
+        let _ = std::fs::remove_dir_all(&cache_dir);
+        let builder = env.session_builder().optimized_model_cache_dir(&cache_dir);
+        let entries = || {
+            std::fs::read_dir(&cache_dir)
+                .unwrap()
+                .map(|entry| entry.unwrap().path())
+                .collect::<Vec<_>>()
+        };
+
+        let run = |sess: &Session| {
+            let input = env
+                .new_tensor(&[1.0f32, 2.0, 3.0, 3.0, 4.0, 5.0], &[1, 1, 2, 3])
+                .unwrap();
+            let outputs = sess.run(&[("up_sampling2d_input:0", input)]).unwrap();
+            outputs[0].1.as_slice::<f32>().unwrap().to_vec()
+        };
+        let expected = run(&env.new_session("src/onnx/testdata/upsample.onnx").unwrap());
+
+        // The first load populates the cache, and the second one uses it.
+        for _ in 0..2 {
+            let sess = builder.build("src/onnx/testdata/upsample.onnx").unwrap();
+            assert_eq!(run(&sess), expected);
+            assert_eq!(entries().len(), 1);
+        }
+
+        // Corrupt entries get replaced.
+        let entry = entries().remove(0);
+        std::fs::write(&entry, b"garbage").unwrap();
+        let sess = builder.build("src/onnx/testdata/upsample.onnx").unwrap();
+        assert_eq!(run(&sess), expected);
+        assert_eq!(entries(), vec![entry.clone()]);
+        assert_ne!(std::fs::read(&entry).unwrap(), b"garbage");
+
+        // Different options result in a different entry.
+        env.session_builder()
+            .optimized_model_cache_dir(&cache_dir)
+            .free_dimension_override_by_name("unk__31", 1)
+            .build("src/onnx/testdata/upsample.onnx")
+            .unwrap();
+        assert_eq!(entries().len(), 2);
+
+        // Failures unrelated to the entry, like a missing custom ops library, leave it alone.
+        let library_path = cache_dir.join("custom_ops_library");
+        std::fs::copy(env!("INFER_TEST_CUSTOM_OP_LIBRARY"), &library_path).unwrap();
+        let builder = builder.custom_ops_library(&library_path);
+        builder
+            .build("src/onnx/testdata/custom_op_library.onnx")
+            .unwrap();
+        let mut before = entries();
+        before.retain(|path| path != &library_path);
+        before.sort();
+        std::fs::remove_file(&library_path).unwrap();
+        assert!(builder
+            .build("src/onnx/testdata/custom_op_library.onnx")
+            .is_err());
+        let mut after = entries();
+        after.sort();
+        assert_eq!(after, before);
+
+        std::fs::remove_dir_all(&cache_dir).unwrap();
+    }
+
+    #[test]
+    fn test_ort_format() {
+        let env = Environment::new().unwrap();
+
+        let ort_path =
+            std::env::temp_dir().join(format!("infer_test_ort_format_{}.ort", std::process::id()));
+        env.convert_to_ort_format("src/onnx/testdata/upsample.onnx", &ort_path)
+            .unwrap();
+
+        let run = |sess: &Session| {
+            let input = env
+                .new_tensor(&[1.0f32, 2.0, 3.0, 3.0, 4.0, 5.0], &[1, 1, 2, 3])
+                .unwrap();
+            let outputs = sess.run(&[("up_sampling2d_input:0", input)]).unwrap();
+            outputs[0].1.as_slice::<f32>().unwrap().to_vec()
+        };
+        let onnx_sess = env.new_session("src/onnx/testdata/upsample.onnx").unwrap();
+        let ort_sess = env
+            .session_builder()
+            .load_model_format(ModelFormat::ORT)
+            .build(&ort_path)
+            .unwrap();
+        assert_eq!(ort_sess.inputs(), onnx_sess.inputs());
+        assert_eq!(run(&ort_sess), run(&onnx_sess));
+
+        std::fs::remove_file(&ort_path).unwrap();
+    }
+
+    // future_opset.onnx is a single Identity node that claims to need opset 99. It was produced
+    // via:
+    //
+    // ```
+    // import onnx
+    // from onnx import helper, TensorProto
+    //
+    // graph = helper.make_graph(
+    //     [helper.make_node('Identity', ['x'], ['y'])],
+    //     'future_opset',
+    //     [helper.make_tensor_value_info('x', TensorProto.FLOAT, [3])],
+    //     [helper.make_tensor_value_info('y', TensorProto.FLOAT, [3])],
+    // )
+    // model = helper.make_model(
+    //     graph, ir_version=7, opset_imports=[helper.make_opsetid('', 99)]
+    // )
+    // onnx.save(model, 'future_opset.onnx')
+    // ```
+    #[test]
+    fn test_unsupported_opset() {
+        let env = Environment::new().unwrap();
+
+        match env.new_session("src/onnx/testdata/future_opset.onnx") {
+            Err(NewSessionError::UnsupportedOpset {
+                model_opset,
+                max_opset,
+                ..
+            }) => {
+                assert_eq!(model_opset, 99);
+                assert_eq!(Some(max_opset), max_supported_opset());
+            }
+            r => panic!("unexpected result: {:?}", r.map(|_| ())),
+        };
+    }
+
+    // overridable_initializer.onnx multiplies its input by a "scale" initializer, which is also
+    // listed as a graph input so that it can be overridden. It was produced via:
+    //
+    // ```
+    // import onnx
+    // from onnx import helper, TensorProto
+    //
+    // graph = helper.make_graph(
+    //     [helper.make_node('Mul', ['x', 'scale'], ['y'])],
+    //     'overridable_initializer',
+    //     [
+    //         helper.make_tensor_value_info('x', TensorProto.FLOAT, [3]),
+    //         helper.make_tensor_value_info('scale', TensorProto.FLOAT, [1]),
+    //     ],
+    //     [helper.make_tensor_value_info('y', TensorProto.FLOAT, [3])],
+    //     [helper.make_tensor('scale', TensorProto.FLOAT, [1], [2.0])],
+    // )
+    // model = helper.make_model(graph, ir_version=7, opset_imports=[helper.make_opsetid('', 12)])
+    // onnx.save(model, 'overridable_initializer.onnx')
+    // ```
+    #[test]
+    fn test_overridable_initializers() {
+        let env = Environment::new().unwrap();
+
+        let sess = env
+            .new_session("src/onnx/testdata/overridable_initializer.onnx")
+            .unwrap();
+        assert_eq!(
+            sess.overridable_initializers(),
+            &[TensorInfo {
+                name: "scale".to_string(),
+                element_type: ElementType::Float,
+                dimensions: vec![Dimension::Fixed(1)],
+            }]
+        );
+
+        let x = env.new_tensor(&[1.0f32, 2.0, 3.0], &[3]).unwrap();
+        let outputs = sess.run(&[("x", x)]).unwrap();
+        assert_eq!(outputs[0].1.as_slice::<f32>().unwrap(), &[2.0, 4.0, 6.0]);
+
+        let x = env.new_tensor(&[1.0f32, 2.0, 3.0], &[3]).unwrap();
+        let scale = env.new_tensor(&[3.0f32], &[1]).unwrap();
+        let outputs = sess.run(&[("x", x), ("scale", scale)]).unwrap();
+        assert_eq!(outputs[0].1.as_slice::<f32>().unwrap(), &[3.0, 6.0, 9.0]);
+
+        let x = env.new_tensor(&[1.0f32, 2.0, 3.0], &[3]).unwrap();
+        let scale = env.new_tensor(&[3.0f64], &[1]).unwrap();
+        match sess.run(&[("x", x), ("scale", scale)]) {
+            Err(SessionRunError::InitializerTypeMismatch { name, .. }) => assert_eq!(name, "scale"),
+            r => panic!("unexpected result: {:?}", r.map(|_| ())),
+        };
+
+        let x = env.new_tensor(&[1.0f32, 2.0, 3.0], &[3]).unwrap();
+        let scale = env.new_tensor(&[3.0f32, 4.0], &[2]).unwrap();
+        match sess.run(&[("x", x), ("scale", scale)]) {
+            Err(SessionRunError::InitializerShapeMismatch { name, .. }) => {
+                assert_eq!(name, "scale")
+            }
+            r => panic!("unexpected result: {:?}", r.map(|_| ())),
+        };
+    }
 }
@@ -0,0 +1,179 @@
+//! Parses the JSON traces written by ORT when profiling is enabled via
+//! `SessionBuilder::enable_profiling`.
+
+use serde::Deserialize;
+use std::{collections::HashMap, path::Path, time::Duration};
+
+#[derive(thiserror::Error, Debug)]
+pub enum ProfileError {
+    #[error("unable to read profile: {0}")]
+    Io(#[from] std::io::Error),
+    #[error("malformed profile: {0}")]
+    Json(#[from] serde_json::Error),
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub enum EventCategory {
+    /// Session-level events such as model loading and whole runs.
+    Session,
+    /// Per-node events, including kernel execution.
+    Node,
+    Other(String),
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct Event {
+    pub category: EventCategory,
+    pub name: String,
+    /// The time at which the event started, relative to the start of profiling.
+    pub start: Duration,
+    pub duration: Duration,
+    /// The op type (e.g. "Conv") for node events.
+    pub op_type: Option<String>,
+    /// The execution provider that ran the node for node events.
+    pub provider: Option<String>,
+}
+
+impl Event {
+    /// Returns true if this event measures the execution of a node's kernel, as opposed to the
+    /// fences surrounding it.
+    pub fn is_kernel_time(&self) -> bool {
+        self.category == EventCategory::Node && self.name.ends_with("_kernel_time")
+    }
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct OpTypeSummary {
+    pub op_type: String,
+    /// The number of kernel executions of this op type.
+    pub count: usize,
+    pub total_duration: Duration,
+}
+
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct Profile {
+    pub events: Vec<Event>,
+}
+
+#[derive(Deserialize)]
+struct RawEvent {
+    cat: String,
+    name: String,
+    #[serde(default)]
+    ts: u64,
+    #[serde(default)]
+    dur: u64,
+    #[serde(default)]
+    args: RawArgs,
+}
+
+#[derive(Default, Deserialize)]
+struct RawArgs {
+    op_name: Option<String>,
+    provider: Option<String>,
+}
+
+impl Profile {
+    pub fn from_path<P: AsRef<Path>>(path: P) -> Result<Self, ProfileError> {
+        Self::parse(&std::fs::read_to_string(path)?)
+    }
+
+    pub fn parse(json: &str) -> Result<Self, ProfileError> {
+        let raw: Vec<RawEvent> = serde_json::from_str(json)?;
+        Ok(Self {
+            events: raw
+                .into_iter()
+                .map(|e| Event {
+                    category: match e.cat.as_str() {
+                        "Session" => EventCategory::Session,
+                        "Node" => EventCategory::Node,
+                        _ => EventCategory::Other(e.cat),
+                    },
+                    name: e.name,
+                    start: Duration::from_micros(e.ts),
+                    duration: Duration::from_micros(e.dur),
+                    op_type: e.args.op_name,
+                    provider: e.args.provider,
+                })
+                .collect(),
+        })
+    }
+
+    /// Aggregates kernel execution times by op type, ordered from the most to the least total
+    /// time spent.
+    pub fn op_type_summaries(&self) -> Vec<OpTypeSummary> {
+        let mut summaries: HashMap<&str, OpTypeSummary> = HashMap::new();
+        for event in self.events.iter().filter(|e| e.is_kernel_time()) {
+            let op_type = match &event.op_type {
+                Some(op_type) => op_type,
+                None => continue,
+            };
+            let summary = summaries.entry(op_type).or_insert_with(|| OpTypeSummary {
+                op_type: op_type.clone(),
+                count: 0,
+                total_duration: Duration::default(),
+            });
+            summary.count += 1;
+            summary.total_duration += event.duration;
+        }
+        let mut summaries: Vec<_> = summaries.into_values().collect();
+        summaries.sort_by(|a, b| {
+            b.total_duration
+                .cmp(&a.total_duration)
+                .then_with(|| a.op_type.cmp(&b.op_type))
+        });
+        summaries
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse() {
+        let profile = Profile::parse(
+            r#"[
+{"cat" : "Session","pid" :1,"tid" :1,"dur" :250,"ts" :3,"ph" : "X","name" :"model_loading_uri","args" : {}},
+{"cat" : "Node","pid" :1,"tid" :1,"dur" :0,"ts" :300,"ph" : "X","name" :"Conv_0_fence_before","args" : {"op_name" : "Conv"}},
+{"cat" : "Node","pid" :1,"tid" :1,"dur" :40,"ts" :301,"ph" : "X","name" :"Conv_0_kernel_time","args" : {"op_name" : "Conv","provider" : "CPUExecutionProvider"}},
+{"cat" : "Node","pid" :1,"tid" :1,"dur" :5,"ts" :342,"ph" : "X","name" :"Relu_1_kernel_time","args" : {"op_name" : "Relu","provider" : "CPUExecutionProvider"}},
+{"cat" : "Node","pid" :1,"tid" :1,"dur" :30,"ts" :348,"ph" : "X","name" :"Conv_2_kernel_time","args" : {"op_name" : "Conv","provider" : "CPUExecutionProvider"}},
+{"cat" : "Session","pid" :1,"tid" :1,"dur" :90,"ts" :295,"ph" : "X","name" :"model_run","args" : {}}
+]
+"#,
+        )
+        .unwrap();
+
+        assert_eq!(profile.events.len(), 6);
+        assert_eq!(
+            profile.events[2],
+            Event {
+                category: EventCategory::Node,
+                name: "Conv_0_kernel_time".to_string(),
+                start: Duration::from_micros(301),
+                duration: Duration::from_micros(40),
+                op_type: Some("Conv".to_string()),
+                provider: Some("CPUExecutionProvider".to_string()),
+            }
+        );
+
+        assert_eq!(
+            profile.op_type_summaries(),
+            vec![
+                OpTypeSummary {
+                    op_type: "Conv".to_string(),
+                    count: 2,
+                    total_duration: Duration::from_micros(70),
+                },
+                OpTypeSummary {
+                    op_type: "Relu".to_string(),
+                    count: 1,
+                    total_duration: Duration::from_micros(5),
+                },
+            ]
+        );
+
+        assert!(Profile::parse("{").is_err());
+    }
+}
@@ -0,0 +1,606 @@
+//! A read-only view of ONNX models, decoded in pure Rust. Unlike the rest of the `onnx` module,
+//! this doesn't need ORT, so it's available even without the `onnx` feature.
+
+use crate::{
+    protobuf::{Reader, Value},
+    Element, OwnedTensor, TensorData,
+};
+use half::{bf16, f16};
+use std::{collections::BTreeMap, path::Path};
+
+pub use crate::protobuf::DecodeError;
+
+#[derive(thiserror::Error, Debug)]
+pub enum ReadModelError {
+    #[error("unable to read model: {0}")]
+    Io(#[from] std::io::Error),
+    #[error(transparent)]
+    Decode(#[from] DecodeError),
+}
+
+/// The element type of a tensor.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub enum ElementType {
+    Undefined,
+    Float,
+    UInt8,
+    Int8,
+    UInt16,
+    Int16,
+    Int32,
+    Int64,
+    String,
+    Bool,
+    Float16,
+    Double,
+    UInt32,
+    UInt64,
+    Complex64,
+    Complex128,
+    BFloat16,
+}
+
+impl ElementType {
+    /// Converts from a `TensorProto.DataType` value.
+    pub fn from_onnx(data_type: i64) -> Self {
+        match data_type {
+            1 => Self::Float,
+            2 => Self::UInt8,
+            3 => Self::Int8,
+            4 => Self::UInt16,
+            5 => Self::Int16,
+            6 => Self::Int32,
+            7 => Self::Int64,
+            8 => Self::String,
+            9 => Self::Bool,
+            10 => Self::Float16,
+            11 => Self::Double,
+            12 => Self::UInt32,
+            13 => Self::UInt64,
+            14 => Self::Complex64,
+            15 => Self::Complex128,
+            16 => Self::BFloat16,
+            _ => Self::Undefined,
+        }
+    }
+
+    /// Returns the size of a single element, or `None` for types without a fixed size.
+    pub fn size_in_bytes(self) -> Option<usize> {
+        match self {
+            Self::Undefined | Self::String => None,
+            Self::UInt8 | Self::Int8 | Self::Bool => Some(1),
+            Self::UInt16 | Self::Int16 | Self::Float16 | Self::BFloat16 => Some(2),
+            Self::Float | Self::Int32 | Self::UInt32 => Some(4),
+            Self::Int64 | Self::UInt64 | Self::Double | Self::Complex64 => Some(8),
+            Self::Complex128 => Some(16),
+        }
+    }
+}
+
+/// A single dimension of a tensor's shape.
+#[derive(Clone, Debug, PartialEq, Eq, Hash)]
+pub enum Dimension {
+    Fixed(usize),
+    /// A dimension that isn't known until run time, identified by name (e.g. "batch").
+    Symbolic(String),
+    /// A dimension that isn't known until run time and has no name.
+    Unknown,
+}
+
+/// Describes one of a model's inputs or outputs. Inputs and outputs that aren't tensors have an
+/// element type of `ElementType::Undefined` and no dimensions.
+#[derive(Clone, Debug, PartialEq)]
+pub struct TensorInfo {
+    pub name: String,
+    pub element_type: ElementType,
+    pub dimensions: Vec<Dimension>,
+}
+
+impl TensorInfo {
+    /// Returns true if a tensor with the given shape satisfies this tensor's dimensions.
+    pub fn accepts_shape(&self, shape: &[usize]) -> bool {
+        self.dimensions.len() == shape.len()
+            && self.dimensions.iter().zip(shape).all(|(dim, n)| match dim {
+                Dimension::Fixed(expected) => expected == n,
+                Dimension::Symbolic(_) | Dimension::Unknown => true,
+            })
+    }
+
+    fn decode(r: Reader) -> Result<Self, DecodeError> {
+        let mut ret = Self {
+            name: String::new(),
+            element_type: ElementType::Undefined,
+            dimensions: Vec::new(),
+        };
+        for field in r {
+            match field? {
+                (1, v) => ret.name = v.as_string()?,
+                (2, v) => {
+                    // Only tensor types (TypeProto.tensor_type) are described.
+                    for field in v.as_message()? {
+                        if let (1, v) = field? {
+                            ret.decode_tensor_type(v.as_message()?)?;
+                        }
+                    }
+                }
+                _ => {}
+            }
+        }
+        Ok(ret)
+    }
+
+    fn decode_tensor_type(&mut self, r: Reader) -> Result<(), DecodeError> {
+        for field in r {
+            match field? {
+                (1, v) => self.element_type = ElementType::from_onnx(v.as_i64()?),
+                (2, v) => {
+                    for field in v.as_message()? {
+                        if let (1, v) = field? {
+                            let mut dim = Dimension::Unknown;
+                            for field in v.as_message()? {
+                                match field? {
+                                    (1, v) if v.as_i64()? >= 0 => {
+                                        dim = Dimension::Fixed(v.as_i64()? as _)
+                                    }
+                                    (2, v) => dim = Dimension::Symbolic(v.as_string()?),
+                                    _ => {}
+                                }
+                            }
+                            self.dimensions.push(dim);
+                        }
+                    }
+                }
+                _ => {}
+            }
+        }
+        Ok(())
+    }
+}
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct OperatorSetId {
+    /// The operator set's domain. The default "ai.onnx" domain is usually given as "".
+    pub domain: String,
+    pub version: i64,
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub enum AttributeValue {
+    Float(f32),
+    Int(i64),
+    String(String),
+    /// A tensor, or `None` if its data is stored externally or its type isn't one `TensorData`
+    /// can hold.
+    Tensor(Option<OwnedTensor>),
+    Floats(Vec<f32>),
+    Ints(Vec<i64>),
+    Strings(Vec<String>),
+    /// A type that isn't decoded, e.g. a graph.
+    Other,
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct Attribute {
+    pub name: String,
+    pub value: AttributeValue,
+}
+
+impl Attribute {
+    fn decode(r: Reader) -> Result<Self, DecodeError> {
+        let mut name = String::new();
+        let mut attribute_type = 0;
+        let (mut f, mut i, mut s, mut t) = (None, None, None, None);
+        let (mut floats, mut ints, mut strings) = (Vec::new(), Vec::new(), Vec::new());
+        for field in r {
+            match field? {
+                (1, v) => name = v.as_string()?,
+                (2, v) => f = Some(f32::from_bits(v.as_u64()? as u32)),
+                (3, v) => i = Some(v.as_i64()?),
+                (4, v) => s = Some(String::from_utf8_lossy(v.as_bytes()?).into_owned()),
+                (5, v) => t = Some(decode_tensor(v.as_message()?)?.1),
+                (7, v) => v.extend_fixed32s(&mut floats)?,
+                (8, v) => v.extend_i64s(&mut ints)?,
+                (9, v) => strings.push(String::from_utf8_lossy(v.as_bytes()?).into_owned()),
+                (20, v) => attribute_type = v.as_i64()?,
+                _ => {}
+            }
+        }
+        let floats = floats.into_iter().map(f32::from_bits).collect();
+        // Old models may not give the type, in which case it's implied by the field that's set.
+        let value = match (attribute_type, f, i, s, t) {
+            (1, Some(f), ..) | (0, Some(f), ..) => AttributeValue::Float(f),
+            (2, _, Some(i), ..) | (0, None, Some(i), ..) => AttributeValue::Int(i),
+            (3, _, _, Some(s), _) | (0, None, None, Some(s), _) => AttributeValue::String(s),
+            (4, _, _, _, Some(t)) | (0, None, None, None, Some(t)) => AttributeValue::Tensor(t),
+            (6, ..) => AttributeValue::Floats(floats),
+            (7, ..) => AttributeValue::Ints(ints),
+            (8, ..) => AttributeValue::Strings(strings),
+            (0, ..) if !floats.is_empty() => AttributeValue::Floats(floats),
+            (0, ..) if !ints.is_empty() => AttributeValue::Ints(ints),
+            (0, ..) if !strings.is_empty() => AttributeValue::Strings(strings),
+            _ => AttributeValue::Other,
+        };
+        Ok(Self { name, value })
+    }
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct Node {
+    pub name: String,
+    pub op_type: String,
+    pub domain: String,
+    pub inputs: Vec<String>,
+    pub outputs: Vec<String>,
+    pub attributes: Vec<Attribute>,
+}
+
+impl Node {
+    pub fn attribute(&self, name: &str) -> Option<&AttributeValue> {
+        self.attributes
+            .iter()
+            .find(|a| a.name == name)
+            .map(|a| &a.value)
+    }
+
+    fn decode(r: Reader) -> Result<Self, DecodeError> {
+        let mut ret = Self {
+            name: String::new(),
+            op_type: String::new(),
+            domain: String::new(),
+            inputs: Vec::new(),
+            outputs: Vec::new(),
+            attributes: Vec::new(),
+        };
+        for field in r {
+            match field? {
+                (1, v) => ret.inputs.push(v.as_string()?),
+                (2, v) => ret.outputs.push(v.as_string()?),
+                (3, v) => ret.name = v.as_string()?,
+                (4, v) => ret.op_type = v.as_string()?,
+                (5, v) => ret.attributes.push(Attribute::decode(v.as_message()?)?),
+                (7, v) => ret.domain = v.as_string()?,
+                _ => {}
+            }
+        }
+        Ok(ret)
+    }
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct Initializer {
+    pub name: String,
+    pub element_type: ElementType,
+    pub dimensions: Vec<i64>,
+    /// True if the data is stored outside of the model file.
+    pub external: bool,
+}
+
+impl Initializer {
+    /// Returns the number of elements, or `None` if it overflows.
+    pub fn element_count(&self) -> Option<usize> {
+        self.dimensions
+            .iter()
+            .try_fold(1usize, |count, &d| count.checked_mul(d.max(0) as usize))
+    }
+
+    /// Returns the size of the initializer's data, or `None` for types without a fixed size or if
+    /// the size overflows.
+    pub fn size_in_bytes(&self) -> Option<usize> {
+        self.element_type
+            .size_in_bytes()?
+            .checked_mul(self.element_count()?)
+    }
+
+    fn decode(r: Reader) -> Result<Self, DecodeError> {
+        let mut ret = Self {
+            name: String::new(),
+            element_type: ElementType::Undefined,
+            dimensions: Vec::new(),
+            external: false,
+        };
+        for field in r {
+            match field? {
+                (1, v) => v.extend_i64s(&mut ret.dimensions)?,
+                (2, v) => ret.element_type = ElementType::from_onnx(v.as_i64()?),
+                (8, v) => ret.name = v.as_string()?,
+                (14, v) => ret.external = v.as_i64()? == 1,
+                _ => {}
+            }
+        }
+        Ok(ret)
+    }
+}
+
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct Graph {
+    pub name: String,
+    pub nodes: Vec<Node>,
+    /// The graph's inputs. Depending on the IR version, these may include initializers.
+    pub inputs: Vec<TensorInfo>,
+    pub outputs: Vec<TensorInfo>,
+    pub initializers: Vec<Initializer>,
+}
+
+impl Graph {
+    /// Counts the graph's nodes by op type. Nodes outside the default domain are keyed as
+    /// "domain.OpType". Subgraphs (e.g. the branches of an `If`) aren't included.
+    pub fn op_type_histogram(&self) -> BTreeMap<String, usize> {
+        let mut ret = BTreeMap::new();
+        for node in &self.nodes {
+            let key = match node.domain.as_str() {
+                "" | "ai.onnx" => node.op_type.clone(),
+                domain => format!("{}.{}", domain, node.op_type),
+            };
+            *ret.entry(key).or_insert(0) += 1;
+        }
+        ret
+    }
+
+    /// Returns the inputs that aren't initializers, i.e. the ones that must be provided at run
+    /// time.
+    pub fn runtime_inputs(&self) -> impl Iterator<Item = &TensorInfo> {
+        self.inputs
+            .iter()
+            .filter(move |input| !self.initializers.iter().any(|i| i.name == input.name))
+    }
+
+    fn decode(r: Reader) -> Result<Self, DecodeError> {
+        let mut ret = Self::default();
+        for field in r {
+            match field? {
+                (1, v) => ret.nodes.push(Node::decode(v.as_message()?)?),
+                (2, v) => ret.name = v.as_string()?,
+                (5, v) => ret.initializers.push(Initializer::decode(v.as_message()?)?),
+                (11, v) => ret.inputs.push(TensorInfo::decode(v.as_message()?)?),
+                (12, v) => ret.outputs.push(TensorInfo::decode(v.as_message()?)?),
+                _ => {}
+            }
+        }
+        Ok(ret)
+    }
+}
+
+/// An ONNX `ModelProto`.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct Model {
+    pub ir_version: i64,
+    pub opset_imports: Vec<OperatorSetId>,
+    pub producer_name: String,
+    pub producer_version: String,
+    pub domain: String,
+    pub model_version: i64,
+    pub doc_string: String,
+    pub graph: Graph,
+    pub metadata: Vec<(String, String)>,
+}
+
+impl Model {
+    pub fn from_path<P: AsRef<Path>>(path: P) -> Result<Self, ReadModelError> {
+        Ok(Self::decode(&std::fs::read(path)?)?)
+    }
+
+    pub fn decode(buf: &[u8]) -> Result<Self, DecodeError> {
+        let mut ret = Self::default();
+        for field in Reader::new(buf) {
+            match field? {
+                (1, v) => ret.ir_version = v.as_i64()?,
+                (2, v) => ret.producer_name = v.as_string()?,
+                (3, v) => ret.producer_version = v.as_string()?,
+                (4, v) => ret.domain = v.as_string()?,
+                (5, v) => ret.model_version = v.as_i64()?,
+                (6, v) => ret.doc_string = v.as_string()?,
+                (7, v) => ret.graph = Graph::decode(v.as_message()?)?,
+                (8, v) => {
+                    let mut opset = OperatorSetId {
+                        domain: String::new(),
+                        version: 0,
+                    };
+                    for field in v.as_message()? {
+                        match field? {
+                            (1, v) => opset.domain = v.as_string()?,
+                            (2, v) => opset.version = v.as_i64()?,
+                            _ => {}
+                        }
+                    }
+                    ret.opset_imports.push(opset);
+                }
+                (14, v) => ret.metadata.push(decode_string_pair(v)?),
+                _ => {}
+            }
+        }
+        Ok(ret)
+    }
+
+    /// Returns the version of the default "ai.onnx" operator set used by the model.
+    pub fn opset_version(&self) -> Option<i64> {
+        self.opset_imports
+            .iter()
+            .find(|opset| opset.domain.is_empty() || opset.domain == "ai.onnx")
+            .map(|opset| opset.version)
+    }
+}
+
+/// Decodes a `TensorProto`'s name and data. The data is `None` if it's stored externally or its
+/// type isn't one `TensorData` can hold.
+fn decode_tensor(r: Reader) -> Result<(String, Option<OwnedTensor>), DecodeError> {
+    let mut name = String::new();
+    let mut element_type = ElementType::Undefined;
+    let mut dimensions = Vec::new();
+    let mut raw_data = None;
+    let (mut fixed32s, mut fixed64s) = (Vec::new(), Vec::new());
+    let (mut int32s, mut int64s, mut uint64s) = (Vec::new(), Vec::new(), Vec::new());
+    let mut external = false;
+    for field in r {
+        match field? {
+            (1, v) => v.extend_i64s(&mut dimensions)?,
+            (2, v) => element_type = ElementType::from_onnx(v.as_i64()?),
+            (4, v) => v.extend_fixed32s(&mut fixed32s)?,
+            (5, v) => v.extend_i64s(&mut int32s)?,
+            (7, v) => v.extend_i64s(&mut int64s)?,
+            (8, v) => name = v.as_string()?,
+            (9, v) => raw_data = Some(v.as_bytes()?),
+            (10, v) => v.extend_fixed64s(&mut fixed64s)?,
+            (11, v) => v.extend_i64s(&mut uint64s)?,
+            (14, v) => external = v.as_i64()? == 1,
+            _ => {}
+        }
+    }
+    if external || dimensions.iter().any(|&d| d < 0) {
+        return Ok((name, None));
+    }
+
+    // Types without a field of their own are stored in int32_data, with 16-bit floats as their
+    // bits.
+    fn convert<T, U: Element>(v: Vec<T>, f: impl Fn(T) -> U) -> Option<TensorData> {
+        Some(U::into_data(v.into_iter().map(f).collect()))
+    }
+    let data = match raw_data {
+        Some(raw_data) => TensorData::from_le_bytes(element_type, raw_data),
+        None => match element_type {
+            ElementType::Bool => convert(int32s, |v| v != 0),
+            ElementType::Int8 => convert(int32s, |v| v as i8),
+            ElementType::Int16 => convert(int32s, |v| v as i16),
+            ElementType::Int32 => convert(int32s, |v| v as i32),
+            ElementType::Int64 => convert(int64s, |v| v),
+            ElementType::UInt8 => convert(int32s, |v| v as u8),
+            ElementType::UInt16 => convert(int32s, |v| v as u16),
+            ElementType::UInt32 => convert(uint64s, |v| v as u32),
+            ElementType::UInt64 => convert(uint64s, |v| v as u64),
+            ElementType::Float16 => convert(int32s, |v| f16::from_bits(v as u16)),
+            ElementType::BFloat16 => convert(int32s, |v| bf16::from_bits(v as u16)),
+            ElementType::Float => convert(fixed32s, f32::from_bits),
+            ElementType::Double => convert(fixed64s, f64::from_bits),
+            _ => None,
+        },
+    };
+    let shape = dimensions.into_iter().map(|d| d as usize).collect();
+    Ok((
+        name,
+        data.and_then(|data| OwnedTensor::new(data, shape).ok()),
+    ))
+}
+
+/// Decodes the data of a model's initializers, which `Model::decode` skips so that looking at a
+/// model's structure doesn't need memory for its weights. Initializers whose data is stored
+/// externally or whose type isn't one `TensorData` can hold are left out.
+pub fn decode_initializer_data(buf: &[u8]) -> Result<Vec<(String, OwnedTensor)>, DecodeError> {
+    let mut ret = Vec::new();
+    for field in Reader::new(buf) {
+        if let (7, v) = field? {
+            for field in v.as_message()? {
+                if let (5, v) = field? {
+                    if let (name, Some(tensor)) = decode_tensor(v.as_message()?)? {
+                        ret.push((name, tensor));
+                    }
+                }
+            }
+        }
+    }
+    Ok(ret)
+}
+
+fn decode_string_pair(v: Value) -> Result<(String, String), DecodeError> {
+    let mut ret = (String::new(), String::new());
+    for field in v.as_message()? {
+        match field? {
+            (1, v) => ret.0 = v.as_string()?,
+            (2, v) => ret.1 = v.as_string()?,
+            _ => {}
+        }
+    }
+    Ok(ret)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_model() {
+        let model = Model::from_path("src/onnx/testdata/upsample.onnx").unwrap();
+        assert_eq!(model.ir_version, 7);
+        assert_eq!(model.producer_name, "tf2onnx");
+        assert_eq!(model.producer_version, "1.7.2");
+        assert_eq!(model.opset_version(), Some(12));
+
+        assert_eq!(
+            model.graph.runtime_inputs().collect::<Vec<_>>(),
+            vec![&TensorInfo {
+                name: "up_sampling2d_input:0".to_string(),
+                element_type: ElementType::Float,
+                dimensions: vec![
+                    Dimension::Symbolic("unk__31".to_string()),
+                    Dimension::Symbolic("unk__32".to_string()),
+                    Dimension::Symbolic("unk__33".to_string()),
+                    Dimension::Fixed(3),
+                ],
+            }]
+        );
+        assert_eq!(model.graph.outputs.len(), 1);
+        assert_eq!(model.graph.outputs[0].name, "Identity:0");
+
+        let roi = model
+            .graph
+            .initializers
+            .iter()
+            .find(|i| i.name == "roi__12")
+            .unwrap();
+        assert_eq!(roi.element_type, ElementType::Float);
+        assert_eq!(roi.size_in_bytes(), Some(roi.element_count().unwrap() * 4));
+
+        let huge = Initializer {
+            name: "huge".to_string(),
+            element_type: ElementType::Double,
+            dimensions: vec![i64::MAX, i64::MAX],
+            external: false,
+        };
+        assert_eq!(huge.element_count(), None);
+        assert_eq!(huge.size_in_bytes(), None);
+
+        let histogram = model.graph.op_type_histogram();
+        assert_eq!(histogram.get("Resize"), Some(&1));
+        assert_eq!(histogram.values().sum::<usize>(), model.graph.nodes.len());
+
+        assert!(Model::decode(b"\x0a\xff").is_err());
+    }
+
+    #[test]
+    fn test_attributes_and_initializer_data() {
+        let buf = std::fs::read("src/onnx/testdata/upsample.onnx").unwrap();
+        let model = Model::decode(&buf).unwrap();
+        let resize = model
+            .graph
+            .nodes
+            .iter()
+            .find(|n| n.op_type == "Resize")
+            .unwrap();
+        assert_eq!(
+            resize.attribute("mode"),
+            Some(&AttributeValue::String("nearest".to_string()))
+        );
+        assert_eq!(
+            resize.attribute("coordinate_transformation_mode"),
+            Some(&AttributeValue::String("tf_half_pixel_for_nn".to_string()))
+        );
+        let transpose = model
+            .graph
+            .nodes
+            .iter()
+            .find(|n| n.op_type == "Transpose")
+            .unwrap();
+        assert_eq!(
+            transpose.attribute("perm"),
+            Some(&AttributeValue::Ints(vec![0, 3, 1, 2]))
+        );
+
+        let initializers = decode_initializer_data(&buf).unwrap();
+        assert_eq!(initializers.len(), model.graph.initializers.len());
+        let (_, roi) = initializers.iter().find(|(n, _)| n == "roi__12").unwrap();
+        assert_eq!(roi.shape(), &[0]);
+        let (_, scale) = initializers
+            .iter()
+            .find(|(n, _)| n.ends_with("up_sampling2d/Const:0"))
+            .unwrap();
+        assert_eq!(scale.as_slice::<i32>(), Some(&[2, 2][..]));
+    }
+}
@@ -0,0 +1,113 @@
+// A minimal custom op library used by the tests. It registers a "CustomAdd" op in the "infer.test"
+// domain, which adds two float tensors of the same shape.
+//
+// test_custom_ops_library compiles this into a shared library before loading it.
+
+#include <onnxruntime_c_api.h>
+
+// Releases the status if there is one, returning whether the call failed.
+static int failed(const OrtApi* api, OrtStatus* status) {
+    if (status) {
+        api->ReleaseStatus(status);
+        return 1;
+    }
+    return 0;
+}
+
+static void* create_kernel(OrtCustomOp* op, const OrtApi* api, const OrtKernelInfo* info) {
+    // The kernel has no state of its own, so we just hand it the api.
+    return (void*)api;
+}
+
+static const char* get_name(OrtCustomOp* op) {
+    return "CustomAdd";
+}
+
+static const char* get_execution_provider_type(OrtCustomOp* op) {
+    return NULL;
+}
+
+static ONNXTensorElementDataType get_type(OrtCustomOp* op, size_t index) {
+    return ONNX_TENSOR_ELEMENT_DATA_TYPE_FLOAT;
+}
+
+static size_t get_input_type_count(OrtCustomOp* op) {
+    return 2;
+}
+
+static size_t get_output_type_count(OrtCustomOp* op) {
+    return 1;
+}
+
+static void kernel_compute(void* kernel, OrtKernelContext* context) {
+    const OrtApi* api = (const OrtApi*)kernel;
+
+    const OrtValue* x = NULL;
+    const OrtValue* y = NULL;
+    OrtTensorTypeAndShapeInfo* info = NULL;
+    if (failed(api, api->KernelContext_GetInput(context, 0, &x)) ||
+        failed(api, api->KernelContext_GetInput(context, 1, &y)) ||
+        failed(api, api->GetTensorTypeAndShape(x, &info))) {
+        return;
+    }
+
+    size_t dim_count = 0;
+    size_t element_count = 0;
+    int64_t dims[8];
+    int info_failed = failed(api, api->GetDimensionsCount(info, &dim_count)) || dim_count > 8 ||
+                      failed(api, api->GetDimensions(info, dims, dim_count)) ||
+                      failed(api, api->GetTensorShapeElementCount(info, &element_count));
+    api->ReleaseTensorTypeAndShapeInfo(info);
+    if (info_failed) {
+        return;
+    }
+
+    OrtValue* z = NULL;
+    float* x_data = NULL;
+    float* y_data = NULL;
+    float* z_data = NULL;
+    if (failed(api, api->KernelContext_GetOutput(context, 0, dims, dim_count, &z)) ||
+        failed(api, api->GetTensorMutableData((OrtValue*)x, (void**)&x_data)) ||
+        failed(api, api->GetTensorMutableData((OrtValue*)y, (void**)&y_data)) ||
+        failed(api, api->GetTensorMutableData(z, (void**)&z_data))) {
+        return;
+    }
+
+    for (size_t i = 0; i < element_count; ++i) {
+        z_data[i] = x_data[i] + y_data[i];
+    }
+}
+
+static void kernel_destroy(void* kernel) {}
+
+static OrtCustomOp custom_add = {
+    ORT_API_VERSION,
+    create_kernel,
+    get_name,
+    get_execution_provider_type,
+    get_type,
+    get_input_type_count,
+    get_type,
+    get_output_type_count,
+    kernel_compute,
+    kernel_destroy,
+};
+
+// The domain has to outlive every session using it, so it's intentionally never released.
+static OrtCustomOpDomain* domain = NULL;
+
+OrtStatus* RegisterCustomOps(OrtSessionOptions* options, const OrtApiBase* api_base) {
+    const OrtApi* api = api_base->GetApi(ORT_API_VERSION);
+    OrtStatus* status = NULL;
+    if (!domain) {
+        if ((status = api->CreateCustomOpDomain("infer.test", &domain))) {
+            return status;
+        }
+        if ((status = api->CustomOpDomain_Add(domain, &custom_add))) {
+            api->ReleaseCustomOpDomain(domain);
+            domain = NULL;
+            return status;
+        }
+    }
+    return api->AddCustomOpDomain(options, domain);
+}
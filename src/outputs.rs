@@ -0,0 +1,170 @@
+use super::{names::normalize, OutputTensor};
+use std::ops::Index;
+
+/// The outputs of `Session::run`, in the order the model declares them.
+pub struct Outputs<'a> {
+    outputs: Vec<(String, OutputTensor<'a>)>,
+}
+
+impl<'a> Outputs<'a> {
+    pub(crate) fn new(outputs: Vec<(String, OutputTensor<'a>)>) -> Self {
+        Self { outputs }
+    }
+
+    pub fn len(&self) -> usize {
+        self.outputs.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.outputs.is_empty()
+    }
+
+    pub fn get(&self, name: &str) -> Option<&OutputTensor<'a>> {
+        self.position(name).map(|i| &self.outputs[i].1)
+    }
+
+    pub fn contains(&self, name: &str) -> bool {
+        self.position(name).is_some()
+    }
+
+    pub fn names(&self) -> impl Iterator<Item = &str> {
+        self.outputs.iter().map(|(name, _)| name.as_str())
+    }
+
+    pub fn iter(&self) -> impl Iterator<Item = (&str, &OutputTensor<'a>)> {
+        self.outputs
+            .iter()
+            .map(|(name, output)| (name.as_str(), output))
+    }
+
+    /// Takes an output out of the collection, e.g. to keep it after the others are dropped. The
+    /// remaining outputs keep their order.
+    pub fn remove(&mut self, name: &str) -> Option<OutputTensor<'a>> {
+        self.position(name).map(|i| self.outputs.remove(i).1)
+    }
+
+    /// Finds an output by its exact name, or failing that, ignoring the ":0" suffix some
+    /// converters add.
+    fn position(&self, name: &str) -> Option<usize> {
+        self.outputs
+            .iter()
+            .position(|(n, _)| n == name)
+            .or_else(|| {
+                self.outputs
+                    .iter()
+                    .position(|(n, _)| normalize(n) == normalize(name))
+            })
+    }
+}
+
+/// Sorts outputs into the order a model declares them in. Outputs that aren't declared go last,
+/// sorted by name so that the order is at least stable.
+#[cfg(any(
+    test,
+    feature = "testing",
+    all(feature = "coreml", target_os = "macos")
+))]
+pub(crate) fn sort_by_declared_order<T>(outputs: &mut [(String, T)], declared: &[String]) {
+    outputs.sort_by_key(|(name, _)| {
+        let position = declared.iter().position(|n| n == name);
+        (position.unwrap_or(usize::MAX), name.clone())
+    });
+}
+
+impl<'a> Index<usize> for Outputs<'a> {
+    type Output = OutputTensor<'a>;
+
+    fn index(&self, index: usize) -> &Self::Output {
+        &self.outputs[index].1
+    }
+}
+
+impl<'a> IntoIterator for Outputs<'a> {
+    type Item = (String, OutputTensor<'a>);
+    type IntoIter = std::vec::IntoIter<Self::Item>;
+
+    fn into_iter(self) -> Self::IntoIter {
+        self.outputs.into_iter()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::OwnedTensor;
+    use std::borrow::Cow;
+
+    fn outputs() -> Outputs<'static> {
+        let tensor = |value: f32| {
+            OutputTensor::Tensor(Cow::Owned(OwnedTensor::new(vec![value], vec![1]).unwrap()))
+        };
+        Outputs::new(vec![
+            ("scores:0".to_string(), tensor(1.0)),
+            ("labels".to_string(), tensor(2.0)),
+            ("boxes".to_string(), tensor(3.0)),
+        ])
+    }
+
+    fn value(output: &OutputTensor) -> f32 {
+        output.as_slice().unwrap()[0]
+    }
+
+    #[test]
+    fn test_outputs() {
+        let mut outputs = outputs();
+        assert_eq!(outputs.len(), 3);
+        assert!(!outputs.is_empty());
+        assert_eq!(
+            outputs.names().collect::<Vec<_>>(),
+            vec!["scores:0", "labels", "boxes"]
+        );
+
+        assert_eq!(outputs.get("labels").map(value), Some(2.0));
+        assert_eq!(outputs.get("scores:0").map(value), Some(1.0));
+        assert_eq!(outputs.get("scores").map(value), Some(1.0));
+        assert_eq!(outputs.get("labels:0").map(value), Some(2.0));
+        assert!(outputs.get("missing").is_none());
+        assert!(outputs.contains("boxes"));
+        assert!(!outputs.contains("missing"));
+
+        assert_eq!(value(&outputs[0]), 1.0);
+        assert_eq!(value(&outputs[2]), 3.0);
+        assert_eq!(
+            outputs
+                .iter()
+                .map(|(name, output)| (name, value(output)))
+                .collect::<Vec<_>>(),
+            vec![("scores:0", 1.0), ("labels", 2.0), ("boxes", 3.0)]
+        );
+
+        assert_eq!(outputs.remove("scores").map(|o| value(&o)), Some(1.0));
+        assert!(outputs.remove("scores").is_none());
+        assert_eq!(outputs.names().collect::<Vec<_>>(), vec!["labels", "boxes"]);
+        assert_eq!(value(&outputs[0]), 2.0);
+
+        assert_eq!(
+            outputs
+                .into_iter()
+                .map(|(name, output)| (name, value(&output)))
+                .collect::<Vec<_>>(),
+            vec![("labels".to_string(), 2.0), ("boxes".to_string(), 3.0)]
+        );
+    }
+
+    #[test]
+    fn test_sort_by_declared_order() {
+        let mut outputs = vec![
+            ("c".to_string(), 0),
+            ("extra_b".to_string(), 1),
+            ("a".to_string(), 2),
+            ("extra_a".to_string(), 3),
+            ("b".to_string(), 4),
+        ];
+        let declared = vec!["b".to_string(), "a".to_string(), "c".to_string()];
+        sort_by_declared_order(&mut outputs, &declared);
+        assert_eq!(
+            outputs.iter().map(|(_, i)| *i).collect::<Vec<_>>(),
+            vec![4, 2, 0, 3, 1]
+        );
+    }
+}
@@ -0,0 +1,241 @@
+//! Just enough of the protobuf wire format to read ONNX and CoreML models without generated code.
+
+use std::{
+    convert::TryInto,
+    io::{self, Read, Seek, SeekFrom},
+};
+
+pub(crate) const WIRE_TYPE_VARINT: u8 = 0;
+pub(crate) const WIRE_TYPE_FIXED64: u8 = 1;
+pub(crate) const WIRE_TYPE_LENGTH_DELIMITED: u8 = 2;
+pub(crate) const WIRE_TYPE_FIXED32: u8 = 5;
+
+/// Reads a varint, returning `None` if the reader is already at its end.
+pub(crate) fn read_varint<R: Read>(r: &mut R) -> io::Result<Option<u64>> {
+    let mut ret = 0u64;
+    for i in 0..10 {
+        let mut byte = [0u8];
+        if r.read(&mut byte)? == 0 {
+            return if i == 0 {
+                Ok(None)
+            } else {
+                Err(io::ErrorKind::UnexpectedEof.into())
+            };
+        }
+        ret |= ((byte[0] & 0x7f) as u64) << (7 * i);
+        if byte[0] & 0x80 == 0 {
+            return Ok(Some(ret));
+        }
+    }
+    Err(io::Error::new(
+        io::ErrorKind::InvalidData,
+        "malformed varint",
+    ))
+}
+
+/// Iterates over the field numbers and wire types of a message's top-level fields, seeking past
+/// their values. This allows cheaply looking at large messages without reading them into memory.
+pub(crate) struct FieldScanner<R> {
+    r: R,
+    len: u64,
+}
+
+impl<R: Read + Seek> FieldScanner<R> {
+    pub fn new(mut r: R) -> io::Result<Self> {
+        let len = r.seek(SeekFrom::End(0))?;
+        r.seek(SeekFrom::Start(0))?;
+        Ok(Self { r, len })
+    }
+
+    /// Returns the next field's number and wire type.
+    pub fn next_field(&mut self) -> io::Result<Option<(u64, u8)>> {
+        let key = match read_varint(&mut self.r)? {
+            Some(key) => key,
+            None => return Ok(None),
+        };
+        let (field, wire_type) = (key >> 3, (key & 7) as u8);
+        let skip = match wire_type {
+            WIRE_TYPE_VARINT => {
+                read_varint(&mut self.r)?.ok_or(io::ErrorKind::UnexpectedEof)?;
+                0
+            }
+            WIRE_TYPE_FIXED64 => 8,
+            WIRE_TYPE_LENGTH_DELIMITED => {
+                read_varint(&mut self.r)?.ok_or(io::ErrorKind::UnexpectedEof)?
+            }
+            WIRE_TYPE_FIXED32 => 4,
+            _ => {
+                return Err(io::Error::new(
+                    io::ErrorKind::InvalidData,
+                    "unsupported wire type",
+                ))
+            }
+        };
+        let pos = self.r.stream_position()?;
+        match pos.checked_add(skip) {
+            Some(end) if end <= self.len => {
+                self.r.seek(SeekFrom::Start(end))?;
+            }
+            _ => return Err(io::ErrorKind::UnexpectedEof.into()),
+        }
+        Ok(Some((field, wire_type)))
+    }
+}
+
+#[derive(thiserror::Error, Debug)]
+#[error("malformed protobuf: {0}")]
+pub struct DecodeError(&'static str);
+
+/// A field's value, as far as it can be determined from the wire format alone.
+#[derive(Clone, Copy)]
+pub(crate) enum Value<'a> {
+    Varint(u64),
+    Fixed64(u64),
+    Bytes(&'a [u8]),
+    Fixed32(u32),
+}
+
+impl<'a> Value<'a> {
+    pub fn as_u64(self) -> Result<u64, DecodeError> {
+        match self {
+            Self::Varint(v) | Self::Fixed64(v) => Ok(v),
+            Self::Fixed32(v) => Ok(v as _),
+            Self::Bytes(_) => Err(DecodeError("expected a number")),
+        }
+    }
+
+    pub fn as_i64(self) -> Result<i64, DecodeError> {
+        self.as_u64().map(|v| v as _)
+    }
+
+    pub fn as_bytes(self) -> Result<&'a [u8], DecodeError> {
+        match self {
+            Self::Bytes(b) => Ok(b),
+            _ => Err(DecodeError("expected a length-delimited field")),
+        }
+    }
+
+    pub fn as_str(self) -> Result<&'a str, DecodeError> {
+        std::str::from_utf8(self.as_bytes()?).map_err(|_| DecodeError("malformed string"))
+    }
+
+    pub fn as_string(self) -> Result<String, DecodeError> {
+        self.as_str().map(|s| s.to_string())
+    }
+
+    /// Returns a reader for an embedded message.
+    pub fn as_message(self) -> Result<Reader<'a>, DecodeError> {
+        self.as_bytes().map(Reader::new)
+    }
+
+    /// Appends the value(s) of a repeated fixed32 field (e.g. `float`), which may or may not be
+    /// packed.
+    pub fn extend_fixed32s(self, dest: &mut Vec<u32>) -> Result<(), DecodeError> {
+        match self {
+            Self::Bytes(b) if b.len() % 4 == 0 => dest
+                .extend(b.chunks_exact(4).map(|c| {
+                    u32::from_le_bytes(c.try_into().expect("chunks should have 4 bytes"))
+                })),
+            Self::Fixed32(v) => dest.push(v),
+            _ => return Err(DecodeError("expected fixed32 values")),
+        }
+        Ok(())
+    }
+
+    /// Appends the value(s) of a repeated fixed64 field (e.g. `double`), which may or may not be
+    /// packed.
+    pub fn extend_fixed64s(self, dest: &mut Vec<u64>) -> Result<(), DecodeError> {
+        match self {
+            Self::Bytes(b) if b.len() % 8 == 0 => dest
+                .extend(b.chunks_exact(8).map(|c| {
+                    u64::from_le_bytes(c.try_into().expect("chunks should have 8 bytes"))
+                })),
+            Self::Fixed64(v) => dest.push(v),
+            _ => return Err(DecodeError("expected fixed64 values")),
+        }
+        Ok(())
+    }
+
+    /// Appends the value(s) of a repeated int64 field, which may or may not be packed.
+    pub fn extend_i64s(self, dest: &mut Vec<i64>) -> Result<(), DecodeError> {
+        match self {
+            Self::Bytes(b) => {
+                let mut r = Reader::new(b);
+                while !r.buf.is_empty() {
+                    dest.push(r.varint()? as _);
+                }
+            }
+            _ => dest.push(self.as_i64()?),
+        }
+        Ok(())
+    }
+}
+
+/// Iterates over the fields of a message that has been read into memory.
+pub(crate) struct Reader<'a> {
+    buf: &'a [u8],
+}
+
+impl<'a> Reader<'a> {
+    pub fn new(buf: &'a [u8]) -> Self {
+        Self { buf }
+    }
+
+    fn take(&mut self, n: usize) -> Result<&'a [u8], DecodeError> {
+        if n > self.buf.len() {
+            return Err(DecodeError("unexpected end of message"));
+        }
+        let (ret, rest) = self.buf.split_at(n);
+        self.buf = rest;
+        Ok(ret)
+    }
+
+    fn varint(&mut self) -> Result<u64, DecodeError> {
+        read_varint(&mut self.buf)
+            .ok()
+            .flatten()
+            .ok_or(DecodeError("malformed varint"))
+    }
+
+    fn next_field(&mut self) -> Result<(u64, Value<'a>), DecodeError> {
+        let key = self.varint()?;
+        let value = match (key & 7) as u8 {
+            WIRE_TYPE_VARINT => Value::Varint(self.varint()?),
+            WIRE_TYPE_FIXED64 => {
+                let mut b = [0u8; 8];
+                b.copy_from_slice(self.take(8)?);
+                Value::Fixed64(u64::from_le_bytes(b))
+            }
+            WIRE_TYPE_LENGTH_DELIMITED => {
+                let len = self.varint()?;
+                if len > self.buf.len() as u64 {
+                    return Err(DecodeError("unexpected end of message"));
+                }
+                Value::Bytes(self.take(len as _)?)
+            }
+            WIRE_TYPE_FIXED32 => {
+                let mut b = [0u8; 4];
+                b.copy_from_slice(self.take(4)?);
+                Value::Fixed32(u32::from_le_bytes(b))
+            }
+            _ => return Err(DecodeError("unsupported wire type")),
+        };
+        Ok((key >> 3, value))
+    }
+}
+
+impl<'a> Iterator for Reader<'a> {
+    type Item = Result<(u64, Value<'a>), DecodeError>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        if self.buf.is_empty() {
+            None
+        } else {
+            let ret = self.next_field();
+            if ret.is_err() {
+                self.buf = &[];
+            }
+            Some(ret)
+        }
+    }
+}
@@ -0,0 +1,594 @@
+//! A pure-Rust interpreter for a subset of ONNX ops. It's much slower than ORT, but it needs no
+//! native libraries and its implementations are simple enough to check by eye, so it also serves
+//! as a numerical reference for other backends' outputs.
+//!
+//! Elementwise and shape ops support the common integer and float types. Ops that do arithmetic
+//! on windows or matrices (convolutions, pooling, matrix products, softmax and linear resizing)
+//! only support `f32`.
+
+mod ops;
+
+use super::{
+    onnx::proto::{self, DecodeError},
+    Backend, Model, NewSessionError, OutputTensor, OwnedTensor, SessionInfoError, SessionInput,
+    SessionOptions, SessionRunError, Signature,
+};
+use std::{borrow::Cow, collections::HashMap, io, path::Path};
+
+#[derive(thiserror::Error, Debug)]
+pub enum ReferenceError {
+    #[error("unable to read model: {0}")]
+    Io(#[from] io::Error),
+    #[error(transparent)]
+    Decode(#[from] DecodeError),
+    #[error("op {0} isn't supported by the reference backend")]
+    UnsupportedOp(String),
+    #[error("initializer {0} is stored externally or has an unsupported element type")]
+    UnsupportedInitializer(String),
+    #[error("missing input {0}")]
+    MissingInput(String),
+    #[error("the model has no input named {0}")]
+    UnknownInput(String),
+    #[error("{op_type} node {node:?}: {message}")]
+    Op {
+        node: String,
+        op_type: String,
+        message: String,
+    },
+}
+
+/// Loads ONNX models into `ReferenceModel`s. Without the onnx feature, it's the built-in backend
+/// for ONNX models. Otherwise, register it with `Environment::register_backend` to use it instead
+/// of ORT.
+pub struct ReferenceBackend;
+
+impl Backend for ReferenceBackend {
+    fn load<'a>(
+        &'a self,
+        path: &Path,
+        _options: &SessionOptions,
+    ) -> Result<Box<dyn Model<'a> + 'a>, NewSessionError> {
+        let model =
+            ReferenceModel::from_path(path).map_err(|e| NewSessionError::Backend(Box::new(e)))?;
+        Ok(Box::new(model))
+    }
+}
+
+/// An ONNX model whose ops are all supported by the reference backend.
+pub struct ReferenceModel {
+    model: proto::Model,
+    initializers: HashMap<String, OwnedTensor>,
+    opset: i64,
+}
+
+impl ReferenceModel {
+    pub fn from_path<P: AsRef<Path>>(path: P) -> Result<Self, ReferenceError> {
+        Self::decode(&std::fs::read(path)?)
+    }
+
+    /// Decodes a model, checking that all of its ops and initializers are supported.
+    pub fn decode(buf: &[u8]) -> Result<Self, ReferenceError> {
+        let model = proto::Model::decode(buf)?;
+        if let Some(node) = model.graph.nodes.iter().find(|n| !ops::is_supported(n)) {
+            return Err(ReferenceError::UnsupportedOp(match node.domain.as_str() {
+                "" | "ai.onnx" => node.op_type.clone(),
+                domain => format!("{}.{}", domain, node.op_type),
+            }));
+        }
+        let initializers: HashMap<_, _> =
+            proto::decode_initializer_data(buf)?.into_iter().collect();
+        if let Some(initializer) = model
+            .graph
+            .initializers
+            .iter()
+            .find(|i| !initializers.contains_key(&i.name))
+        {
+            return Err(ReferenceError::UnsupportedInitializer(
+                initializer.name.clone(),
+            ));
+        }
+        Ok(Self {
+            // Models that don't import the default operator set can't use any of its ops.
+            opset: model.opset_version().unwrap_or(1),
+            model,
+            initializers,
+        })
+    }
+
+    pub fn model(&self) -> &proto::Model {
+        &self.model
+    }
+
+    /// Runs the model's nodes in order, returning its outputs in the order it declares them.
+    /// Inputs that are also initializers override them.
+    pub fn evaluate(
+        &self,
+        inputs: &[(&str, &OwnedTensor)],
+    ) -> Result<Vec<(String, OwnedTensor)>, ReferenceError> {
+        let graph = &self.model.graph;
+        let mut values: HashMap<&str, Cow<OwnedTensor>> = self
+            .initializers
+            .iter()
+            .map(|(name, tensor)| (name.as_str(), Cow::Borrowed(tensor)))
+            .collect();
+        for (name, tensor) in inputs {
+            if !graph.inputs.iter().any(|input| input.name == *name) {
+                return Err(ReferenceError::UnknownInput(name.to_string()));
+            }
+            values.insert(name, Cow::Borrowed(*tensor));
+        }
+        if let Some(input) = graph
+            .inputs
+            .iter()
+            .find(|input| !values.contains_key(input.name.as_str()))
+        {
+            return Err(ReferenceError::MissingInput(input.name.clone()));
+        }
+
+        for node in &graph.nodes {
+            let op_error = |message: String| ReferenceError::Op {
+                node: node.name.clone(),
+                op_type: node.op_type.clone(),
+                message,
+            };
+            // Optional inputs that are left out have empty names.
+            let node_inputs = node
+                .inputs
+                .iter()
+                .map(|name| match name.as_str() {
+                    "" => Ok(None),
+                    name => values
+                        .get(name)
+                        .map(|tensor| Some(tensor.as_ref()))
+                        .ok_or_else(|| op_error(format!("input {} isn't available", name))),
+                })
+                .collect::<Result<Vec<_>, _>>()?;
+            let outputs = ops::run(node, &node_inputs, self.opset).map_err(op_error)?;
+            for (name, tensor) in node.outputs.iter().zip(outputs) {
+                if !name.is_empty() {
+                    values.insert(name, Cow::Owned(tensor));
+                }
+            }
+        }
+
+        graph
+            .outputs
+            .iter()
+            .map(|output| {
+                let tensor =
+                    values
+                        .get(output.name.as_str())
+                        .ok_or_else(|| ReferenceError::Op {
+                            node: String::new(),
+                            op_type: "output".to_string(),
+                            message: format!("{} isn't produced by any node", output.name),
+                        })?;
+                Ok((output.name.clone(), tensor.as_ref().clone()))
+            })
+            .collect()
+    }
+}
+
+impl<'a> Model<'a> for ReferenceModel {
+    fn backend_name(&self) -> &'static str {
+        "reference"
+    }
+
+    fn signature(&self) -> Result<Signature, SessionInfoError> {
+        Ok(Signature::from_onnx(&self.model))
+    }
+
+    fn metadata(&self) -> Result<Vec<(String, String)>, SessionInfoError> {
+        let model = &self.model;
+        Ok(vec![
+            ("producer_name", model.producer_name.clone()),
+            ("graph_name", model.graph.name.clone()),
+            ("domain", model.domain.clone()),
+            ("description", model.doc_string.clone()),
+            ("version", model.model_version.to_string()),
+        ]
+        .into_iter()
+        .map(|(k, v)| (k.to_string(), v))
+        .chain(model.metadata.iter().cloned())
+        .collect())
+    }
+
+    fn run(
+        &self,
+        inputs: &[(&str, SessionInput<'_>)],
+    ) -> Result<Vec<(String, OutputTensor<'a>)>, SessionRunError> {
+        let tensors = inputs
+            .iter()
+            .map(|(name, input)| input.as_tensor(name))
+            .collect::<Result<Vec<_>, _>>()?;
+        let inputs: Vec<_> = inputs
+            .iter()
+            .zip(&tensors)
+            .map(|((name, _), tensor)| (*name, tensor.as_ref()))
+            .collect();
+        let outputs = self
+            .evaluate(&inputs)
+            .map_err(|e| SessionRunError::Backend(Box::new(e)))?;
+        Ok(outputs
+            .into_iter()
+            .map(|(name, tensor)| (name, OutputTensor::Tensor(Cow::Owned(tensor))))
+            .collect())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::onnx::proto::{Attribute, AttributeValue, Node};
+
+    const UPSAMPLED: [f32; 24] = [
+        1., 2., 3., 1., 2., 3., 3., 4., 5., 3., 4., 5., 1., 2., 3., 1., 2., 3., 3., 4., 5., 3., 4.,
+        5.,
+    ];
+
+    fn node(op_type: &str, attributes: Vec<(&str, AttributeValue)>) -> Node {
+        Node {
+            name: "test".to_string(),
+            op_type: op_type.to_string(),
+            domain: String::new(),
+            inputs: Vec::new(),
+            outputs: vec!["y".to_string()],
+            attributes: attributes
+                .into_iter()
+                .map(|(name, value)| Attribute {
+                    name: name.to_string(),
+                    value,
+                })
+                .collect(),
+        }
+    }
+
+    fn run_op(node: &Node, inputs: &[&OwnedTensor]) -> OwnedTensor {
+        let inputs: Vec<_> = inputs.iter().copied().map(Some).collect();
+        ops::run(node, &inputs, 13).unwrap().remove(0)
+    }
+
+    #[test]
+    fn test_upsample() {
+        let model = ReferenceModel::from_path("src/onnx/testdata/upsample.onnx").unwrap();
+        let input = OwnedTensor::new(vec![1f32, 2., 3., 3., 4., 5.], vec![1, 1, 2, 3]).unwrap();
+        let outputs = model
+            .evaluate(&[("up_sampling2d_input:0", &input)])
+            .unwrap();
+        assert_eq!(outputs.len(), 1);
+        assert_eq!(outputs[0].0, "Identity:0");
+        assert_eq!(outputs[0].1.shape(), &[1, 2, 4, 3]);
+        assert_eq!(outputs[0].1.as_slice::<f32>().unwrap(), &UPSAMPLED[..]);
+
+        assert!(matches!(
+            model.evaluate(&[]),
+            Err(ReferenceError::MissingInput(name)) if name == "up_sampling2d_input:0"
+        ));
+        assert!(matches!(
+            model.evaluate(&[("x", &input)]),
+            Err(ReferenceError::UnknownInput(name)) if name == "x"
+        ));
+        assert!(matches!(
+            ReferenceModel::from_path("src/onnx/testdata/custom_op.onnx"),
+            Err(ReferenceError::UnsupportedOp(_))
+        ));
+    }
+
+    #[test]
+    fn test_session() {
+        let mut env = crate::Environment::new().unwrap();
+        env.register_backend(crate::Format::ONNX, ReferenceBackend);
+        let sess = env.new_session("src/onnx/testdata/upsample.onnx").unwrap();
+        assert_eq!(sess.backend_name(), "reference");
+        let input = [1f32, 2., 3., 3., 4., 5.];
+        let outputs = sess
+            .run(vec![(
+                "up_sampling2d_input",
+                crate::InputTensor::new(&input, &[1, 1, 2, 3]),
+            )])
+            .unwrap();
+        assert_eq!(outputs[0].shape(), &[1, 2, 4, 3]);
+        assert_eq!(outputs[0].as_slice(), Some(&UPSAMPLED[..]));
+    }
+
+    #[cfg(feature = "onnx")]
+    #[test]
+    fn test_matches_onnx() {
+        let env = crate::Environment::new().unwrap();
+        let ort = env.new_session("src/onnx/testdata/upsample.onnx").unwrap();
+        let reference = ReferenceModel::from_path("src/onnx/testdata/upsample.onnx").unwrap();
+        let input = OwnedTensor::new(
+            (0..24).map(|i| i as f32 * 0.5).collect::<Vec<_>>(),
+            vec![1, 2, 4, 3],
+        )
+        .unwrap();
+        let expected = ort
+            .run(vec![("up_sampling2d_input:0", &input)])
+            .unwrap()
+            .remove("Identity:0")
+            .unwrap()
+            .to_owned_tensor()
+            .unwrap();
+        let outputs = reference
+            .evaluate(&[("up_sampling2d_input:0", &input)])
+            .unwrap();
+        assert_eq!(outputs[0].1, expected);
+    }
+
+    #[test]
+    fn test_conv() {
+        let x = OwnedTensor::new(
+            (1..=9).map(|v| v as f32).collect::<Vec<_>>(),
+            vec![1, 1, 3, 3],
+        )
+        .unwrap();
+        let w = OwnedTensor::new(vec![1f32; 4], vec![1, 1, 2, 2]).unwrap();
+        let conv = node(
+            "Conv",
+            vec![("pads", AttributeValue::Ints(vec![0, 0, 1, 1]))],
+        );
+        let y = run_op(&conv, &[&x, &w]);
+        assert_eq!(y.shape(), &[1, 1, 3, 3]);
+        assert_eq!(
+            y.as_slice::<f32>().unwrap(),
+            &[12., 16., 9., 24., 28., 15., 15., 17., 9.]
+        );
+
+        let pool = node(
+            "AveragePool",
+            vec![
+                ("kernel_shape", AttributeValue::Ints(vec![2, 2])),
+                ("strides", AttributeValue::Ints(vec![2, 2])),
+                ("ceil_mode", AttributeValue::Int(1)),
+            ],
+        );
+        let y = run_op(&pool, &[&x]);
+        assert_eq!(y.shape(), &[1, 1, 2, 2]);
+        assert_eq!(y.as_slice::<f32>().unwrap(), &[3., 4.5, 7.5, 9.]);
+    }
+
+    #[test]
+    fn test_matmul() {
+        let a = OwnedTensor::new(vec![1f32, 2., 3., 4., 5., 6.], vec![2, 3]).unwrap();
+        let b = OwnedTensor::new(vec![1f32, 0., 1.], vec![3]).unwrap();
+        let y = run_op(&node("MatMul", vec![]), &[&a, &b]);
+        assert_eq!(y.shape(), &[2]);
+        assert_eq!(y.as_slice::<f32>().unwrap(), &[4., 10.]);
+
+        let c = OwnedTensor::new(vec![1f32, -1.], vec![2]).unwrap();
+        let gemm = node("Gemm", vec![("transB", AttributeValue::Int(1))]);
+        let y = run_op(&gemm, &[&a, &a, &c]);
+        assert_eq!(y.as_slice::<f32>().unwrap(), &[15., 31., 33., 76.]);
+    }
+
+    #[test]
+    fn test_elementwise() {
+        let a = OwnedTensor::new(vec![1i64, 2, 3, 4], vec![2, 2]).unwrap();
+        let b = OwnedTensor::new(vec![10i64, 20], vec![2, 1]).unwrap();
+        let y = run_op(&node("Add", vec![]), &[&a, &b]);
+        assert_eq!(y.as_slice::<i64>().unwrap(), &[11, 12, 23, 24]);
+
+        let y = run_op(&node("Transpose", vec![]), &[&a]);
+        assert_eq!(y.as_slice::<i64>().unwrap(), &[1, 3, 2, 4]);
+
+        let x = OwnedTensor::new(vec![0f32, 0., 1., 1.], vec![2, 2]).unwrap();
+        let y = run_op(&node("Softmax", vec![]), &[&x]);
+        assert_eq!(y.as_slice::<f32>().unwrap(), &[0.5; 4]);
+    }
+
+    fn ints(v: Vec<i64>) -> OwnedTensor {
+        let len = v.len();
+        OwnedTensor::new(v, vec![len]).unwrap()
+    }
+
+    fn assert_close(actual: &OwnedTensor, expected: &[f32]) {
+        let actual = actual.as_slice::<f32>().unwrap();
+        assert_eq!(actual.len(), expected.len(), "{:?}", actual);
+        for (a, e) in actual.iter().zip(expected) {
+            assert!((a - e).abs() < 1e-5, "{:?} != {:?}", actual, expected);
+        }
+    }
+
+    #[test]
+    fn test_slice() {
+        let x = OwnedTensor::new((0..12).collect::<Vec<i64>>(), vec![3, 4]).unwrap();
+        let slice = node("Slice", vec![]);
+        let y = run_op(&slice, &[&x, &ints(vec![0, 1]), &ints(vec![2, 3])]);
+        assert_eq!(y.shape(), &[2, 2]);
+        assert_eq!(y.as_slice::<i64>().unwrap(), &[1, 2, 5, 6]);
+
+        let (axes, steps) = (ints(vec![1]), ints(vec![-1]));
+        let y = run_op(
+            &slice,
+            &[&x, &ints(vec![-1]), &ints(vec![i64::MIN]), &axes, &steps],
+        );
+        assert_eq!(y.shape(), &[3, 4]);
+        assert_eq!(
+            y.as_slice::<i64>().unwrap(),
+            &[3, 2, 1, 0, 7, 6, 5, 4, 11, 10, 9, 8]
+        );
+
+        let y = run_op(
+            &slice,
+            &[&x, &ints(vec![0]), &ints(vec![100]), &axes, &ints(vec![2])],
+        );
+        assert_eq!(y.as_slice::<i64>().unwrap(), &[0, 2, 4, 6, 8, 10]);
+
+        let empty = OwnedTensor::new(Vec::<f32>::new(), vec![0, 2]).unwrap();
+        let y = run_op(
+            &slice,
+            &[
+                &empty,
+                &ints(vec![-1]),
+                &ints(vec![i64::MIN]),
+                &ints(vec![0]),
+                &steps,
+            ],
+        );
+        assert_eq!(y.shape(), &[0, 2]);
+
+        let zero_step = ints(vec![0]);
+        let inputs = [&x, &ints(vec![0]), &ints(vec![1]), &axes, &zero_step];
+        let inputs: Vec<_> = inputs.iter().copied().map(Some).collect();
+        assert!(ops::run(&slice, &inputs, 13).is_err());
+    }
+
+    #[test]
+    fn test_gather() {
+        let x = OwnedTensor::new(vec![1i64, 2, 3, 4, 5, 6], vec![3, 2]).unwrap();
+        let indices = OwnedTensor::new(vec![0i64, 1, 1, 2], vec![2, 2]).unwrap();
+        let y = run_op(&node("Gather", vec![]), &[&x, &indices]);
+        assert_eq!(y.shape(), &[2, 2, 2]);
+        assert_eq!(y.as_slice::<i64>().unwrap(), &[1, 2, 3, 4, 3, 4, 5, 6]);
+
+        let gather = node("Gather", vec![("axis", AttributeValue::Int(1))]);
+        let y = run_op(&gather, &[&x, &ints(vec![-1])]);
+        assert_eq!(y.shape(), &[3, 1]);
+        assert_eq!(y.as_slice::<i64>().unwrap(), &[2, 4, 6]);
+
+        let out_of_range = ints(vec![2]);
+        assert!(ops::run(&gather, &[Some(&x), Some(&out_of_range)], 13).is_err());
+    }
+
+    #[test]
+    fn test_concat() {
+        let a = OwnedTensor::new(vec![1f32, 2.], vec![2, 1]).unwrap();
+        let b = OwnedTensor::new(vec![3f32, 4., 5., 6.], vec![2, 2]).unwrap();
+        let concat = node("Concat", vec![("axis", AttributeValue::Int(-1))]);
+        let y = run_op(&concat, &[&a, &b]);
+        assert_eq!(y.shape(), &[2, 3]);
+        assert_eq!(y.as_slice::<f32>().unwrap(), &[1., 3., 4., 2., 5., 6.]);
+
+        let concat = node("Concat", vec![("axis", AttributeValue::Int(0))]);
+        let c = OwnedTensor::new(vec![7f32, 8.], vec![1, 2]).unwrap();
+        let y = run_op(&concat, &[&b, &c]);
+        assert_eq!(y.shape(), &[3, 2]);
+        assert_eq!(y.as_slice::<f32>().unwrap(), &[3., 4., 5., 6., 7., 8.]);
+
+        assert!(ops::run(&concat, &[Some(&a), Some(&b)], 13).is_err());
+        let ints = OwnedTensor::new(vec![1i64, 2], vec![1, 2]).unwrap();
+        assert!(ops::run(&concat, &[Some(&c), Some(&ints)], 13).is_err());
+    }
+
+    #[test]
+    fn test_reshape() {
+        let x = OwnedTensor::new((0..6).collect::<Vec<i64>>(), vec![2, 3]).unwrap();
+        let reshape = node("Reshape", vec![]);
+        let y = run_op(&reshape, &[&x, &ints(vec![3, -1])]);
+        assert_eq!(y.shape(), &[3, 2]);
+        assert_eq!(y.data(), x.data());
+
+        let y = run_op(&reshape, &[&x, &ints(vec![0, -1, 1])]);
+        assert_eq!(y.shape(), &[2, 3, 1]);
+
+        let shape = ints(vec![4, -1]);
+        assert!(ops::run(&reshape, &[Some(&x), Some(&shape)], 13).is_err());
+    }
+
+    #[test]
+    fn test_cast() {
+        let x = OwnedTensor::new(vec![1.5f32, -2.7, 0.], vec![3]).unwrap();
+        let cast = |to| node("Cast", vec![("to", AttributeValue::Int(to))]);
+        // The element types' numbers in onnx.proto.
+        let y = run_op(&cast(6), &[&x]);
+        assert_eq!(y.as_slice::<i32>().unwrap(), &[1, -2, 0]);
+        let y = run_op(&cast(9), &[&x]);
+        assert_eq!(y.as_slice::<bool>().unwrap(), &[true, true, false]);
+        let y = run_op(&cast(1), &[&ints(vec![3, -4])]);
+        assert_eq!(y.as_slice::<f32>().unwrap(), &[3., -4.]);
+        assert_eq!(run_op(&cast(1), &[&x]), x);
+    }
+
+    #[test]
+    fn test_batch_normalization() {
+        let x = OwnedTensor::new(vec![1f32, 2., 3., 4.], vec![1, 2, 1, 2]).unwrap();
+        let params = |v: Vec<f32>| OwnedTensor::new(v, vec![2]).unwrap();
+        let (scale, bias) = (params(vec![1., 2.]), params(vec![0., 1.]));
+        let (mean, var) = (params(vec![1., 3.]), params(vec![1., 4.]));
+        let batch_norm = node(
+            "BatchNormalization",
+            vec![("epsilon", AttributeValue::Float(0.))],
+        );
+        let y = run_op(&batch_norm, &[&x, &scale, &bias, &mean, &var]);
+        assert_eq!(y.shape(), &[1, 2, 1, 2]);
+        assert_eq!(y.as_slice::<f32>().unwrap(), &[0., 1., 1., 2.]);
+    }
+
+    #[test]
+    fn test_squeeze_and_unsqueeze() {
+        let x = OwnedTensor::new(vec![1f32, 2., 3.], vec![1, 3, 1]).unwrap();
+        let squeeze = node("Squeeze", vec![]);
+        assert_eq!(run_op(&squeeze, &[&x]).shape(), &[3]);
+        assert_eq!(run_op(&squeeze, &[&x, &ints(vec![-1])]).shape(), &[1, 3]);
+        let axes = ints(vec![1]);
+        assert!(ops::run(&squeeze, &[Some(&x), Some(&axes)], 13).is_err());
+
+        let v = OwnedTensor::new(vec![1f32, 2., 3.], vec![3]).unwrap();
+        let y = run_op(&node("Unsqueeze", vec![]), &[&v, &ints(vec![0, -1])]);
+        assert_eq!(y, x);
+
+        // Before opset 13, the axes are an attribute.
+        let unsqueeze = node("Unsqueeze", vec![("axes", AttributeValue::Ints(vec![1]))]);
+        let y = ops::run(&unsqueeze, &[Some(&v)], 11).unwrap().remove(0);
+        assert_eq!(y.shape(), &[3, 1]);
+    }
+
+    #[test]
+    fn test_resize() {
+        let x = OwnedTensor::new(vec![1f32, 2.], vec![1, 1, 1, 2]).unwrap();
+        let roi = OwnedTensor::new(Vec::<f32>::new(), vec![0]).unwrap();
+        let scales = OwnedTensor::new(vec![1f32, 1., 1., 2.], vec![4]).unwrap();
+        let resize = |coordinate_mode: &str| {
+            node(
+                "Resize",
+                vec![
+                    ("mode", AttributeValue::String("linear".to_string())),
+                    (
+                        "coordinate_transformation_mode",
+                        AttributeValue::String(coordinate_mode.to_string()),
+                    ),
+                ],
+            )
+        };
+
+        let y = run_op(&resize("half_pixel"), &[&x, &roi, &scales]);
+        assert_eq!(y.shape(), &[1, 1, 1, 4]);
+        assert_close(&y, &[1., 1.25, 1.75, 2.]);
+
+        let y = run_op(&resize("align_corners"), &[&x, &roi, &scales]);
+        assert_close(&y, &[1., 4. / 3., 5. / 3., 2.]);
+
+        let y = run_op(&resize("asymmetric"), &[&x, &roi, &scales]);
+        assert_close(&y, &[1., 1.5, 2., 2.]);
+
+        // Sizes are used instead of scales when the scales are empty.
+        let sizes = ints(vec![1, 1, 1, 4]);
+        let y = run_op(&resize("half_pixel"), &[&x, &roi, &roi, &sizes]);
+        assert_close(&y, &[1., 1.25, 1.75, 2.]);
+
+        // Linear interpolation is separable, so 2-D resizing blends both axes.
+        let x = OwnedTensor::new(vec![1f32, 2., 3., 4.], vec![1, 1, 2, 2]).unwrap();
+        let scales = OwnedTensor::new(vec![1f32, 1., 2., 2.], vec![4]).unwrap();
+        let y = run_op(&resize("align_corners"), &[&x, &roi, &scales]);
+        assert_eq!(y.shape(), &[1, 1, 4, 4]);
+        let expected: Vec<f32> = (0..16)
+            .map(|i| 1. + (i % 4) as f32 / 3. + 2. * (i / 4) as f32 / 3.)
+            .collect();
+        assert_close(&y, &expected);
+    }
+
+    #[test]
+    fn test_pool_empty_input() {
+        let x = OwnedTensor::new(Vec::<f32>::new(), vec![1, 1, 0, 0]).unwrap();
+        let pool = node(
+            "AveragePool",
+            vec![
+                ("kernel_shape", AttributeValue::Ints(vec![1, 1])),
+                ("pads", AttributeValue::Ints(vec![1, 1, 1, 1])),
+            ],
+        );
+        let y = run_op(&pool, &[&x]);
+        assert_eq!(y.shape(), &[1, 1, 2, 2]);
+        assert_eq!(y.as_slice::<f32>().unwrap(), &[0.; 4]);
+    }
+}
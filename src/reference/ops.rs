@@ -0,0 +1,1223 @@
+//! The reference backend's op implementations. Each takes a node's inputs (`None` for optional
+//! inputs that are left out) and returns its outputs. Errors are messages that
+//! `ReferenceModel::evaluate` attaches the node to.
+//!
+//! Sums of products are accumulated in `f64`, so results may differ from other backends' in the
+//! last few bits, but they're at least as accurate.
+
+use crate::{
+    onnx::proto::{AttributeValue, ElementType, Node},
+    tensor::map_data,
+    Element, OwnedTensor, TensorData,
+};
+use half::{bf16, f16};
+
+type OpResult = Result<Vec<OwnedTensor>, String>;
+
+const SUPPORTED_OPS: &[&str] = &[
+    "Add",
+    "AveragePool",
+    "BatchNormalization",
+    "Cast",
+    "Concat",
+    "Constant",
+    "Conv",
+    "Div",
+    "Flatten",
+    "Gather",
+    "Gemm",
+    "GlobalAveragePool",
+    "GlobalMaxPool",
+    "Identity",
+    "MatMul",
+    "MaxPool",
+    "Mul",
+    "Relu",
+    "Reshape",
+    "Resize",
+    "Shape",
+    "Sigmoid",
+    "Slice",
+    "Softmax",
+    "Squeeze",
+    "Sub",
+    "Tanh",
+    "Transpose",
+    "Unsqueeze",
+    "Upsample",
+];
+
+pub(super) fn is_supported(node: &Node) -> bool {
+    matches!(node.domain.as_str(), "" | "ai.onnx") && SUPPORTED_OPS.contains(&node.op_type.as_str())
+}
+
+pub(super) fn run(node: &Node, inputs: &[Option<&OwnedTensor>], opset: i64) -> OpResult {
+    let ctx = Context {
+        node,
+        inputs,
+        opset,
+    };
+    match node.op_type.as_str() {
+        "Identity" => Ok(vec![ctx.input(0)?.clone()]),
+        "Constant" => constant(&ctx),
+        "Shape" => shape(&ctx),
+        "Cast" => cast(&ctx),
+        "Reshape" => reshape(&ctx),
+        "Flatten" => flatten(&ctx),
+        "Squeeze" => squeeze(&ctx),
+        "Unsqueeze" => unsqueeze(&ctx),
+        "Transpose" => transpose(&ctx),
+        "Concat" => concat(&ctx),
+        "Slice" => slice(&ctx),
+        "Gather" => gather(&ctx),
+        "Add" | "Sub" | "Mul" | "Div" => arithmetic(&ctx),
+        "Relu" => unary(&ctx, |x| x.max(0.0)),
+        "Sigmoid" => unary(&ctx, |x| 1.0 / (1.0 + (-x).exp())),
+        "Tanh" => unary(&ctx, f32::tanh),
+        "Softmax" => softmax(&ctx),
+        "MatMul" => matmul(&ctx),
+        "Gemm" => gemm(&ctx),
+        "Conv" => conv(&ctx),
+        "BatchNormalization" => batch_normalization(&ctx),
+        "MaxPool" => pool(&ctx, true),
+        "AveragePool" => pool(&ctx, false),
+        "GlobalMaxPool" => global_pool(&ctx, true),
+        "GlobalAveragePool" => global_pool(&ctx, false),
+        "Resize" => resize(&ctx),
+        "Upsample" => upsample(&ctx),
+        op_type => Err(format!("unsupported op {}", op_type)),
+    }
+}
+
+struct Context<'n> {
+    node: &'n Node,
+    inputs: &'n [Option<&'n OwnedTensor>],
+    opset: i64,
+}
+
+impl<'n> Context<'n> {
+    fn input(&self, i: usize) -> Result<&'n OwnedTensor, String> {
+        self.optional_input(i)
+            .ok_or_else(|| format!("missing input {}", i))
+    }
+
+    fn optional_input(&self, i: usize) -> Option<&'n OwnedTensor> {
+        self.inputs.get(i).copied().flatten()
+    }
+
+    /// Returns an optional input that some exporters pass as an empty tensor instead of leaving
+    /// out, as `None` if it's empty.
+    fn nonempty_input(&self, i: usize) -> Option<&'n OwnedTensor> {
+        self.optional_input(i).filter(|t| !t.data().is_empty())
+    }
+
+    fn int(&self, name: &str, default: i64) -> Result<i64, String> {
+        match self.node.attribute(name) {
+            None => Ok(default),
+            Some(AttributeValue::Int(v)) => Ok(*v),
+            Some(_) => Err(format!("attribute {} should be an int", name)),
+        }
+    }
+
+    fn required_int(&self, name: &str) -> Result<i64, String> {
+        match self.node.attribute(name) {
+            Some(AttributeValue::Int(v)) => Ok(*v),
+            Some(_) => Err(format!("attribute {} should be an int", name)),
+            None => Err(format!("missing attribute {}", name)),
+        }
+    }
+
+    fn float(&self, name: &str, default: f32) -> Result<f32, String> {
+        match self.node.attribute(name) {
+            None => Ok(default),
+            Some(AttributeValue::Float(v)) => Ok(*v),
+            Some(_) => Err(format!("attribute {} should be a float", name)),
+        }
+    }
+
+    fn string(&self, name: &str, default: &'n str) -> Result<&'n str, String> {
+        match self.node.attribute(name) {
+            None => Ok(default),
+            Some(AttributeValue::String(v)) => Ok(v),
+            Some(_) => Err(format!("attribute {} should be a string", name)),
+        }
+    }
+
+    fn ints(&self, name: &str) -> Result<Option<Vec<i64>>, String> {
+        match self.node.attribute(name) {
+            None => Ok(None),
+            Some(AttributeValue::Ints(v)) => Ok(Some(v.clone())),
+            Some(_) => Err(format!("attribute {} should be a list of ints", name)),
+        }
+    }
+
+    fn floats(&self, name: &str) -> Result<Option<Vec<f32>>, String> {
+        match self.node.attribute(name) {
+            None => Ok(None),
+            Some(AttributeValue::Floats(v)) => Ok(Some(v.clone())),
+            Some(_) => Err(format!("attribute {} should be a list of floats", name)),
+        }
+    }
+
+    /// Returns a list of ints that's an input from the given opset on, and an attribute before it.
+    fn ints_since(&self, opset: i64, input: usize, name: &str) -> Result<Option<Vec<i64>>, String> {
+        if self.opset >= opset {
+            self.optional_input(input).map(ints).transpose()
+        } else {
+            self.ints(name)
+        }
+    }
+}
+
+fn tensor<D: Into<TensorData>>(data: D, shape: Vec<usize>) -> Result<OwnedTensor, String> {
+    OwnedTensor::new(data, shape).map_err(|e| e.to_string())
+}
+
+fn ints(tensor: &OwnedTensor) -> Result<Vec<i64>, String> {
+    match tensor.data() {
+        TensorData::Int64(v) => Ok(v.clone()),
+        TensorData::Int32(v) => Ok(v.iter().map(|&v| v as i64).collect()),
+        _ => Err(format!(
+            "expected an integer tensor, not {:?}",
+            tensor.element_type()
+        )),
+    }
+}
+
+fn floats(tensor: &OwnedTensor) -> Result<&[f32], String> {
+    tensor
+        .as_slice()
+        .ok_or_else(|| format!("expected a float tensor, not {:?}", tensor.element_type()))
+}
+
+/// Resolves a possibly negative axis.
+fn axis(axis: i64, rank: usize) -> Result<usize, String> {
+    let resolved = if axis < 0 { axis + rank as i64 } else { axis };
+    if (0..rank as i64).contains(&resolved) {
+        Ok(resolved as usize)
+    } else {
+        Err(format!("axis {} is out of range for rank {}", axis, rank))
+    }
+}
+
+/// Returns the row-major strides of a shape, in elements.
+fn strides(shape: &[usize]) -> Vec<usize> {
+    let mut ret = vec![1; shape.len()];
+    for i in (0..shape.len().saturating_sub(1)).rev() {
+        ret[i] = ret[i + 1] * shape[i + 1];
+    }
+    ret
+}
+
+/// Returns the sums of every combination of one offset from each axis, in row-major order. Ops
+/// that rearrange elements describe which elements they take along each axis this way.
+fn offsets(axes: &[Vec<usize>]) -> Vec<usize> {
+    let mut ret = vec![0];
+    for axis in axes {
+        ret = ret
+            .iter()
+            .flat_map(|&base| axis.iter().map(move |&offset| base + offset))
+            .collect();
+    }
+    ret
+}
+
+fn select(data: &TensorData, indices: &[usize]) -> TensorData {
+    map_data!(data, v => indices.iter().map(|&i| v[i]).collect::<Vec<_>>().into())
+}
+
+fn broadcast_shape(a: &[usize], b: &[usize]) -> Result<Vec<usize>, String> {
+    let rank = a.len().max(b.len());
+    let dim =
+        |shape: &[usize], i: usize| (i + shape.len()).checked_sub(rank).map_or(1, |i| shape[i]);
+    (0..rank)
+        .map(|i| match (dim(a, i), dim(b, i)) {
+            (a, b) if a == b || b == 1 => Ok(a),
+            (1, b) => Ok(b),
+            _ => Err(format!("shapes {:?} and {:?} can't be broadcast", a, b)),
+        })
+        .collect()
+}
+
+/// Returns the index into a tensor with the given shape of each element of it broadcast to
+/// `to`.
+fn broadcast_offsets(shape: &[usize], to: &[usize]) -> Vec<usize> {
+    let skipped = to.len() - shape.len();
+    let strides = strides(shape);
+    let axes: Vec<_> = to
+        .iter()
+        .enumerate()
+        .map(|(i, &n)| match i.checked_sub(skipped) {
+            Some(i) if shape[i] != 1 => (0..n).map(|j| j * strides[i]).collect(),
+            _ => vec![0; n],
+        })
+        .collect();
+    offsets(&axes)
+}
+
+fn constant(ctx: &Context) -> OpResult {
+    let attribute = ctx
+        .node
+        .attributes
+        .first()
+        .ok_or("missing value attribute")?;
+    let tensor = match (attribute.name.as_str(), &attribute.value) {
+        ("value", AttributeValue::Tensor(Some(t))) => t.clone(),
+        ("value_float", AttributeValue::Float(v)) => tensor(vec![*v], vec![])?,
+        ("value_floats", AttributeValue::Floats(v)) => tensor(v.clone(), vec![v.len()])?,
+        ("value_int", AttributeValue::Int(v)) => tensor(vec![*v], vec![])?,
+        ("value_ints", AttributeValue::Ints(v)) => tensor(v.clone(), vec![v.len()])?,
+        (name, _) => return Err(format!("unsupported value attribute {}", name)),
+    };
+    Ok(vec![tensor])
+}
+
+fn shape(ctx: &Context) -> OpResult {
+    let shape = ctx.input(0)?.shape();
+    let rank = shape.len() as i64;
+    let clamp = |v: i64| (if v < 0 { v + rank } else { v }).clamp(0, rank) as usize;
+    let start = clamp(ctx.int("start", 0)?);
+    let end = clamp(ctx.int("end", rank)?).max(start);
+    let dims: Vec<i64> = shape[start..end].iter().map(|&d| d as i64).collect();
+    Ok(vec![tensor(dims, vec![end - start])?])
+}
+
+/// Casts elements through `f64`, so 64-bit integers beyond 2^53 lose precision.
+fn cast(ctx: &Context) -> OpResult {
+    let input = ctx.input(0)?;
+    let to = ElementType::from_onnx(ctx.required_int("to")?);
+    if to == input.element_type() {
+        return Ok(vec![input.clone()]);
+    }
+    fn convert<T: Element>(v: Vec<f64>, f: impl Fn(f64) -> T) -> Option<TensorData> {
+        Some(v.into_iter().map(f).collect::<Vec<_>>().into())
+    }
+    let v: Vec<f64> = map_data!(input.data(), v => v.iter().map(|x| x.to_f64()).collect());
+    let data = match to {
+        ElementType::Bool => convert(v, |x| x != 0.0),
+        ElementType::Int8 => convert(v, |x| x as i8),
+        ElementType::Int16 => convert(v, |x| x as i16),
+        ElementType::Int32 => convert(v, |x| x as i32),
+        ElementType::Int64 => convert(v, |x| x as i64),
+        ElementType::UInt8 => convert(v, |x| x as u8),
+        ElementType::UInt16 => convert(v, |x| x as u16),
+        ElementType::UInt32 => convert(v, |x| x as u32),
+        ElementType::UInt64 => convert(v, |x| x as u64),
+        ElementType::Float16 => convert(v, f16::from_f64),
+        ElementType::BFloat16 => convert(v, bf16::from_f64),
+        ElementType::Float => convert(v, |x| x as f32),
+        ElementType::Double => convert(v, |x| x),
+        _ => None,
+    }
+    .ok_or_else(|| format!("unsupported element type {:?}", to))?;
+    Ok(vec![tensor(data, input.shape().to_vec())?])
+}
+
+fn reshape(ctx: &Context) -> OpResult {
+    let input = ctx.input(0)?;
+    let dims = ints(ctx.input(1)?)?;
+    let allow_zero = ctx.int("allowzero", 0)? != 0;
+    let mut inferred = None;
+    let mut shape = Vec::with_capacity(dims.len());
+    for (i, &d) in dims.iter().enumerate() {
+        shape.push(match d {
+            -1 if inferred.is_none() => {
+                inferred = Some(i);
+                1
+            }
+            0 if !allow_zero => *input
+                .shape()
+                .get(i)
+                .ok_or_else(|| format!("can't copy dimension {} of {:?}", i, input.shape()))?,
+            d if d >= 0 => d as usize,
+            _ => return Err(format!("invalid shape {:?}", dims)),
+        });
+    }
+    if let Some(i) = inferred {
+        let known: usize = shape.iter().product();
+        let len = input.data().len();
+        if known == 0 || len % known != 0 {
+            return Err(format!("can't reshape {:?} to {:?}", input.shape(), dims));
+        }
+        shape[i] = len / known;
+    }
+    Ok(vec![tensor(input.data().clone(), shape)?])
+}
+
+fn flatten(ctx: &Context) -> OpResult {
+    let input = ctx.input(0)?;
+    let rank = input.shape().len();
+    // Unlike most axes, this one may equal the rank.
+    let axis = axis(ctx.int("axis", 1)?, rank + 1)?;
+    let outer = input.shape()[..axis].iter().product();
+    let inner = input.shape()[axis..].iter().product();
+    Ok(vec![tensor(input.data().clone(), vec![outer, inner])?])
+}
+
+fn squeeze(ctx: &Context) -> OpResult {
+    let input = ctx.input(0)?;
+    let rank = input.shape().len();
+    let axes = match ctx.ints_since(13, 1, "axes")? {
+        Some(axes) => axes
+            .into_iter()
+            .map(|a| axis(a, rank))
+            .collect::<Result<Vec<_>, _>>()?,
+        None => (0..rank).filter(|&i| input.shape()[i] == 1).collect(),
+    };
+    if let Some(&a) = axes.iter().find(|&&a| input.shape()[a] != 1) {
+        return Err(format!("can't squeeze axis {} of {:?}", a, input.shape()));
+    }
+    let shape = (0..rank)
+        .filter(|i| !axes.contains(i))
+        .map(|i| input.shape()[i])
+        .collect();
+    Ok(vec![tensor(input.data().clone(), shape)?])
+}
+
+fn unsqueeze(ctx: &Context) -> OpResult {
+    let input = ctx.input(0)?;
+    let axes = ctx.ints_since(13, 1, "axes")?.ok_or("missing axes")?;
+    let rank = input.shape().len() + axes.len();
+    let mut axes = axes
+        .into_iter()
+        .map(|a| axis(a, rank))
+        .collect::<Result<Vec<_>, _>>()?;
+    axes.sort_unstable();
+    let mut shape = input.shape().to_vec();
+    for a in axes {
+        shape.insert(a, 1);
+    }
+    Ok(vec![tensor(input.data().clone(), shape)?])
+}
+
+fn transpose(ctx: &Context) -> OpResult {
+    let input = ctx.input(0)?;
+    let shape = input.shape();
+    let rank = shape.len();
+    let perm = match ctx.ints("perm")? {
+        Some(perm) => perm
+            .into_iter()
+            .map(|a| axis(a, rank))
+            .collect::<Result<Vec<_>, _>>()?,
+        None => (0..rank).rev().collect(),
+    };
+    let mut sorted = perm.clone();
+    sorted.sort_unstable();
+    if sorted != (0..rank).collect::<Vec<_>>() {
+        return Err(format!("invalid permutation {:?}", perm));
+    }
+    let strides = strides(shape);
+    let axes: Vec<Vec<usize>> = perm
+        .iter()
+        .map(|&a| (0..shape[a]).map(|i| i * strides[a]).collect())
+        .collect();
+    let data = select(input.data(), &offsets(&axes));
+    Ok(vec![tensor(
+        data,
+        perm.iter().map(|&a| shape[a]).collect(),
+    )?])
+}
+
+fn concat(ctx: &Context) -> OpResult {
+    let inputs: Vec<&OwnedTensor> = ctx.inputs.iter().flatten().copied().collect();
+    let first = inputs.first().ok_or("missing input 0")?;
+    let rank = first.shape().len();
+    let axis = axis(ctx.required_int("axis")?, rank)?;
+    let mut shape = first.shape().to_vec();
+    shape[axis] = 0;
+    for input in &inputs {
+        let compatible = input.shape().len() == rank
+            && (0..rank).all(|i| i == axis || input.shape()[i] == first.shape()[i]);
+        if !compatible {
+            return Err(format!(
+                "can't concatenate {:?} and {:?} along axis {}",
+                first.shape(),
+                input.shape(),
+                axis
+            ));
+        }
+        shape[axis] += input.shape()[axis];
+    }
+
+    fn interleave<T: Element>(
+        _like: &[T],
+        parts: &[&OwnedTensor],
+        outer: usize,
+    ) -> Result<TensorData, String> {
+        let parts = parts
+            .iter()
+            .map(|t| {
+                let chunk = t.data().len().checked_div(outer).unwrap_or(0);
+                T::slice(t.data())
+                    .map(|s| (s, chunk))
+                    .ok_or("inputs have different element types")
+            })
+            .collect::<Result<Vec<_>, _>>()?;
+        let mut ret = Vec::with_capacity(parts.iter().map(|(s, _)| s.len()).sum());
+        for i in 0..outer {
+            for (s, chunk) in &parts {
+                ret.extend_from_slice(&s[i * chunk..(i + 1) * chunk]);
+            }
+        }
+        Ok(ret.into())
+    }
+    let outer = shape[..axis].iter().product();
+    let data = map_data!(first.data(), v => interleave(v, &inputs, outer)?);
+    Ok(vec![tensor(data, shape)?])
+}
+
+fn slice(ctx: &Context) -> OpResult {
+    let input = ctx.input(0)?;
+    let shape = input.shape();
+    let rank = shape.len();
+    let (starts, ends, axes, steps) = if ctx.opset >= 10 {
+        let optional = |i| ctx.optional_input(i).map(ints).transpose();
+        (
+            ints(ctx.input(1)?)?,
+            ints(ctx.input(2)?)?,
+            optional(3)?,
+            optional(4)?,
+        )
+    } else {
+        (
+            ctx.ints("starts")?.ok_or("missing starts")?,
+            ctx.ints("ends")?.ok_or("missing ends")?,
+            ctx.ints("axes")?,
+            None,
+        )
+    };
+    let axes = axes.unwrap_or_else(|| (0..starts.len() as i64).collect());
+    let steps = steps.unwrap_or_else(|| vec![1; starts.len()]);
+    if ends.len() != starts.len() || axes.len() != starts.len() || steps.len() != starts.len() {
+        return Err("starts, ends, axes and steps have different lengths".to_string());
+    }
+
+    let mut ranges: Vec<_> = shape.iter().map(|&d| (0, d as i64, 1)).collect();
+    for i in 0..starts.len() {
+        let a = axis(axes[i], rank)?;
+        let dim = shape[a] as i64;
+        let step = steps[i];
+        let resolve = |v: i64| if v < 0 { v + dim } else { v };
+        // Out-of-range bounds are clamped, which for negative steps allows ending before 0.
+        ranges[a] = match step {
+            0 => return Err("steps can't be 0".to_string()),
+            // Nothing can be selected from an empty axis, and clamping to it would fail.
+            s if dim == 0 => (0, 0, s),
+            s if s > 0 => (
+                resolve(starts[i]).clamp(0, dim),
+                resolve(ends[i]).clamp(0, dim),
+                s,
+            ),
+            s => (
+                resolve(starts[i]).clamp(0, dim - 1),
+                resolve(ends[i]).clamp(-1, dim - 1),
+                s,
+            ),
+        };
+    }
+    let strides = strides(shape);
+    let axes: Vec<Vec<usize>> = ranges
+        .iter()
+        .zip(&strides)
+        .map(|(&(start, end, step), &stride)| {
+            let mut indices = Vec::new();
+            let mut i = start;
+            while (step > 0 && i < end) || (step < 0 && i > end) {
+                indices.push(i as usize * stride);
+                i += step;
+            }
+            indices
+        })
+        .collect();
+    let data = select(input.data(), &offsets(&axes));
+    Ok(vec![tensor(data, axes.iter().map(Vec::len).collect())?])
+}
+
+fn gather(ctx: &Context) -> OpResult {
+    let input = ctx.input(0)?;
+    let indices = ctx.input(1)?;
+    let shape = input.shape();
+    let a = axis(ctx.int("axis", 0)?, shape.len())?;
+    let dim = shape[a] as i64;
+    let strides = strides(shape);
+    let selected = ints(indices)?
+        .into_iter()
+        .map(|i| match if i < 0 { i + dim } else { i } {
+            i if (0..dim).contains(&i) => Ok(i as usize * strides[a]),
+            _ => Err(format!(
+                "index {} is out of range for axis {} of {:?}",
+                i, a, shape
+            )),
+        })
+        .collect::<Result<Vec<_>, _>>()?;
+    // The indices are flattened into a single axis, since their elements stay in order.
+    let range = |i: usize| (0..shape[i]).map(|j| j * strides[i]).collect();
+    let axes: Vec<Vec<usize>> = (0..a)
+        .map(range)
+        .chain(Some(selected))
+        .chain((a + 1..shape.len()).map(range))
+        .collect();
+    let out_shape = shape[..a]
+        .iter()
+        .chain(indices.shape())
+        .chain(&shape[a + 1..])
+        .copied()
+        .collect();
+    Ok(vec![tensor(
+        select(input.data(), &offsets(&axes)),
+        out_shape,
+    )?])
+}
+
+/// Add, Sub, Mul and Div, with broadcasting. Integer arithmetic wraps on overflow.
+fn arithmetic(ctx: &Context) -> OpResult {
+    let (a, b) = (ctx.input(0)?, ctx.input(1)?);
+    let shape = broadcast_shape(a.shape(), b.shape())?;
+    let (ia, ib) = (
+        broadcast_offsets(a.shape(), &shape),
+        broadcast_offsets(b.shape(), &shape),
+    );
+    fn zip<T: Copy, U: Element>(
+        x: &[T],
+        y: &[T],
+        ia: &[usize],
+        ib: &[usize],
+        f: impl Fn(T, T) -> U,
+    ) -> TensorData {
+        ia.iter()
+            .zip(ib)
+            .map(|(&i, &j)| f(x[i], y[j]))
+            .collect::<Vec<_>>()
+            .into()
+    }
+    macro_rules! float_op {
+        ($x:expr, $y:expr) => {
+            match ctx.node.op_type.as_str() {
+                "Add" => zip($x, $y, &ia, &ib, |x, y| x + y),
+                "Sub" => zip($x, $y, &ia, &ib, |x, y| x - y),
+                "Mul" => zip($x, $y, &ia, &ib, |x, y| x * y),
+                _ => zip($x, $y, &ia, &ib, |x, y| x / y),
+            }
+        };
+    }
+    macro_rules! int_op {
+        ($x:expr, $y:expr) => {
+            match ctx.node.op_type.as_str() {
+                "Add" => zip($x, $y, &ia, &ib, |x, y| x.wrapping_add(y)),
+                "Sub" => zip($x, $y, &ia, &ib, |x, y| x.wrapping_sub(y)),
+                "Mul" => zip($x, $y, &ia, &ib, |x, y| x.wrapping_mul(y)),
+                _ if $y.contains(&0) => return Err("division by zero".to_string()),
+                _ => zip($x, $y, &ia, &ib, |x, y| x.wrapping_div(y)),
+            }
+        };
+    }
+    let data = match (a.data(), b.data()) {
+        (TensorData::Float32(x), TensorData::Float32(y)) => float_op!(x, y),
+        (TensorData::Float64(x), TensorData::Float64(y)) => float_op!(x, y),
+        (TensorData::Int32(x), TensorData::Int32(y)) => int_op!(x, y),
+        (TensorData::Int64(x), TensorData::Int64(y)) => int_op!(x, y),
+        _ => {
+            return Err(format!(
+                "unsupported element types {:?} and {:?}",
+                a.element_type(),
+                b.element_type()
+            ))
+        }
+    };
+    Ok(vec![tensor(data, shape)?])
+}
+
+fn unary(ctx: &Context, f: impl Fn(f32) -> f32) -> OpResult {
+    let input = ctx.input(0)?;
+    let data: Vec<f32> = floats(input)?.iter().map(|&x| f(x)).collect();
+    Ok(vec![tensor(data, input.shape().to_vec())?])
+}
+
+fn softmax(ctx: &Context) -> OpResult {
+    let input = ctx.input(0)?;
+    let x = floats(input)?;
+    let shape = input.shape();
+    // Before opset 13, the input is treated as a matrix whose rows start at the axis.
+    let (default_axis, inner_axis) = if ctx.opset < 13 {
+        (1, shape.len())
+    } else {
+        (-1, 0)
+    };
+    let a = axis(ctx.int("axis", default_axis)?, shape.len())?;
+    let inner_axis = inner_axis.max(a + 1);
+    let outer: usize = shape[..a].iter().product();
+    let n: usize = shape[a..inner_axis].iter().product();
+    let inner: usize = shape[inner_axis..].iter().product();
+    let mut ret = vec![0.0; x.len()];
+    for o in 0..outer {
+        for i in 0..inner {
+            let index = |k: usize| (o * n + k) * inner + i;
+            let max = (0..n)
+                .map(|k| x[index(k)])
+                .fold(f32::NEG_INFINITY, f32::max);
+            let sum: f64 = (0..n).map(|k| ((x[index(k)] - max) as f64).exp()).sum();
+            for k in 0..n {
+                ret[index(k)] = (((x[index(k)] - max) as f64).exp() / sum) as f32;
+            }
+        }
+    }
+    Ok(vec![tensor(ret, shape.to_vec())?])
+}
+
+fn matmul(ctx: &Context) -> OpResult {
+    let (a, b) = (ctx.input(0)?, ctx.input(1)?);
+    let (x, y) = (floats(a)?, floats(b)?);
+    // 1-D operands are promoted to matrices, and the added dimension is removed afterwards.
+    let a_shape = match a.shape() {
+        [] => return Err("MatMul operands can't be scalars".to_string()),
+        [k] => vec![1, *k],
+        shape => shape.to_vec(),
+    };
+    let b_shape = match b.shape() {
+        [] => return Err("MatMul operands can't be scalars".to_string()),
+        [k] => vec![*k, 1],
+        shape => shape.to_vec(),
+    };
+    let (m, k) = (a_shape[a_shape.len() - 2], a_shape[a_shape.len() - 1]);
+    let (k2, n) = (b_shape[b_shape.len() - 2], b_shape[b_shape.len() - 1]);
+    if k != k2 {
+        return Err(format!("can't multiply {:?} by {:?}", a.shape(), b.shape()));
+    }
+    let (a_batch, b_batch) = (&a_shape[..a_shape.len() - 2], &b_shape[..b_shape.len() - 2]);
+    let batch = broadcast_shape(a_batch, b_batch)?;
+    let mut ret = Vec::with_capacity(batch.iter().product::<usize>() * m * n);
+    for (i, j) in broadcast_offsets(a_batch, &batch)
+        .into_iter()
+        .zip(broadcast_offsets(b_batch, &batch))
+    {
+        let (x, y) = (&x[i * m * k..], &y[j * k * n..]);
+        for r in 0..m {
+            for c in 0..n {
+                let sum: f64 = (0..k)
+                    .map(|l| x[r * k + l] as f64 * y[l * n + c] as f64)
+                    .sum();
+                ret.push(sum as f32);
+            }
+        }
+    }
+    let mut shape = batch;
+    if a.shape().len() > 1 {
+        shape.push(m);
+    }
+    if b.shape().len() > 1 {
+        shape.push(n);
+    }
+    Ok(vec![tensor(ret, shape)?])
+}
+
+fn gemm(ctx: &Context) -> OpResult {
+    let (a, b) = (ctx.input(0)?, ctx.input(1)?);
+    let (x, y) = (floats(a)?, floats(b)?);
+    let (trans_a, trans_b) = (ctx.int("transA", 0)? != 0, ctx.int("transB", 0)? != 0);
+    let (alpha, beta) = (
+        ctx.float("alpha", 1.0)? as f64,
+        ctx.float("beta", 1.0)? as f64,
+    );
+    let (a_rows, a_cols, b_rows, b_cols) = match (a.shape(), b.shape()) {
+        (&[a_rows, a_cols], &[b_rows, b_cols]) => (a_rows, a_cols, b_rows, b_cols),
+        _ => return Err("Gemm operands must be matrices".to_string()),
+    };
+    let (m, k) = if trans_a {
+        (a_cols, a_rows)
+    } else {
+        (a_rows, a_cols)
+    };
+    let (k2, n) = if trans_b {
+        (b_cols, b_rows)
+    } else {
+        (b_rows, b_cols)
+    };
+    if k != k2 {
+        return Err(format!("can't multiply {:?} by {:?}", a.shape(), b.shape()));
+    }
+    let bias = match ctx.optional_input(2) {
+        Some(c) => {
+            let shape = broadcast_shape(c.shape(), &[m, n])?;
+            if shape != [m, n] {
+                return Err(format!("C's shape {:?} can't be broadcast", c.shape()));
+            }
+            Some((floats(c)?, broadcast_offsets(c.shape(), &shape)))
+        }
+        None => None,
+    };
+    let a_at = |r: usize, l: usize| x[if trans_a { l * m + r } else { r * k + l }] as f64;
+    let b_at = |l: usize, c: usize| y[if trans_b { c * k + l } else { l * n + c }] as f64;
+    let mut ret = Vec::with_capacity(m * n);
+    for r in 0..m {
+        for c in 0..n {
+            let mut sum = alpha * (0..k).map(|l| a_at(r, l) * b_at(l, c)).sum::<f64>();
+            if let Some((bias, offsets)) = &bias {
+                sum += beta * bias[offsets[r * n + c]] as f64;
+            }
+            ret.push(sum as f32);
+        }
+    }
+    Ok(vec![tensor(ret, vec![m, n])?])
+}
+
+/// The geometry of a sliding window over one or two spatial axes. 1-D windows are handled as 2-D
+/// ones with a height of 1.
+struct Window {
+    input: [usize; 2],
+    kernel: [usize; 2],
+    strides: [usize; 2],
+    dilations: [usize; 2],
+    pads_begin: [usize; 2],
+    pads_end: [usize; 2],
+    output: [usize; 2],
+}
+
+impl Window {
+    fn new(
+        ctx: &Context,
+        input: &[usize],
+        kernel: &[usize],
+        ceil_mode: bool,
+    ) -> Result<Self, String> {
+        let n = input.len();
+        if !(1..=2).contains(&n) || kernel.len() != n {
+            return Err(format!(
+                "only 1-D and 2-D windows are supported, not {}-D",
+                n
+            ));
+        }
+        let usizes = |name: &str, len: usize, default: usize| -> Result<Vec<usize>, String> {
+            match ctx.ints(name)? {
+                Some(v) if v.len() == len && v.iter().all(|&v| v >= 0) => {
+                    Ok(v.into_iter().map(|v| v as usize).collect())
+                }
+                Some(v) => Err(format!("invalid {} {:?}", name, v)),
+                None => Ok(vec![default; len]),
+            }
+        };
+        let strides = usizes("strides", n, 1)?;
+        let dilations = usizes("dilations", n, 1)?;
+        let pads = usizes("pads", 2 * n, 0)?;
+        let auto_pad = ctx.string("auto_pad", "NOTSET")?;
+
+        let mut ret = Self {
+            input: [1; 2],
+            kernel: [1; 2],
+            strides: [1; 2],
+            dilations: [1; 2],
+            pads_begin: [0; 2],
+            pads_end: [0; 2],
+            output: [1; 2],
+        };
+        for i in 0..n {
+            let j = i + 2 - n;
+            if strides[i] == 0 || dilations[i] == 0 {
+                return Err("strides and dilations can't be 0".to_string());
+            }
+            let extent = (kernel[i] - 1) * dilations[i] + 1;
+            let (begin, end) = match auto_pad {
+                "NOTSET" => (pads[i], pads[i + n]),
+                "VALID" => (0, 0),
+                "SAME_UPPER" | "SAME_LOWER" => {
+                    // The output size is input / stride rounded up, but at least 1.
+                    let output = (input[i].max(1) - 1) / strides[i] + 1;
+                    let total = ((output - 1) * strides[i] + extent).saturating_sub(input[i]);
+                    if auto_pad == "SAME_UPPER" {
+                        (total / 2, total - total / 2)
+                    } else {
+                        (total - total / 2, total / 2)
+                    }
+                }
+                _ => return Err(format!("unsupported auto_pad {}", auto_pad)),
+            };
+            let padded = input[i] + begin + end;
+            if padded < extent {
+                return Err(format!("the window doesn't fit the input {:?}", input));
+            }
+            let mut output = (padded - extent) / strides[i] + 1;
+            // In ceil mode, a partial window is added if the last full one ends before the padded
+            // input does, unless it would start in the end padding.
+            if ceil_mode
+                && (output - 1) * strides[i] + extent < padded
+                && output * strides[i] < input[i] + begin
+            {
+                output += 1;
+            }
+            ret.input[j] = input[i];
+            ret.kernel[j] = kernel[i];
+            ret.strides[j] = strides[i];
+            ret.dilations[j] = dilations[i];
+            ret.pads_begin[j] = begin;
+            ret.pads_end[j] = end;
+            ret.output[j] = output;
+        }
+        Ok(ret)
+    }
+
+    /// Returns the input index of a kernel element along an axis, or `None` if it's in the
+    /// padding.
+    fn input_index(&self, axis: usize, output: usize, kernel: usize) -> Option<usize> {
+        (output * self.strides[axis] + kernel * self.dilations[axis])
+            .checked_sub(self.pads_begin[axis])
+            .filter(|&i| i < self.input[axis])
+    }
+
+    /// Returns true if a kernel element is within the input or its explicit padding, as opposed
+    /// to past the end of it because of ceil mode.
+    fn in_padded_input(&self, axis: usize, output: usize, kernel: usize) -> bool {
+        output * self.strides[axis] + kernel * self.dilations[axis]
+            < self.pads_begin[axis] + self.input[axis] + self.pads_end[axis]
+    }
+
+    fn output_shape(&self, batch: usize, channels: usize, spatial_rank: usize) -> Vec<usize> {
+        let mut ret = vec![batch, channels];
+        ret.extend_from_slice(&self.output[2 - spatial_rank..]);
+        ret
+    }
+}
+
+/// Splits an NCHW-like shape into its batch size, channel count and spatial dimensions.
+fn split_channels(shape: &[usize]) -> Result<(usize, usize, &[usize]), String> {
+    match shape {
+        [batch, channels, spatial @ ..] => Ok((*batch, *channels, spatial)),
+        _ => Err(format!("expected a batch and channels in {:?}", shape)),
+    }
+}
+
+fn conv(ctx: &Context) -> OpResult {
+    let (input, weights) = (ctx.input(0)?, ctx.input(1)?);
+    let (x, w) = (floats(input)?, floats(weights)?);
+    let (batch, channels, spatial) = split_channels(input.shape())?;
+    let (filters, group_channels, kernel) = split_channels(weights.shape())?;
+    let groups = ctx.int("group", 1)?.max(1) as usize;
+    if channels != group_channels * groups || filters % groups != 0 {
+        return Err(format!(
+            "weights {:?} don't match input {:?} with {} groups",
+            weights.shape(),
+            input.shape(),
+            groups
+        ));
+    }
+    let bias = ctx.optional_input(2).map(floats).transpose()?;
+    if matches!(bias, Some(b) if b.len() != filters) {
+        return Err(format!("expected {} biases", filters));
+    }
+    let window = Window::new(ctx, spatial, kernel, false)?;
+    let [height, width] = window.input;
+    let [kernel_height, kernel_width] = window.kernel;
+    let [output_height, output_width] = window.output;
+
+    let mut ret = Vec::with_capacity(batch * filters * output_height * output_width);
+    for b in 0..batch {
+        for f in 0..filters {
+            let group = f / (filters / groups);
+            for oy in 0..output_height {
+                for ox in 0..output_width {
+                    let mut sum = bias.map_or(0.0, |bias| bias[f] as f64);
+                    for c in 0..group_channels {
+                        let ic = group * group_channels + c;
+                        for ky in 0..kernel_height {
+                            let iy = match window.input_index(0, oy, ky) {
+                                Some(iy) => iy,
+                                None => continue,
+                            };
+                            for kx in 0..kernel_width {
+                                if let Some(ix) = window.input_index(1, ox, kx) {
+                                    let xi = ((b * channels + ic) * height + iy) * width + ix;
+                                    let wi = ((f * group_channels + c) * kernel_height + ky)
+                                        * kernel_width
+                                        + kx;
+                                    sum += x[xi] as f64 * w[wi] as f64;
+                                }
+                            }
+                        }
+                    }
+                    ret.push(sum as f32);
+                }
+            }
+        }
+    }
+    Ok(vec![tensor(
+        ret,
+        window.output_shape(batch, filters, spatial.len()),
+    )?])
+}
+
+fn batch_normalization(ctx: &Context) -> OpResult {
+    if ctx.int("training_mode", 0)? != 0 {
+        return Err("training mode isn't supported".to_string());
+    }
+    let input = ctx.input(0)?;
+    let x = floats(input)?;
+    let (_, channels, spatial) = split_channels(input.shape())?;
+    let params = (1..5)
+        .map(|i| {
+            let v = floats(ctx.input(i)?)?;
+            if v.len() == channels {
+                Ok(v)
+            } else {
+                Err(format!("expected {} values for input {}", channels, i))
+            }
+        })
+        .collect::<Result<Vec<_>, String>>()?;
+    let (scale, bias, mean, var) = (params[0], params[1], params[2], params[3]);
+    let epsilon = ctx.float("epsilon", 1e-5)? as f64;
+    let size: usize = spatial.iter().product();
+    let data: Vec<f32> = x
+        .iter()
+        .enumerate()
+        .map(|(i, &x)| {
+            let c = i / size.max(1) % channels;
+            let normalized = (x as f64 - mean[c] as f64) / (var[c] as f64 + epsilon).sqrt();
+            (normalized * scale[c] as f64 + bias[c] as f64) as f32
+        })
+        .collect();
+    Ok(vec![tensor(data, input.shape().to_vec())?])
+}
+
+fn pool(ctx: &Context, max: bool) -> OpResult {
+    if matches!(ctx.node.outputs.get(1), Some(name) if !name.is_empty()) {
+        return Err("the Indices output isn't supported".to_string());
+    }
+    let input = ctx.input(0)?;
+    let x = floats(input)?;
+    let (batch, channels, spatial) = split_channels(input.shape())?;
+    let kernel: Vec<usize> = ctx
+        .ints("kernel_shape")?
+        .ok_or("missing kernel_shape")?
+        .into_iter()
+        .map(|k| k.max(1) as usize)
+        .collect();
+    let ceil_mode = ctx.int("ceil_mode", 0)? != 0;
+    let count_include_pad = ctx.int("count_include_pad", 0)? != 0;
+    let window = Window::new(ctx, spatial, &kernel, ceil_mode)?;
+    let [height, width] = window.input;
+    let [kernel_height, kernel_width] = window.kernel;
+    let [output_height, output_width] = window.output;
+
+    let mut ret = Vec::with_capacity(batch * channels * output_height * output_width);
+    let size = height * width;
+    for i in 0..batch * channels {
+        // With padding, the output isn't empty even if the planes are.
+        let plane = &x[i * size..(i + 1) * size];
+        for oy in 0..output_height {
+            for ox in 0..output_width {
+                let (mut max_value, mut sum, mut count) = (f32::NEG_INFINITY, 0.0, 0);
+                for ky in 0..kernel_height {
+                    for kx in 0..kernel_width {
+                        match (window.input_index(0, oy, ky), window.input_index(1, ox, kx)) {
+                            (Some(iy), Some(ix)) => {
+                                let v = plane[iy * width + ix];
+                                max_value = max_value.max(v);
+                                sum += v as f64;
+                                count += 1;
+                            }
+                            _ if count_include_pad
+                                && window.in_padded_input(0, oy, ky)
+                                && window.in_padded_input(1, ox, kx) =>
+                            {
+                                count += 1
+                            }
+                            _ => {}
+                        }
+                    }
+                }
+                ret.push(if max {
+                    max_value
+                } else {
+                    (sum / count.max(1) as f64) as f32
+                });
+            }
+        }
+    }
+    Ok(vec![tensor(
+        ret,
+        window.output_shape(batch, channels, spatial.len()),
+    )?])
+}
+
+fn global_pool(ctx: &Context, max: bool) -> OpResult {
+    let input = ctx.input(0)?;
+    let x = floats(input)?;
+    let (batch, channels, spatial) = split_channels(input.shape())?;
+    let size: usize = spatial.iter().product();
+    let data: Vec<f32> = (0..batch * channels)
+        .map(|i| {
+            let plane = &x[i * size..(i + 1) * size];
+            if max {
+                plane.iter().copied().fold(f32::NEG_INFINITY, f32::max)
+            } else {
+                (plane.iter().map(|&v| v as f64).sum::<f64>() / size as f64) as f32
+            }
+        })
+        .collect();
+    let mut shape = vec![batch, channels];
+    shape.resize(input.shape().len(), 1);
+    Ok(vec![tensor(data, shape)?])
+}
+
+fn resize(ctx: &Context) -> OpResult {
+    let input = ctx.input(0)?;
+    let mode = ctx.string("mode", "nearest")?;
+    // Resize-10 has the same semantics as Upsample.
+    if ctx.opset < 11 {
+        let scales = floats(ctx.input(1)?)?.to_vec();
+        let output = resample(input, Some(scales), None, mode, "asymmetric", "floor")?;
+        return Ok(vec![output]);
+    }
+    if ctx.node.attribute("axes").is_some() {
+        return Err("the axes attribute isn't supported".to_string());
+    }
+    let scales = ctx
+        .nonempty_input(2)
+        .map(|t| floats(t).map(<[f32]>::to_vec))
+        .transpose()?;
+    let sizes = ctx.nonempty_input(3).map(ints).transpose()?;
+    let output = resample(
+        input,
+        scales,
+        sizes,
+        mode,
+        ctx.string("coordinate_transformation_mode", "half_pixel")?,
+        ctx.string("nearest_mode", "round_prefer_floor")?,
+    )?;
+    Ok(vec![output])
+}
+
+fn upsample(ctx: &Context) -> OpResult {
+    let scales = if ctx.opset >= 9 {
+        floats(ctx.input(1)?)?.to_vec()
+    } else {
+        ctx.floats("scales")?.ok_or("missing scales")?
+    };
+    let output = resample(
+        ctx.input(0)?,
+        Some(scales),
+        None,
+        ctx.string("mode", "nearest")?,
+        "asymmetric",
+        "floor",
+    )?;
+    Ok(vec![output])
+}
+
+/// Resizes a tensor to the given sizes, or its sizes multiplied by the given scales. Nearest
+/// neighbor resizing works with any element type, and linear resizing needs floats.
+fn resample(
+    input: &OwnedTensor,
+    scales: Option<Vec<f32>>,
+    sizes: Option<Vec<i64>>,
+    mode: &str,
+    coordinate_mode: &str,
+    nearest_mode: &str,
+) -> Result<OwnedTensor, String> {
+    let shape = input.shape();
+    let (scales, output_shape): (Vec<f32>, Vec<usize>) = match (sizes, scales) {
+        (Some(sizes), _) if sizes.len() == shape.len() => {
+            let output: Vec<usize> = sizes.iter().map(|&s| s.max(0) as usize).collect();
+            let scales = output
+                .iter()
+                .zip(shape)
+                .map(|(&o, &i)| o as f32 / i as f32)
+                .collect();
+            (scales, output)
+        }
+        (None, Some(scales)) if scales.len() == shape.len() => {
+            let output = scales
+                .iter()
+                .zip(shape)
+                .map(|(&s, &i)| (i as f32 * s).floor().max(0.0) as usize)
+                .collect();
+            (scales, output)
+        }
+        _ => {
+            return Err(format!(
+                "expected scales or sizes for each of the {} dimensions",
+                shape.len()
+            ))
+        }
+    };
+    let source = |axis: usize, i: usize| -> Result<f32, String> {
+        let (x, scale) = (i as f32, scales[axis]);
+        let (input, output) = (shape[axis], output_shape[axis]);
+        Ok(match coordinate_mode {
+            "half_pixel" => (x + 0.5) / scale - 0.5,
+            "pytorch_half_pixel" if output > 1 => (x + 0.5) / scale - 0.5,
+            "pytorch_half_pixel" => 0.0,
+            "align_corners" if output > 1 => x * (input - 1) as f32 / (output - 1) as f32,
+            "align_corners" => 0.0,
+            "asymmetric" => x / scale,
+            "tf_half_pixel_for_nn" => (x + 0.5) / scale,
+            _ => {
+                return Err(format!(
+                    "unsupported coordinate transformation mode {}",
+                    coordinate_mode
+                ))
+            }
+        })
+    };
+
+    match mode {
+        "nearest" => {
+            let round = |x: f32| -> Result<f32, String> {
+                let ties = x - x.floor() == 0.5;
+                Ok(match nearest_mode {
+                    "round_prefer_floor" if ties => x.floor(),
+                    "round_prefer_ceil" if ties => x.ceil(),
+                    "round_prefer_floor" | "round_prefer_ceil" => x.round(),
+                    "floor" => x.floor(),
+                    "ceil" => x.ceil(),
+                    _ => return Err(format!("unsupported nearest mode {}", nearest_mode)),
+                })
+            };
+            let strides = strides(shape);
+            let axes = (0..shape.len())
+                .map(|axis| {
+                    (0..output_shape[axis])
+                        .map(|i| {
+                            let x = round(source(axis, i)?)?;
+                            let index = (x.max(0.0) as usize).min(shape[axis].saturating_sub(1));
+                            Ok(index * strides[axis])
+                        })
+                        .collect::<Result<Vec<_>, String>>()
+                })
+                .collect::<Result<Vec<_>, String>>()?;
+            tensor(select(input.data(), &offsets(&axes)), output_shape)
+        }
+        // Linear interpolation is separable, so it's done one axis at a time.
+        "linear" => {
+            let mut data = floats(input)?.to_vec();
+            let mut current = shape.to_vec();
+            for axis in 0..shape.len() {
+                let (n, m) = (shape[axis], output_shape[axis]);
+                if n == 0 {
+                    current[axis] = m;
+                    continue;
+                }
+                let taps = (0..m)
+                    .map(|i| {
+                        let x = source(axis, i)?.max(0.0).min((n - 1) as f32);
+                        let i0 = x.floor() as usize;
+                        Ok((i0, (i0 + 1).min(n - 1), x - x.floor()))
+                    })
+                    .collect::<Result<Vec<_>, String>>()?;
+                let outer: usize = current[..axis].iter().product();
+                let inner: usize = current[axis + 1..].iter().product();
+                let mut resized = Vec::with_capacity(outer * m * inner);
+                for o in 0..outer {
+                    for &(i0, i1, w) in &taps {
+                        for k in 0..inner {
+                            let a = data[(o * n + i0) * inner + k];
+                            let b = data[(o * n + i1) * inner + k];
+                            resized.push(a + (b - a) * w);
+                        }
+                    }
+                }
+                data = resized;
+                current[axis] = m;
+            }
+            tensor(data, output_shape)
+        }
+        _ => Err(format!("unsupported mode {}", mode)),
+    }
+}
@@ -0,0 +1,701 @@
+//! A backend for models served by another host over HTTP with the KServe v2 protocol, e.g. by
+//! Triton, KServe or an `infer`-based server on a Mac running CoreML models.
+//!
+//! Tensors are sent and received with Triton's binary tensor extension by default, which avoids
+//! encoding them as JSON numbers. Servers without the extension can be used by turning it off
+//! with `RemoteBackend::binary_data`.
+
+use super::{
+    onnx::proto::{Dimension, ElementType},
+    Backend, Element, Model, NewSessionError, OutputTensor, OwnedTensor, SessionInfoError,
+    SessionInput, SessionOptions, SessionRunError, Signature, TensorData, TensorSignature,
+};
+use half::{bf16, f16};
+use serde::{Deserialize, Serialize};
+use serde_json::{json, Value};
+use std::{borrow::Cow, convert::TryFrom, io::Read, path::Path, time::Duration};
+
+/// The header giving the length of the JSON part of a request or response that has binary tensor
+/// data after it.
+const HEADER_LENGTH: &str = "Inference-Header-Content-Length";
+
+#[derive(thiserror::Error, Debug)]
+pub enum RemoteError {
+    #[error("unable to reach {url}: {message}")]
+    Transport { url: String, message: String },
+    #[error("{url} responded with status {status}: {message}")]
+    Status {
+        url: String,
+        status: u16,
+        message: String,
+    },
+    #[error("unable to read response: {0}")]
+    Io(#[from] std::io::Error),
+    #[error("malformed response: {0}")]
+    MalformedResponse(String),
+    #[error("model {0} isn't ready")]
+    NotReady(String),
+    #[error("output {name} has unsupported data type {datatype}")]
+    UnsupportedDatatype { name: String, datatype: String },
+}
+
+impl From<serde_json::Error> for RemoteError {
+    fn from(e: serde_json::Error) -> Self {
+        Self::MalformedResponse(e.to_string())
+    }
+}
+
+/// Connects to models on a KServe v2 server. Registered for a format with
+/// `Environment::register_backend`, it loads the model named by the file stem of the model
+/// path, so "models/resnet50.mlmodel" is served as "resnet50". Nothing is read from the path
+/// itself, so the file doesn't need to exist locally.
+#[derive(Clone, Debug)]
+pub struct RemoteBackend {
+    url: String,
+    binary_data: bool,
+    timeout: Option<Duration>,
+}
+
+impl RemoteBackend {
+    /// Creates a backend for the server at the given base URL, e.g. "http://gpu-server:8000".
+    ///
+    /// `https://` URLs need the `remote-tls` feature.
+    pub fn new<S: Into<String>>(url: S) -> Self {
+        Self {
+            url: url.into().trim_end_matches('/').to_string(),
+            binary_data: true,
+            timeout: None,
+        }
+    }
+
+    /// Sets whether tensors are sent and received as binary data. Enabled by default.
+    pub fn binary_data(mut self, enabled: bool) -> Self {
+        self.binary_data = enabled;
+        self
+    }
+
+    /// Sets a timeout for each request, including connecting and reading the response.
+    pub fn timeout(mut self, timeout: Duration) -> Self {
+        self.timeout = Some(timeout);
+        self
+    }
+
+    /// Connects to the model with the given name, checking that it's ready and reading its
+    /// metadata.
+    pub fn connect(&self, name: &str) -> Result<RemoteModel, RemoteError> {
+        let mut agent = ureq::AgentBuilder::new();
+        if let Some(timeout) = self.timeout {
+            agent = agent.timeout(timeout);
+        }
+        let agent = agent.build();
+        let url = format!("{}/v2/models/{}", self.url, name);
+
+        let ready_url = format!("{}/ready", url);
+        match agent.get(&ready_url).call() {
+            Ok(response) if response.status() == 200 => {}
+            Ok(_) => return Err(RemoteError::NotReady(name.to_string())),
+            Err(e) => return Err(error(&ready_url, e)),
+        }
+        let response = agent.get(&url).call().map_err(|e| error(&url, e))?;
+        let metadata: ModelMetadata = serde_json::from_reader(response.into_reader())?;
+        Ok(RemoteModel {
+            agent,
+            url,
+            binary_data: self.binary_data,
+            metadata,
+        })
+    }
+}
+
+impl Backend for RemoteBackend {
+    fn load<'a>(
+        &'a self,
+        path: &Path,
+        _options: &SessionOptions,
+    ) -> Result<Box<dyn Model<'a> + 'a>, NewSessionError> {
+        let name = path
+            .file_stem()
+            .unwrap_or(path.as_os_str())
+            .to_string_lossy();
+        let model = self
+            .connect(&name)
+            .map_err(|e| NewSessionError::Backend(Box::new(e)))?;
+        Ok(Box::new(model))
+    }
+}
+
+#[derive(Debug, Deserialize)]
+struct ModelMetadata {
+    name: String,
+    #[serde(default)]
+    versions: Vec<String>,
+    #[serde(default)]
+    platform: String,
+    #[serde(default)]
+    inputs: Vec<TensorMetadata>,
+    #[serde(default)]
+    outputs: Vec<TensorMetadata>,
+}
+
+#[derive(Debug, Deserialize)]
+struct TensorMetadata {
+    name: String,
+    datatype: String,
+    shape: Vec<i64>,
+}
+
+impl From<&TensorMetadata> for TensorSignature {
+    fn from(metadata: &TensorMetadata) -> Self {
+        Self {
+            name: metadata.name.clone(),
+            element_type: element_type(&metadata.datatype),
+            dimensions: Some(
+                metadata
+                    .shape
+                    .iter()
+                    .map(|&d| match usize::try_from(d) {
+                        Ok(d) => Dimension::Fixed(d),
+                        Err(_) => Dimension::Unknown,
+                    })
+                    .collect(),
+            ),
+        }
+    }
+}
+
+#[derive(Debug, Default, Deserialize, Serialize)]
+struct Parameters {
+    #[serde(skip_serializing_if = "Option::is_none")]
+    binary_data: Option<bool>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    binary_data_size: Option<usize>,
+}
+
+#[derive(Serialize)]
+struct InferRequest<'a> {
+    inputs: Vec<RequestInput<'a>>,
+    outputs: Vec<RequestOutput<'a>>,
+}
+
+#[derive(Serialize)]
+struct RequestInput<'a> {
+    name: &'a str,
+    shape: &'a [usize],
+    datatype: &'static str,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    data: Option<Value>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    parameters: Option<Parameters>,
+}
+
+#[derive(Serialize)]
+struct RequestOutput<'a> {
+    name: &'a str,
+    parameters: Parameters,
+}
+
+#[derive(Deserialize)]
+struct InferResponse {
+    outputs: Vec<ResponseOutput>,
+}
+
+#[derive(Deserialize)]
+struct ResponseOutput {
+    name: String,
+    shape: Vec<usize>,
+    datatype: String,
+    #[serde(default)]
+    data: Option<Value>,
+    #[serde(default)]
+    parameters: Option<Parameters>,
+}
+
+/// A model on a KServe v2 server, connected to with `RemoteBackend::connect`.
+pub struct RemoteModel {
+    agent: ureq::Agent,
+    url: String,
+    binary_data: bool,
+    metadata: ModelMetadata,
+}
+
+impl RemoteModel {
+    /// Sends the inputs to the server and returns the outputs it responds with, in the order the
+    /// model declares them.
+    pub fn infer(
+        &self,
+        inputs: &[(&str, &OwnedTensor)],
+    ) -> Result<Vec<(String, OwnedTensor)>, RemoteError> {
+        let binary: Vec<Vec<u8>> = if self.binary_data {
+            inputs.iter().map(|(_, t)| t.data().to_le_bytes()).collect()
+        } else {
+            Vec::new()
+        };
+        let request = InferRequest {
+            inputs: inputs
+                .iter()
+                .enumerate()
+                .map(|(i, (name, tensor))| RequestInput {
+                    name,
+                    shape: tensor.shape(),
+                    datatype: datatype(tensor.element_type()),
+                    data: if self.binary_data {
+                        None
+                    } else {
+                        Some(json_data(tensor.data()))
+                    },
+                    parameters: if self.binary_data {
+                        Some(Parameters {
+                            binary_data_size: Some(binary[i].len()),
+                            ..Parameters::default()
+                        })
+                    } else {
+                        None
+                    },
+                })
+                .collect(),
+            outputs: self
+                .metadata
+                .outputs
+                .iter()
+                .map(|output| RequestOutput {
+                    name: &output.name,
+                    parameters: Parameters {
+                        binary_data: Some(self.binary_data),
+                        ..Parameters::default()
+                    },
+                })
+                .collect(),
+        };
+        let mut body = serde_json::to_vec(&request).expect("requests should serialize");
+
+        let url = format!("{}/infer", self.url);
+        let response = if self.binary_data {
+            let header_length = body.len();
+            for bytes in &binary {
+                body.extend(bytes);
+            }
+            self.agent
+                .post(&url)
+                .set("Content-Type", "application/octet-stream")
+                .set(HEADER_LENGTH, &header_length.to_string())
+                .send_bytes(&body)
+        } else {
+            self.agent
+                .post(&url)
+                .set("Content-Type", "application/json")
+                .send_bytes(&body)
+        }
+        .map_err(|e| error(&url, e))?;
+
+        let header_length = response
+            .header(HEADER_LENGTH)
+            .map(|s| s.parse::<usize>())
+            .transpose()
+            .map_err(|e| RemoteError::MalformedResponse(e.to_string()))?;
+        let mut body = Vec::new();
+        response.into_reader().read_to_end(&mut body)?;
+        let (header, mut binary) = match header_length {
+            Some(n) if n <= body.len() => body.split_at(n),
+            Some(n) => {
+                return Err(RemoteError::MalformedResponse(format!(
+                    "header length {} is longer than the response",
+                    n
+                )))
+            }
+            None => (&body[..], &[][..]),
+        };
+        let response: InferResponse = serde_json::from_slice(header)?;
+
+        // Binary data follows the header in the order of the outputs that have it.
+        let mut outputs = Vec::with_capacity(response.outputs.len());
+        for output in response.outputs {
+            let element_type = element_type(&output.datatype);
+            let unsupported = || RemoteError::UnsupportedDatatype {
+                name: output.name.clone(),
+                datatype: output.datatype.clone(),
+            };
+            let malformed = |message: &str| {
+                RemoteError::MalformedResponse(format!("output {}: {}", output.name, message))
+            };
+            let size = output.parameters.as_ref().and_then(|p| p.binary_data_size);
+            let data = match (size, &output.data) {
+                (Some(size), _) => {
+                    if size > binary.len() {
+                        return Err(malformed("binary data is missing"));
+                    }
+                    let (bytes, rest) = binary.split_at(size);
+                    binary = rest;
+                    let element_size = element_type.size_in_bytes().ok_or_else(unsupported)?;
+                    let expected = output
+                        .shape
+                        .iter()
+                        .try_fold(element_size, |n, &d| n.checked_mul(d));
+                    if expected != Some(bytes.len()) {
+                        return Err(malformed("binary data doesn't match the shape"));
+                    }
+                    TensorData::from_le_bytes(element_type, bytes).ok_or_else(unsupported)?
+                }
+                (None, Some(data)) => {
+                    if element_type.size_in_bytes().is_none() {
+                        return Err(unsupported());
+                    }
+                    parse_json_data(element_type, data)
+                        .ok_or_else(|| malformed("data doesn't match the data type"))?
+                }
+                (None, None) => return Err(malformed("no data")),
+            };
+            let tensor = OwnedTensor::new(data, output.shape.clone())
+                .map_err(|e| malformed(&e.to_string()))?;
+            outputs.push((output.name, tensor));
+        }
+        let position = |name: &str| {
+            self.metadata
+                .outputs
+                .iter()
+                .position(|output| output.name == name)
+        };
+        outputs.sort_by_key(|(name, _)| position(name).unwrap_or(usize::MAX));
+        Ok(outputs)
+    }
+}
+
+impl<'a> Model<'a> for RemoteModel {
+    fn backend_name(&self) -> &'static str {
+        "remote"
+    }
+
+    fn signature(&self) -> Result<Signature, SessionInfoError> {
+        Ok(Signature {
+            inputs: self.metadata.inputs.iter().map(Into::into).collect(),
+            outputs: self.metadata.outputs.iter().map(Into::into).collect(),
+        })
+    }
+
+    fn metadata(&self) -> Result<Vec<(String, String)>, SessionInfoError> {
+        Ok(vec![
+            ("name".to_string(), self.metadata.name.clone()),
+            ("platform".to_string(), self.metadata.platform.clone()),
+            ("versions".to_string(), self.metadata.versions.join(",")),
+        ])
+    }
+
+    fn run(
+        &self,
+        inputs: &[(&str, SessionInput<'_>)],
+    ) -> Result<Vec<(String, OutputTensor<'a>)>, SessionRunError> {
+        let tensors = inputs
+            .iter()
+            .map(|(name, input)| input.as_tensor(name))
+            .collect::<Result<Vec<_>, _>>()?;
+        let inputs: Vec<_> = inputs
+            .iter()
+            .zip(&tensors)
+            .map(|((name, _), tensor)| (*name, tensor.as_ref()))
+            .collect();
+        let outputs = self
+            .infer(&inputs)
+            .map_err(|e| SessionRunError::Backend(Box::new(e)))?;
+        Ok(outputs
+            .into_iter()
+            .map(|(name, tensor)| (name, OutputTensor::Tensor(Cow::Owned(tensor))))
+            .collect())
+    }
+}
+
+/// Converts a ureq error, reading the message from the body of error responses. KServe servers
+/// respond with `{"error": "..."}`.
+fn error(url: &str, e: ureq::Error) -> RemoteError {
+    match e {
+        ureq::Error::Status(status, response) => {
+            let body = response.into_string().unwrap_or_default();
+            let message = serde_json::from_str::<Value>(&body)
+                .ok()
+                .and_then(|v| v.get("error")?.as_str().map(str::to_string))
+                .unwrap_or(body);
+            RemoteError::Status {
+                url: url.to_string(),
+                status,
+                message,
+            }
+        }
+        ureq::Error::Transport(e) => RemoteError::Transport {
+            url: url.to_string(),
+            message: e.to_string(),
+        },
+    }
+}
+
+fn datatype(element_type: ElementType) -> &'static str {
+    match element_type {
+        ElementType::Bool => "BOOL",
+        ElementType::Int8 => "INT8",
+        ElementType::Int16 => "INT16",
+        ElementType::Int32 => "INT32",
+        ElementType::Int64 => "INT64",
+        ElementType::UInt8 => "UINT8",
+        ElementType::UInt16 => "UINT16",
+        ElementType::UInt32 => "UINT32",
+        ElementType::UInt64 => "UINT64",
+        ElementType::Float16 => "FP16",
+        ElementType::BFloat16 => "BF16",
+        ElementType::Float => "FP32",
+        ElementType::Double => "FP64",
+        ElementType::String => "BYTES",
+        _ => "",
+    }
+}
+
+fn element_type(datatype: &str) -> ElementType {
+    match datatype {
+        "BOOL" => ElementType::Bool,
+        "INT8" => ElementType::Int8,
+        "INT16" => ElementType::Int16,
+        "INT32" => ElementType::Int32,
+        "INT64" => ElementType::Int64,
+        "UINT8" => ElementType::UInt8,
+        "UINT16" => ElementType::UInt16,
+        "UINT32" => ElementType::UInt32,
+        "UINT64" => ElementType::UInt64,
+        "FP16" => ElementType::Float16,
+        "BF16" => ElementType::BFloat16,
+        "FP32" => ElementType::Float,
+        "FP64" => ElementType::Double,
+        "BYTES" => ElementType::String,
+        _ => ElementType::Undefined,
+    }
+}
+
+/// Encodes tensor data as a flat JSON array. 16-bit floats are encoded as regular numbers.
+fn json_data(data: &TensorData) -> Value {
+    match data {
+        TensorData::Bool(v) => json!(v),
+        TensorData::Int8(v) => json!(v),
+        TensorData::Int16(v) => json!(v),
+        TensorData::Int32(v) => json!(v),
+        TensorData::Int64(v) => json!(v),
+        TensorData::UInt8(v) => json!(v),
+        TensorData::UInt16(v) => json!(v),
+        TensorData::UInt32(v) => json!(v),
+        TensorData::UInt64(v) => json!(v),
+        TensorData::Float16(v) => json!(v.iter().map(|x| x.to_f32()).collect::<Vec<_>>()),
+        TensorData::BFloat16(v) => json!(v.iter().map(|x| x.to_f32()).collect::<Vec<_>>()),
+        TensorData::Float32(v) => json!(v),
+        TensorData::Float64(v) => json!(v),
+    }
+}
+
+/// Decodes a JSON array of the given type. Nested arrays are flattened, since some servers
+/// return data shaped like the tensor.
+fn parse_json_data(element_type: ElementType, data: &Value) -> Option<TensorData> {
+    fn flatten<'v>(value: &'v Value, dest: &mut Vec<&'v Value>) {
+        match value {
+            Value::Array(values) => values.iter().for_each(|v| flatten(v, dest)),
+            value => dest.push(value),
+        }
+    }
+    fn convert<T: Element>(
+        values: &[&Value],
+        f: impl Fn(&Value) -> Option<T>,
+    ) -> Option<TensorData> {
+        values
+            .iter()
+            .map(|v| f(v))
+            .collect::<Option<Vec<_>>>()
+            .map(Into::into)
+    }
+    fn int<T: TryFrom<i64>>(v: &Value) -> Option<T> {
+        T::try_from(v.as_i64()?).ok()
+    }
+    fn uint<T: TryFrom<u64>>(v: &Value) -> Option<T> {
+        T::try_from(v.as_u64()?).ok()
+    }
+
+    let mut values = Vec::new();
+    flatten(data, &mut values);
+    let values = &values;
+    match element_type {
+        ElementType::Bool => convert(values, Value::as_bool),
+        ElementType::Int8 => convert(values, int::<i8>),
+        ElementType::Int16 => convert(values, int::<i16>),
+        ElementType::Int32 => convert(values, int::<i32>),
+        ElementType::Int64 => convert(values, Value::as_i64),
+        ElementType::UInt8 => convert(values, uint::<u8>),
+        ElementType::UInt16 => convert(values, uint::<u16>),
+        ElementType::UInt32 => convert(values, uint::<u32>),
+        ElementType::UInt64 => convert(values, Value::as_u64),
+        ElementType::Float16 => convert(values, |v| v.as_f64().map(f16::from_f64)),
+        ElementType::BFloat16 => convert(values, |v| v.as_f64().map(bf16::from_f64)),
+        ElementType::Float => convert(values, |v| v.as_f64().map(|v| v as f32)),
+        ElementType::Double => convert(values, Value::as_f64),
+        _ => None,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{Environment, Format, Session};
+    use std::convert::TryInto;
+
+    type Response = (u16, Option<usize>, Vec<u8>);
+
+    /// Serves a model named "double", which doubles its FP32 input "x" and also outputs its
+    /// element count as "n". Outputs are returned in the opposite of the declared order.
+    fn serve() -> String {
+        let server = tiny_http::Server::http("127.0.0.1:0").unwrap();
+        let url = format!("http://{}", server.server_addr().to_ip().unwrap());
+        std::thread::spawn(move || {
+            for mut request in server.incoming_requests() {
+                let (status, header_length, body) = handle(&mut request);
+                let mut response = tiny_http::Response::from_data(body).with_status_code(status);
+                if let Some(n) = header_length {
+                    let header = tiny_http::Header::from_bytes(HEADER_LENGTH, n.to_string());
+                    response.add_header(header.unwrap());
+                }
+                request.respond(response).unwrap();
+            }
+        });
+        url
+    }
+
+    fn handle(request: &mut tiny_http::Request) -> Response {
+        let error = |status, message| {
+            let body = json!({ "error": message }).to_string();
+            (status, None, body.into_bytes())
+        };
+        match (request.method(), request.url()) {
+            (tiny_http::Method::Get, "/v2/models/double/ready") => (200, None, Vec::new()),
+            (tiny_http::Method::Get, "/v2/models/double") => {
+                let body = json!({
+                    "name": "double",
+                    "versions": ["1"],
+                    "platform": "coreml",
+                    "inputs": [{"name": "x", "datatype": "FP32", "shape": [-1, 2]}],
+                    "outputs": [
+                        {"name": "y", "datatype": "FP32", "shape": [-1, 2]},
+                        {"name": "n", "datatype": "INT64", "shape": [1]}
+                    ]
+                });
+                (200, None, body.to_string().into_bytes())
+            }
+            (tiny_http::Method::Post, "/v2/models/double/infer") => {
+                infer(request).unwrap_or_else(|message| error(400, message))
+            }
+            _ => error(404, "no such model"),
+        }
+    }
+
+    fn infer(request: &mut tiny_http::Request) -> Result<Response, &'static str> {
+        let header_length = request
+            .headers()
+            .iter()
+            .find(|h| h.field.equiv(HEADER_LENGTH))
+            .map(|h| h.value.as_str().parse::<usize>().unwrap());
+        let mut body = Vec::new();
+        request.as_reader().read_to_end(&mut body).unwrap();
+        let (header, binary) = body.split_at(header_length.unwrap_or(body.len()));
+        let request: Value = serde_json::from_slice(header).unwrap();
+        let input = &request["inputs"][0];
+        if input["name"] != "x" || input["datatype"] != "FP32" {
+            return Err("expected an FP32 input named x");
+        }
+        let x: Vec<f32> = match header_length {
+            Some(_) => binary
+                .chunks_exact(4)
+                .map(|b| f32::from_le_bytes(b.try_into().unwrap()))
+                .collect(),
+            None => input["data"]
+                .as_array()
+                .unwrap()
+                .iter()
+                .map(|v| v.as_f64().unwrap() as f32)
+                .collect(),
+        };
+        let y: Vec<f32> = x.iter().map(|v| v * 2.).collect();
+        let n = json!({"name": "n", "datatype": "INT64", "shape": [1], "data": [x.len()]});
+
+        if request["outputs"][0]["parameters"]["binary_data"] == true {
+            let data: Vec<u8> = y.iter().flat_map(|v| v.to_le_bytes()).collect();
+            let y = json!({
+                "name": "y",
+                "datatype": "FP32",
+                "shape": input["shape"],
+                "parameters": {"binary_data_size": data.len()}
+            });
+            let mut body = json!({ "outputs": [n, y] }).to_string().into_bytes();
+            let header_length = body.len();
+            body.extend(data);
+            Ok((200, Some(header_length), body))
+        } else {
+            // Like some servers, this responds with data shaped like the tensor.
+            let rows: Vec<_> = y.chunks(2).collect();
+            let y = json!({"name": "y", "datatype": "FP32", "shape": input["shape"], "data": rows});
+            let body = json!({ "outputs": [n, y] }).to_string();
+            Ok((200, None, body.into_bytes()))
+        }
+    }
+
+    fn check_outputs(sess: &Session) {
+        let x = OwnedTensor::new(vec![1f32, 2., 3., 4.], vec![2, 2]).unwrap();
+        let outputs = sess.run(vec![("x", &x)]).unwrap();
+        assert_eq!(outputs.names().collect::<Vec<_>>(), vec!["y", "n"]);
+        assert_eq!(outputs[0].shape(), &[2, 2]);
+        assert_eq!(outputs[0].as_slice(), Some(&[2f32, 4., 6., 8.][..]));
+        assert_eq!(
+            outputs.get("n").unwrap().to_owned_tensor(),
+            Some(OwnedTensor::new(vec![4i64], vec![1]).unwrap())
+        );
+    }
+
+    #[test]
+    fn test_remote() {
+        let url = serve();
+        let mut env = Environment::new().unwrap();
+        env.register_backend(Format::CoreML, RemoteBackend::new(&url));
+        let sess = env
+            .new_session_with_format("models/double.mlmodel", Format::CoreML)
+            .unwrap();
+        assert_eq!(sess.backend_name(), "remote");
+        assert_eq!(
+            sess.signature().unwrap().inputs,
+            vec![TensorSignature {
+                name: "x".to_string(),
+                element_type: ElementType::Float,
+                dimensions: Some(vec![Dimension::Unknown, Dimension::Fixed(2)]),
+            }]
+        );
+        assert!(sess
+            .metadata()
+            .unwrap()
+            .contains(&("platform".to_string(), "coreml".to_string())));
+        check_outputs(&sess);
+
+        let model = RemoteBackend::new(&url)
+            .binary_data(false)
+            .connect("double")
+            .unwrap();
+        check_outputs(&Session::new(Box::new(model), &SessionOptions::default()));
+    }
+
+    #[test]
+    fn test_remote_errors() {
+        let url = serve();
+        let backend = RemoteBackend::new(&url);
+        assert!(matches!(
+            backend.connect("missing"),
+            Err(RemoteError::Status { status: 404, message, .. }) if message == "no such model"
+        ));
+
+        let model = backend.connect("double").unwrap();
+        let x = OwnedTensor::new(vec![1i32], vec![1]).unwrap();
+        assert!(matches!(
+            model.infer(&[("x", &x)]),
+            Err(RemoteError::Status { status: 400, .. })
+        ));
+
+        assert!(matches!(
+            RemoteBackend::new("http://127.0.0.1:1").connect("double"),
+            Err(RemoteError::Transport { .. })
+        ));
+    }
+}
@@ -0,0 +1,377 @@
+//! Reading and writing safetensors files: an 8-byte header length, a JSON header describing each
+//! tensor, and then the tensors' little-endian data.
+
+use super::{onnx::proto::ElementType, Outputs, OwnedTensor, TensorData};
+use serde::{Deserialize, Serialize};
+use std::{
+    collections::BTreeMap,
+    fs::File,
+    io::{self, BufWriter, Write},
+    path::Path,
+};
+
+#[derive(thiserror::Error, Debug)]
+pub enum SafetensorsError {
+    #[error(transparent)]
+    Io(#[from] io::Error),
+    #[error("malformed safetensors header: {0}")]
+    MalformedHeader(#[from] serde_json::Error),
+    #[error("the file is too short to contain a header")]
+    Truncated,
+    #[error("the header is larger than the file")]
+    HeaderTooLarge,
+    #[error("unsupported dtype {0}")]
+    UnsupportedDType(String),
+    #[error("{0:?} tensors can't be written to safetensors files")]
+    UnsupportedElementType(ElementType),
+    #[error(
+        "tensor {0} has data offsets that don't match its shape, are out of bounds, or overlap or \
+         leave a gap with another tensor's"
+    )]
+    InvalidOffsets(String),
+    #[error("tensor {0} appears more than once")]
+    DuplicateName(String),
+}
+
+#[derive(Deserialize, Serialize)]
+struct TensorInfo {
+    dtype: String,
+    shape: Vec<usize>,
+    data_offsets: (usize, usize),
+}
+
+fn element_type(dtype: &str) -> Option<ElementType> {
+    Some(match dtype {
+        "BOOL" => ElementType::Bool,
+        "I8" => ElementType::Int8,
+        "I16" => ElementType::Int16,
+        "I32" => ElementType::Int32,
+        "I64" => ElementType::Int64,
+        "U8" => ElementType::UInt8,
+        "U16" => ElementType::UInt16,
+        "U32" => ElementType::UInt32,
+        "U64" => ElementType::UInt64,
+        "F16" => ElementType::Float16,
+        "BF16" => ElementType::BFloat16,
+        "F32" => ElementType::Float,
+        "F64" => ElementType::Double,
+        _ => return None,
+    })
+}
+
+fn dtype(element_type: ElementType) -> Option<&'static str> {
+    Some(match element_type {
+        ElementType::Bool => "BOOL",
+        ElementType::Int8 => "I8",
+        ElementType::Int16 => "I16",
+        ElementType::Int32 => "I32",
+        ElementType::Int64 => "I64",
+        ElementType::UInt8 => "U8",
+        ElementType::UInt16 => "U16",
+        ElementType::UInt32 => "U32",
+        ElementType::UInt64 => "U64",
+        ElementType::Float16 => "F16",
+        ElementType::BFloat16 => "BF16",
+        ElementType::Float => "F32",
+        ElementType::Double => "F64",
+        _ => return None,
+    })
+}
+
+/// The contents of a safetensors file.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct Safetensors {
+    /// The tensors, in the order their data appears in the file.
+    pub tensors: Vec<(String, OwnedTensor)>,
+    /// The free-form string map stored under the header's `__metadata__` key.
+    pub metadata: BTreeMap<String, String>,
+}
+
+impl Safetensors {
+    pub fn decode(buf: &[u8]) -> Result<Self, SafetensorsError> {
+        if buf.len() < 8 {
+            return Err(SafetensorsError::Truncated);
+        }
+        let mut header_len = [0u8; 8];
+        header_len.copy_from_slice(&buf[..8]);
+        let header_len = u64::from_le_bytes(header_len);
+        if header_len > (buf.len() - 8) as u64 {
+            return Err(SafetensorsError::HeaderTooLarge);
+        }
+        let (header, data) = buf[8..].split_at(header_len as usize);
+
+        let mut header: BTreeMap<String, serde_json::Value> = serde_json::from_slice(header)?;
+        let metadata = match header.remove("__metadata__") {
+            Some(metadata) => serde_json::from_value(metadata)?,
+            None => BTreeMap::new(),
+        };
+        let mut tensors = header
+            .into_iter()
+            .map(|(name, info)| Ok((name, serde_json::from_value::<TensorInfo>(info)?)))
+            .collect::<Result<Vec<_>, SafetensorsError>>()?;
+        tensors.sort_by_key(|(_, info)| info.data_offsets);
+
+        // The tensors' data must cover the data section exactly, without gaps or overlaps.
+        let last = tensors.len().saturating_sub(1);
+        let mut offset = 0;
+        let tensors = tensors
+            .into_iter()
+            .enumerate()
+            .map(|(i, (name, info))| {
+                let element_type = element_type(&info.dtype)
+                    .ok_or_else(|| SafetensorsError::UnsupportedDType(info.dtype.clone()))?;
+                let (begin, end) = info.data_offsets;
+                let size = info
+                    .shape
+                    .iter()
+                    .try_fold(element_type.size_in_bytes().unwrap_or(0), |size, &n| {
+                        size.checked_mul(n)
+                    });
+                if begin != offset
+                    || begin > end
+                    || end > data.len()
+                    || Some(end - begin) != size
+                    || (i == last && end != data.len())
+                {
+                    return Err(SafetensorsError::InvalidOffsets(name));
+                }
+                offset = end;
+                let data = TensorData::from_le_bytes(element_type, &data[begin..end])
+                    .expect("safetensors dtypes should all be supported by TensorData");
+                let tensor = OwnedTensor::new(data, info.shape)
+                    .map_err(|_| SafetensorsError::InvalidOffsets(name.clone()))?;
+                Ok((name, tensor))
+            })
+            .collect::<Result<_, _>>()?;
+        Ok(Self { tensors, metadata })
+    }
+
+    pub fn from_path<P: AsRef<Path>>(path: P) -> Result<Self, SafetensorsError> {
+        Self::decode(&std::fs::read(path)?)
+    }
+
+    pub fn get(&self, name: &str) -> Option<&OwnedTensor> {
+        self.tensors
+            .iter()
+            .find(|(n, _)| n == name)
+            .map(|(_, tensor)| tensor)
+    }
+
+    /// Returns the tensors in a form that can be passed directly to `Session::run`.
+    pub fn inputs(&self) -> impl Iterator<Item = (&str, &OwnedTensor)> {
+        self.tensors
+            .iter()
+            .map(|(name, tensor)| (name.as_str(), tensor))
+    }
+}
+
+/// Writes named tensors in the order they're given, along with optional metadata.
+pub fn write<'a, W: Write, I: IntoIterator<Item = (&'a str, &'a OwnedTensor)>>(
+    mut w: W,
+    tensors: I,
+    metadata: &BTreeMap<String, String>,
+) -> Result<(), SafetensorsError> {
+    let mut header = serde_json::Map::new();
+    if !metadata.is_empty() {
+        header.insert("__metadata__".to_string(), serde_json::to_value(metadata)?);
+    }
+    let mut data = Vec::new();
+    for (name, tensor) in tensors {
+        let dtype = dtype(tensor.element_type())
+            .ok_or_else(|| SafetensorsError::UnsupportedElementType(tensor.element_type()))?;
+        let begin = data.len();
+        data.extend(tensor.data().to_le_bytes());
+        let info = TensorInfo {
+            dtype: dtype.to_string(),
+            shape: tensor.shape().to_vec(),
+            data_offsets: (begin, data.len()),
+        };
+        if header
+            .insert(name.to_string(), serde_json::to_value(info)?)
+            .is_some()
+        {
+            return Err(SafetensorsError::DuplicateName(name.to_string()));
+        }
+    }
+    let mut header = serde_json::to_vec(&header)?;
+    // The header is padded with spaces so that the data is 8-byte aligned.
+    header.resize(header.len() + (8 - header.len() % 8) % 8, b' ');
+
+    w.write_all(&(header.len() as u64).to_le_bytes())?;
+    w.write_all(&header)?;
+    w.write_all(&data)?;
+    w.flush()?;
+    Ok(())
+}
+
+pub fn write_path<'a, P: AsRef<Path>, I: IntoIterator<Item = (&'a str, &'a OwnedTensor)>>(
+    path: P,
+    tensors: I,
+    metadata: &BTreeMap<String, String>,
+) -> Result<(), SafetensorsError> {
+    write(BufWriter::new(File::create(path)?), tensors, metadata)
+}
+
+/// Writes the outputs of `Session::run`, keyed by output name.
+pub fn write_outputs<W: Write>(w: W, outputs: &Outputs) -> Result<(), SafetensorsError> {
+    let tensors = outputs
+        .iter()
+        .map(|(name, output)| {
+            output
+                .to_owned_tensor()
+                .map(|tensor| (name, tensor))
+                .ok_or_else(|| SafetensorsError::UnsupportedElementType(output.element_type()))
+        })
+        .collect::<Result<Vec<_>, _>>()?;
+    write(
+        w,
+        tensors.iter().map(|(name, tensor)| (*name, tensor)),
+        &BTreeMap::new(),
+    )
+}
+
+pub fn write_outputs_path<P: AsRef<Path>>(
+    path: P,
+    outputs: &Outputs,
+) -> Result<(), SafetensorsError> {
+    write_outputs(BufWriter::new(File::create(path)?), outputs)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use half::{bf16, f16};
+    use std::convert::TryInto;
+
+    #[test]
+    fn test_round_trip() {
+        let tensors = vec![
+            (
+                "weight".to_string(),
+                OwnedTensor::new(vec![1.0f32, 2.0, 3.0, 4.0], vec![2, 2]).unwrap(),
+            ),
+            (
+                "half".to_string(),
+                OwnedTensor::new(vec![f16::from_f32(0.5), f16::from_f32(-1.0)], vec![2]).unwrap(),
+            ),
+            (
+                "brain".to_string(),
+                OwnedTensor::new(vec![bf16::from_f32(3.0)], vec![]).unwrap(),
+            ),
+            (
+                "mask".to_string(),
+                OwnedTensor::new(vec![true, false, true], vec![3, 1]).unwrap(),
+            ),
+        ];
+        let mut metadata = BTreeMap::new();
+        metadata.insert("format".to_string(), "pt".to_string());
+
+        let mut buf = Vec::new();
+        write(
+            &mut buf,
+            tensors.iter().map(|(name, tensor)| (name.as_str(), tensor)),
+            &metadata,
+        )
+        .unwrap();
+        let header_len = u64::from_le_bytes(buf[..8].try_into().unwrap()) as usize;
+        assert_eq!(header_len % 8, 0);
+
+        let decoded = Safetensors::decode(&buf).unwrap();
+        assert_eq!(decoded.tensors, tensors);
+        assert_eq!(decoded.metadata, metadata);
+        assert_eq!(decoded.get("brain"), Some(&tensors[2].1));
+        assert!(decoded.get("missing").is_none());
+    }
+
+    #[test]
+    fn test_decode() {
+        // from safetensors.numpy import save
+        // save({"b": np.array([1, 2], dtype=np.int16), "a": np.array([0.5], dtype=np.float32)})
+        let header = br#"{"a":{"dtype":"F32","shape":[1],"data_offsets":[4,8]},"b":{"dtype":"I16","shape":[2],"data_offsets":[0,4]}}"#;
+        let mut buf = (header.len() as u64).to_le_bytes().to_vec();
+        buf.extend_from_slice(header);
+        buf.extend_from_slice(&[1, 0, 2, 0, 0, 0, 0, 0x3f]);
+        let decoded = Safetensors::decode(&buf).unwrap();
+        assert_eq!(
+            decoded.tensors,
+            vec![
+                (
+                    "b".to_string(),
+                    OwnedTensor::new(vec![1i16, 2], vec![2]).unwrap()
+                ),
+                (
+                    "a".to_string(),
+                    OwnedTensor::new(vec![0.5f32], vec![1]).unwrap()
+                ),
+            ]
+        );
+
+        let header = br#"{"a":{"dtype":"F32","shape":[2],"data_offsets":[0,4]}}"#;
+        let mut buf = (header.len() as u64).to_le_bytes().to_vec();
+        buf.extend_from_slice(header);
+        buf.extend_from_slice(&[0; 4]);
+        assert!(matches!(
+            Safetensors::decode(&buf),
+            Err(SafetensorsError::InvalidOffsets(name)) if name == "a"
+        ));
+
+        let header = br#"{"a":{"dtype":"F8_E4M3","shape":[1],"data_offsets":[0,1]}}"#;
+        let mut buf = (header.len() as u64).to_le_bytes().to_vec();
+        buf.extend_from_slice(header);
+        buf.push(0);
+        assert!(matches!(
+            Safetensors::decode(&buf),
+            Err(SafetensorsError::UnsupportedDType(dtype)) if dtype == "F8_E4M3"
+        ));
+
+        let invalid = |header: &[u8], data_len: usize| {
+            let mut buf = (header.len() as u64).to_le_bytes().to_vec();
+            buf.extend_from_slice(header);
+            buf.resize(buf.len() + data_len, 0);
+            match Safetensors::decode(&buf) {
+                Err(SafetensorsError::InvalidOffsets(name)) => name,
+                r => panic!("unexpected result: {:?}", r),
+            }
+        };
+        // Overlapping.
+        assert_eq!(
+            invalid(
+                br#"{"a":{"dtype":"U8","shape":[2],"data_offsets":[0,2]},"b":{"dtype":"U8","shape":[2],"data_offsets":[1,3]}}"#,
+                3
+            ),
+            "b"
+        );
+        // A gap between tensors.
+        assert_eq!(
+            invalid(
+                br#"{"a":{"dtype":"U8","shape":[1],"data_offsets":[0,1]},"b":{"dtype":"U8","shape":[1],"data_offsets":[2,3]}}"#,
+                3
+            ),
+            "b"
+        );
+        // Trailing data.
+        assert_eq!(
+            invalid(
+                br#"{"a":{"dtype":"U8","shape":[1],"data_offsets":[0,1]}}"#,
+                2
+            ),
+            "a"
+        );
+        // A shape whose size overflows.
+        let header = format!(
+            r#"{{"a":{{"dtype":"F64","shape":[{},{}],"data_offsets":[0,8]}}}}"#,
+            usize::MAX,
+            usize::MAX
+        );
+        assert_eq!(invalid(header.as_bytes(), 8), "a");
+
+        assert!(matches!(
+            Safetensors::decode(&[0; 4]),
+            Err(SafetensorsError::Truncated)
+        ));
+        assert!(matches!(
+            Safetensors::decode(&[16, 0, 0, 0, 0, 0, 0, 0, b'{', b'}']),
+            Err(SafetensorsError::HeaderTooLarge)
+        ));
+    }
+}
@@ -0,0 +1,271 @@
+use super::{
+    coreml::spec,
+    onnx::proto::{self, Dimension, ElementType},
+    Format,
+};
+use std::path::Path;
+
+/// Describes one of a model's inputs or outputs independently of its format.
+#[derive(Clone, Debug, PartialEq)]
+pub struct TensorSignature {
+    pub name: String,
+    /// The element type, or `ElementType::Undefined` if it isn't a tensor or isn't known.
+    pub element_type: ElementType,
+    /// The dimensions, or `None` if the rank isn't known.
+    pub dimensions: Option<Vec<Dimension>>,
+}
+
+impl From<&proto::TensorInfo> for TensorSignature {
+    fn from(info: &proto::TensorInfo) -> Self {
+        Self {
+            name: info.name.clone(),
+            element_type: info.element_type,
+            dimensions: Some(info.dimensions.clone()),
+        }
+    }
+}
+
+impl TensorSignature {
+    /// Returns the name without the ":0" suffix TensorFlow converters tend to add. This is what
+    /// names are matched by when comparing signatures.
+    pub fn normalized_name(&self) -> &str {
+        super::names::normalize(&self.name)
+    }
+
+    fn mismatches(&self, other: &Self) -> Vec<SignatureMismatch> {
+        let mut ret = Vec::new();
+        if self.element_type != ElementType::Undefined
+            && other.element_type != ElementType::Undefined
+            && self.element_type != other.element_type
+        {
+            ret.push(SignatureMismatch::ElementType {
+                name: self.normalized_name().to_string(),
+                left: self.element_type,
+                right: other.element_type,
+            });
+        }
+        if let (Some(a), Some(b)) = (&self.dimensions, &other.dimensions) {
+            let compatible = a.len() == b.len()
+                && a.iter().zip(b).all(|dims| match dims {
+                    (Dimension::Fixed(a), Dimension::Fixed(b)) => a == b,
+                    _ => true,
+                });
+            if !compatible {
+                ret.push(SignatureMismatch::Shape {
+                    name: self.normalized_name().to_string(),
+                    left: a.clone(),
+                    right: b.clone(),
+                });
+            }
+        }
+        ret
+    }
+}
+
+#[derive(Clone, Debug, PartialEq, thiserror::Error)]
+pub enum SignatureMismatch {
+    #[error("input {0} only exists in the left signature")]
+    LeftOnlyInput(String),
+    #[error("input {0} only exists in the right signature")]
+    RightOnlyInput(String),
+    #[error("output {0} only exists in the left signature")]
+    LeftOnlyOutput(String),
+    #[error("output {0} only exists in the right signature")]
+    RightOnlyOutput(String),
+    #[error("{name} has element type {left:?} on the left, but {right:?} on the right")]
+    ElementType {
+        name: String,
+        left: ElementType,
+        right: ElementType,
+    },
+    #[error("{name} has shape {left:?} on the left, but {right:?} on the right")]
+    Shape {
+        name: String,
+        left: Vec<Dimension>,
+        right: Vec<Dimension>,
+    },
+}
+
+#[derive(thiserror::Error, Debug)]
+pub enum ReadSignatureError {
+    #[error("unable to read model: {0}")]
+    Io(#[from] std::io::Error),
+    #[error(transparent)]
+    ONNX(#[from] proto::ReadModelError),
+    #[error(transparent)]
+    CoreML(#[from] spec::ReadModelError),
+    #[error("unrecognized model format")]
+    UnrecognizedFormat,
+    #[error("signatures can't be read from {0} models")]
+    UnsupportedFormat(Format),
+}
+
+/// The inputs and outputs of a model, read without loading it into a runtime.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct Signature {
+    pub inputs: Vec<TensorSignature>,
+    pub outputs: Vec<TensorSignature>,
+}
+
+impl Signature {
+    /// Reads the signature of an ONNX model, a .mlmodel file, or a .mlpackage directory.
+    pub fn from_path<P: AsRef<Path>>(path: P) -> Result<Self, ReadSignatureError> {
+        let path = path.as_ref();
+        match Format::detect(path)? {
+            Some(Format::ONNX) => Ok(Self::from_onnx(&proto::Model::from_path(path)?)),
+            Some(Format::CoreML) if !path.join("coremldata.bin").exists() => {
+                Ok(Self::from_coreml(&spec::Model::from_path(path)?))
+            }
+            Some(format) => Err(ReadSignatureError::UnsupportedFormat(format)),
+            None => Err(ReadSignatureError::UnrecognizedFormat),
+        }
+    }
+
+    pub fn from_onnx(model: &proto::Model) -> Self {
+        Self {
+            inputs: model.graph.runtime_inputs().map(Into::into).collect(),
+            outputs: model.graph.outputs.iter().map(Into::into).collect(),
+        }
+    }
+
+    pub fn from_coreml(model: &spec::Model) -> Self {
+        let convert = |feature: &spec::FeatureDescription| match &feature.feature_type {
+            spec::FeatureType::MultiArray {
+                data_type,
+                shape,
+                shape_range,
+            } => TensorSignature {
+                name: feature.name.clone(),
+                element_type: match data_type {
+                    spec::ArrayDataType::Float16 => ElementType::Float16,
+                    spec::ArrayDataType::Float32 => ElementType::Float,
+                    spec::ArrayDataType::Double => ElementType::Double,
+                    spec::ArrayDataType::Int32 => ElementType::Int32,
+                    spec::ArrayDataType::Invalid => ElementType::Undefined,
+                },
+                dimensions: match shape_range {
+                    Some(ranges) => Some(
+                        ranges
+                            .iter()
+                            .map(|&(lower, upper)| {
+                                if lower == upper && lower >= 0 {
+                                    Dimension::Fixed(lower as _)
+                                } else {
+                                    Dimension::Unknown
+                                }
+                            })
+                            .collect(),
+                    ),
+                    None if shape.is_empty() => None,
+                    None => Some(
+                        shape
+                            .iter()
+                            .map(|&n| Dimension::Fixed(n.max(0) as _))
+                            .collect(),
+                    ),
+                },
+            },
+            _ => TensorSignature {
+                name: feature.name.clone(),
+                element_type: ElementType::Undefined,
+                dimensions: None,
+            },
+        };
+        Self {
+            inputs: model.inputs.iter().map(convert).collect(),
+            outputs: model.outputs.iter().map(convert).collect(),
+        }
+    }
+
+    /// Checks that the two signatures have the same inputs and outputs with compatible types and
+    /// shapes. Types and dimensions that either side doesn't specify are considered compatible.
+    pub fn check_compatible(&self, other: &Self) -> Result<(), Vec<SignatureMismatch>> {
+        let mut mismatches = Vec::new();
+        compare(
+            &self.inputs,
+            &other.inputs,
+            SignatureMismatch::LeftOnlyInput,
+            SignatureMismatch::RightOnlyInput,
+            &mut mismatches,
+        );
+        compare(
+            &self.outputs,
+            &other.outputs,
+            SignatureMismatch::LeftOnlyOutput,
+            SignatureMismatch::RightOnlyOutput,
+            &mut mismatches,
+        );
+        if mismatches.is_empty() {
+            Ok(())
+        } else {
+            Err(mismatches)
+        }
+    }
+}
+
+fn compare(
+    left: &[TensorSignature],
+    right: &[TensorSignature],
+    left_only: fn(String) -> SignatureMismatch,
+    right_only: fn(String) -> SignatureMismatch,
+    mismatches: &mut Vec<SignatureMismatch>,
+) {
+    for l in left {
+        match right
+            .iter()
+            .find(|r| r.normalized_name() == l.normalized_name())
+        {
+            Some(r) => mismatches.extend(l.mismatches(r)),
+            None => mismatches.push(left_only(l.normalized_name().to_string())),
+        }
+    }
+    for r in right {
+        if !left
+            .iter()
+            .any(|l| l.normalized_name() == r.normalized_name())
+        {
+            mismatches.push(right_only(r.normalized_name().to_string()));
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_check_compatible() {
+        let onnx = Signature::from_path("src/onnx/testdata/upsample.onnx").unwrap();
+        let coreml = Signature::from_path("src/coreml/testdata/upsample.mlmodel").unwrap();
+        assert_eq!(
+            coreml.inputs,
+            vec![TensorSignature {
+                name: "up_sampling2d_input".to_string(),
+                element_type: ElementType::Float,
+                dimensions: Some(vec![
+                    Dimension::Unknown,
+                    Dimension::Fixed(1),
+                    Dimension::Fixed(2),
+                    Dimension::Fixed(3),
+                ]),
+            }]
+        );
+        onnx.check_compatible(&coreml).unwrap();
+
+        let mut other = coreml.clone();
+        other.inputs[0].dimensions = Some(vec![Dimension::Fixed(1), Dimension::Fixed(4)]);
+        other.outputs[0].name = "Output".to_string();
+        assert_eq!(
+            onnx.check_compatible(&other),
+            Err(vec![
+                SignatureMismatch::Shape {
+                    name: "up_sampling2d_input".to_string(),
+                    left: onnx.inputs[0].dimensions.clone().unwrap(),
+                    right: vec![Dimension::Fixed(1), Dimension::Fixed(4)],
+                },
+                SignatureMismatch::LeftOnlyOutput("Identity".to_string()),
+                SignatureMismatch::RightOnlyOutput("Output".to_string()),
+            ])
+        );
+    }
+}
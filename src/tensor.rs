@@ -0,0 +1,500 @@
+//! Owned tensors, for when the data has to outlive a borrow, e.g. after reading it from a file.
+
+use super::{onnx::proto::ElementType, InputTensor, OutputTensor, SessionRunError};
+use half::{bf16, f16};
+use std::{borrow::Cow, convert::TryInto};
+
+/// A tensor's elements, stored in row-major order.
+#[derive(Clone, Debug, PartialEq)]
+pub enum TensorData {
+    Bool(Vec<bool>),
+    Int8(Vec<i8>),
+    Int16(Vec<i16>),
+    Int32(Vec<i32>),
+    Int64(Vec<i64>),
+    UInt8(Vec<u8>),
+    UInt16(Vec<u16>),
+    UInt32(Vec<u32>),
+    UInt64(Vec<u64>),
+    Float16(Vec<f16>),
+    BFloat16(Vec<bf16>),
+    Float32(Vec<f32>),
+    Float64(Vec<f64>),
+}
+
+/// Applies an expression to the vector inside any `TensorData` variant.
+macro_rules! map_data {
+    ($data:expr, $v:ident => $e:expr) => {
+        match $data {
+            TensorData::Bool($v) => $e,
+            TensorData::Int8($v) => $e,
+            TensorData::Int16($v) => $e,
+            TensorData::Int32($v) => $e,
+            TensorData::Int64($v) => $e,
+            TensorData::UInt8($v) => $e,
+            TensorData::UInt16($v) => $e,
+            TensorData::UInt32($v) => $e,
+            TensorData::UInt64($v) => $e,
+            TensorData::Float16($v) => $e,
+            TensorData::BFloat16($v) => $e,
+            TensorData::Float32($v) => $e,
+            TensorData::Float64($v) => $e,
+        }
+    };
+}
+#[cfg(feature = "reference")]
+pub(crate) use map_data;
+
+/// A type that can be stored in an `OwnedTensor`.
+pub trait Element: Copy + Sized {
+    const ELEMENT_TYPE: ElementType;
+
+    fn into_data(v: Vec<Self>) -> TensorData;
+    fn slice(data: &TensorData) -> Option<&[Self]>;
+    fn from_le_bytes(b: &[u8]) -> Self;
+    fn extend_le_bytes(self, dest: &mut Vec<u8>);
+    fn to_f32(self) -> f32;
+
+    /// Converts the element to `f64`. This is lossless except for 64-bit integers.
+    fn to_f64(self) -> f64 {
+        self.to_f32() as _
+    }
+}
+
+macro_rules! impl_element {
+    ($($t:ty => $variant:ident, $element_type:ident;)*) => {
+        $(
+            impl Element for $t {
+                const ELEMENT_TYPE: ElementType = ElementType::$element_type;
+
+                fn into_data(v: Vec<Self>) -> TensorData {
+                    TensorData::$variant(v)
+                }
+
+                fn slice(data: &TensorData) -> Option<&[Self]> {
+                    match data {
+                        TensorData::$variant(v) => Some(v),
+                        _ => None,
+                    }
+                }
+
+                fn from_le_bytes(b: &[u8]) -> Self {
+                    <$t>::from_le_bytes(b.try_into().expect("wrong number of bytes for element"))
+                }
+
+                fn extend_le_bytes(self, dest: &mut Vec<u8>) {
+                    dest.extend_from_slice(&self.to_le_bytes());
+                }
+
+                fn to_f32(self) -> f32 {
+                    self as _
+                }
+
+                fn to_f64(self) -> f64 {
+                    self as _
+                }
+            }
+        )*
+    };
+}
+
+impl_element! {
+    i8 => Int8, Int8;
+    i16 => Int16, Int16;
+    i32 => Int32, Int32;
+    i64 => Int64, Int64;
+    u8 => UInt8, UInt8;
+    u16 => UInt16, UInt16;
+    u32 => UInt32, UInt32;
+    u64 => UInt64, UInt64;
+    f32 => Float32, Float;
+    f64 => Float64, Double;
+}
+
+impl Element for bool {
+    const ELEMENT_TYPE: ElementType = ElementType::Bool;
+
+    fn into_data(v: Vec<Self>) -> TensorData {
+        TensorData::Bool(v)
+    }
+
+    fn slice(data: &TensorData) -> Option<&[Self]> {
+        match data {
+            TensorData::Bool(v) => Some(v),
+            _ => None,
+        }
+    }
+
+    fn from_le_bytes(b: &[u8]) -> Self {
+        b[0] != 0
+    }
+
+    fn extend_le_bytes(self, dest: &mut Vec<u8>) {
+        dest.push(self as u8);
+    }
+
+    fn to_f32(self) -> f32 {
+        self as u8 as f32
+    }
+}
+
+macro_rules! impl_half_element {
+    ($($t:ty => $variant:ident;)*) => {
+        $(
+            impl Element for $t {
+                const ELEMENT_TYPE: ElementType = ElementType::$variant;
+
+                fn into_data(v: Vec<Self>) -> TensorData {
+                    TensorData::$variant(v)
+                }
+
+                fn slice(data: &TensorData) -> Option<&[Self]> {
+                    match data {
+                        TensorData::$variant(v) => Some(v),
+                        _ => None,
+                    }
+                }
+
+                fn from_le_bytes(b: &[u8]) -> Self {
+                    <$t>::from_le_bytes(b.try_into().expect("wrong number of bytes for element"))
+                }
+
+                fn extend_le_bytes(self, dest: &mut Vec<u8>) {
+                    dest.extend_from_slice(&self.to_le_bytes());
+                }
+
+                fn to_f32(self) -> f32 {
+                    <$t>::to_f32(self)
+                }
+
+                fn to_f64(self) -> f64 {
+                    <$t>::to_f64(self)
+                }
+            }
+        )*
+    };
+}
+
+impl_half_element! {
+    f16 => Float16;
+    bf16 => BFloat16;
+}
+
+fn decode<T: Element>(bytes: &[u8]) -> TensorData {
+    T::into_data(
+        bytes
+            .chunks_exact(std::mem::size_of::<T>())
+            .map(T::from_le_bytes)
+            .collect(),
+    )
+}
+
+impl TensorData {
+    /// Decodes little-endian elements of the given type. Returns `None` if the type isn't one
+    /// `TensorData` can hold. Trailing bytes that don't make up a whole element are ignored.
+    pub fn from_le_bytes(element_type: ElementType, bytes: &[u8]) -> Option<Self> {
+        Some(match element_type {
+            ElementType::Bool => decode::<bool>(bytes),
+            ElementType::Int8 => decode::<i8>(bytes),
+            ElementType::Int16 => decode::<i16>(bytes),
+            ElementType::Int32 => decode::<i32>(bytes),
+            ElementType::Int64 => decode::<i64>(bytes),
+            ElementType::UInt8 => decode::<u8>(bytes),
+            ElementType::UInt16 => decode::<u16>(bytes),
+            ElementType::UInt32 => decode::<u32>(bytes),
+            ElementType::UInt64 => decode::<u64>(bytes),
+            ElementType::Float16 => decode::<f16>(bytes),
+            ElementType::BFloat16 => decode::<bf16>(bytes),
+            ElementType::Float => decode::<f32>(bytes),
+            ElementType::Double => decode::<f64>(bytes),
+            _ => return None,
+        })
+    }
+
+    /// Encodes the elements as little-endian bytes.
+    pub fn to_le_bytes(&self) -> Vec<u8> {
+        let mut ret = Vec::new();
+        map_data!(self, v => {
+            ret.reserve(std::mem::size_of_val(&v[..]));
+            v.iter().for_each(|x| x.extend_le_bytes(&mut ret));
+        });
+        ret
+    }
+
+    pub fn element_type(&self) -> ElementType {
+        fn element_type<T: Element>(_: &[T]) -> ElementType {
+            T::ELEMENT_TYPE
+        }
+        map_data!(self, v => element_type(v))
+    }
+
+    pub fn len(&self) -> usize {
+        map_data!(self, v => v.len())
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+}
+
+impl<T: Element> From<Vec<T>> for TensorData {
+    fn from(v: Vec<T>) -> Self {
+        T::into_data(v)
+    }
+}
+
+#[derive(thiserror::Error, Debug)]
+#[error("{len} elements don't fit the shape {shape:?}")]
+pub struct ShapeMismatchError {
+    pub len: usize,
+    pub shape: Vec<usize>,
+}
+
+/// A tensor that owns its data.
+#[derive(Clone, Debug, PartialEq)]
+pub struct OwnedTensor {
+    data: TensorData,
+    shape: Vec<usize>,
+}
+
+impl OwnedTensor {
+    pub fn new<D: Into<TensorData>>(
+        data: D,
+        shape: Vec<usize>,
+    ) -> Result<Self, ShapeMismatchError> {
+        let data = data.into();
+        if data.len() != shape.iter().product::<usize>() {
+            return Err(ShapeMismatchError {
+                len: data.len(),
+                shape,
+            });
+        }
+        Ok(Self { data, shape })
+    }
+
+    pub fn data(&self) -> &TensorData {
+        &self.data
+    }
+
+    pub fn into_data(self) -> TensorData {
+        self.data
+    }
+
+    pub fn shape(&self) -> &[usize] {
+        &self.shape
+    }
+
+    pub fn element_type(&self) -> ElementType {
+        self.data.element_type()
+    }
+
+    pub fn as_slice<T: Element>(&self) -> Option<&[T]> {
+        T::slice(&self.data)
+    }
+
+    /// Borrows the tensor as an input. Returns `None` unless its elements are `f32`.
+    pub fn as_input(&self) -> Option<InputTensor<'_>> {
+        Some(InputTensor::new(self.as_slice()?, &self.shape))
+    }
+
+    /// Converts the elements to `f32`, e.g. for fixtures that were saved as `f64`. This may lose
+    /// precision.
+    pub fn to_f32(&self) -> Self {
+        let data = map_data!(&self.data, v => v.iter().map(|x| x.to_f32()).collect());
+        Self {
+            data: TensorData::Float32(data),
+            shape: self.shape.clone(),
+        }
+    }
+
+    #[cfg(feature = "onnx")]
+    pub(crate) fn to_onnx<'a>(
+        &'a self,
+        env: &'a crate::onnx::Environment,
+    ) -> Result<crate::onnx::Tensor<'a>, crate::onnx::Error> {
+        map_data!(&self.data, v => env.new_tensor(v, &self.shape))
+    }
+}
+
+/// Returns the number of elements a strided tensor's data must have, i.e. one more than the offset
+/// of its last element.
+pub(crate) fn strided_len(shape: &[usize], strides: &[usize]) -> usize {
+    if shape.contains(&0) {
+        0
+    } else {
+        1 + shape
+            .iter()
+            .zip(strides)
+            .map(|(&n, &stride)| (n - 1) * stride)
+            .sum::<usize>()
+    }
+}
+
+impl<'a> InputTensor<'a> {
+    /// Checks that the strides, if any, match the shape and don't reach past the end of the data.
+    pub fn has_valid_layout(&self) -> bool {
+        match self.strides {
+            Some(strides) => {
+                strides.len() == self.shape.len()
+                    && self.data.len() >= strided_len(self.shape, strides)
+            }
+            None => true,
+        }
+    }
+
+    /// Returns the data densely packed in row-major order, copying it only if it isn't already.
+    /// Fails with `SessionRunError::InvalidStrides`, naming the input, if the layout isn't valid.
+    pub fn packed_data(&self, name: &str) -> Result<Cow<'a, [f32]>, SessionRunError> {
+        let strides = match self.strides {
+            Some(strides) => strides,
+            None => return Ok(self.data.into()),
+        };
+        if !self.has_valid_layout() {
+            return Err(SessionRunError::InvalidStrides(name.to_string()));
+        }
+        let len = self.shape.iter().product::<usize>();
+        let mut packed_stride = 1;
+        let is_packed = self.shape.iter().zip(strides).rev().all(|(&n, &stride)| {
+            let ok = n <= 1 || stride == packed_stride;
+            packed_stride *= n;
+            ok
+        });
+        if is_packed {
+            return Ok(self.data[..len].into());
+        }
+
+        let mut ret = Vec::with_capacity(len);
+        let (inner_len, inner_stride) = match (self.shape.last(), strides.last()) {
+            (Some(&n), Some(&stride)) => (n, stride),
+            _ => (1, 1),
+        };
+        let outer_shape = &self.shape[..self.shape.len().saturating_sub(1)];
+        let mut index = vec![0; outer_shape.len()];
+        for _ in 0..outer_shape.iter().product::<usize>() {
+            let offset = index
+                .iter()
+                .zip(strides)
+                .map(|(i, stride)| i * stride)
+                .sum::<usize>();
+            // Rows whose elements are adjacent are copied in one go.
+            if inner_stride == 1 {
+                ret.extend_from_slice(&self.data[offset..offset + inner_len]);
+            } else {
+                ret.extend((0..inner_len).map(|i| self.data[offset + i * inner_stride]));
+            }
+            for (i, &n) in index.iter_mut().zip(outer_shape).rev() {
+                *i += 1;
+                if *i < n {
+                    break;
+                }
+                *i = 0;
+            }
+        }
+        Ok(ret.into())
+    }
+}
+
+/// Copies an ORT tensor's data. Returns `None` if its element type isn't one `TensorData` can hold.
+#[cfg(feature = "onnx")]
+pub(crate) fn onnx_tensor_data(t: &crate::onnx::Tensor) -> Option<TensorData> {
+    use crate::onnx::DataType;
+    fn copy<T: DataType + Element>(t: &crate::onnx::Tensor) -> Option<TensorData> {
+        t.as_slice::<T>().map(|s| s.to_vec().into())
+    }
+    match t.element_type() {
+        ElementType::Bool => copy::<bool>(t),
+        ElementType::Int8 => copy::<i8>(t),
+        ElementType::Int16 => copy::<i16>(t),
+        ElementType::Int32 => copy::<i32>(t),
+        ElementType::Int64 => copy::<i64>(t),
+        ElementType::UInt8 => copy::<u8>(t),
+        ElementType::UInt16 => copy::<u16>(t),
+        ElementType::UInt32 => copy::<u32>(t),
+        ElementType::UInt64 => copy::<u64>(t),
+        ElementType::Float16 => copy::<f16>(t),
+        ElementType::BFloat16 => copy::<bf16>(t),
+        ElementType::Float => copy::<f32>(t),
+        ElementType::Double => copy::<f64>(t),
+        _ => None,
+    }
+}
+
+impl<'a> OutputTensor<'a> {
+    /// Copies the output into an `OwnedTensor`. Returns `None` if its element type isn't one
+    /// `TensorData` can hold.
+    pub fn to_owned_tensor(&self) -> Option<OwnedTensor> {
+        let data = match self {
+            #[cfg(feature = "onnx")]
+            Self::ONNX(t) => onnx_tensor_data(t)?,
+            #[cfg(all(feature = "coreml", target_os = "macos"))]
+            Self::CoreML(t) => TensorData::Float32(t.as_slice().to_vec()),
+            Self::Tensor(t) => t.data().clone(),
+        };
+        Some(OwnedTensor {
+            data,
+            shape: self.shape().to_vec(),
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_owned_tensor() {
+        let t = OwnedTensor::new(vec![1i64, 2, 3, 4, 5, 6], vec![2, 3]).unwrap();
+        assert_eq!(t.element_type(), ElementType::Int64);
+        assert_eq!(t.as_slice::<i64>(), Some(&[1, 2, 3, 4, 5, 6][..]));
+        assert!(t.as_slice::<f32>().is_none());
+        assert!(t.as_input().is_none());
+
+        let t = t.to_f32();
+        let input = t.as_input().unwrap();
+        assert_eq!(input.data, &[1.0, 2.0, 3.0, 4.0, 5.0, 6.0]);
+        assert_eq!(input.shape, &[2, 3]);
+
+        assert!(OwnedTensor::new(vec![1.0f32; 5], vec![2, 3]).is_err());
+    }
+
+    #[test]
+    fn test_packed_data() {
+        // A 2x2 crop starting at (1, 1) of a 3x4 buffer.
+        let buf = (0..12).map(|n| n as f32).collect::<Vec<_>>();
+        let input = InputTensor::new(&buf[5..], &[2, 2]).with_strides(&[4, 1]);
+        assert_eq!(&*input.packed_data("x").unwrap(), &[5.0, 6.0, 9.0, 10.0]);
+
+        // Every other column.
+        let input = InputTensor::new(&buf, &[3, 2]).with_strides(&[4, 2]);
+        assert_eq!(
+            &*input.packed_data("x").unwrap(),
+            &[0.0, 2.0, 4.0, 6.0, 8.0, 10.0]
+        );
+
+        // Already packed, so nothing needs to be copied.
+        let input = InputTensor::new(&buf, &[1, 3, 4]).with_strides(&[100, 4, 1]);
+        assert!(matches!(input.packed_data("x").unwrap(), Cow::Borrowed(_)));
+
+        let input = InputTensor::new(&buf, &[3, 4]).with_strides(&[5, 1]);
+        assert!(matches!(
+            input.packed_data("x"),
+            Err(SessionRunError::InvalidStrides(name)) if name == "x"
+        ));
+    }
+
+    #[test]
+    fn test_le_bytes() {
+        let data = TensorData::from(vec![f16::from_f32(1.0), f16::from_f32(-2.0)]);
+        let bytes = data.to_le_bytes();
+        assert_eq!(bytes, vec![0x00, 0x3c, 0x00, 0xc0]);
+        assert_eq!(
+            TensorData::from_le_bytes(ElementType::Float16, &bytes),
+            Some(data)
+        );
+        assert_eq!(
+            TensorData::from_le_bytes(ElementType::Int16, &bytes),
+            Some(TensorData::Int16(vec![0x3c00, 0xc000u16 as i16]))
+        );
+        assert_eq!(TensorData::from_le_bytes(ElementType::String, &bytes), None);
+    }
+}